@@ -0,0 +1,500 @@
+// color-cycle - render color cycle images
+// Copyright (C) 2025  Mathias Panzenböck
+// 
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+// 
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+// 
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Readers for Atari ST NEOchrome (.NEO) and Degas/Degas Elite (.PI1-.PI3,
+// .PC1-.PC3) images, including their color animation ranges.
+
+use std::io::Read;
+
+use crate::{color::Rgb, error::Error, image::{CycleImage, IndexedImage}, palette::{Cycle, CycleMode, Palette}};
+
+/// The ST redraws the screen 60 times a second in NTSC color modes. Degas
+/// Elite gives its delays in 1/60 seconds, NEOchrome in vertical blanks.
+pub const ST_VBLANK_HZ: u64 = 60;
+
+/// Size of the ST screen memory.
+pub const ST_SCREEN_SIZE: usize = 32000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Resolution {
+    /// 320 x 200, 16 colors
+    Low,
+    /// 640 x 200, 4 colors
+    Medium,
+    /// 640 x 400, monochrome
+    High,
+}
+
+impl Resolution {
+    pub fn from_value(value: u16) -> Result<Self, Error> {
+        match value {
+            0 => Ok(Resolution::Low),
+            1 => Ok(Resolution::Medium),
+            2 => Ok(Resolution::High),
+            _ => Err(Error::new(format!("illegal Atari ST resolution: {value}"))),
+        }
+    }
+
+    #[inline]
+    pub fn size(self) -> (u32, u32) {
+        match self {
+            Resolution::Low    => (320, 200),
+            Resolution::Medium => (640, 200),
+            Resolution::High   => (640, 400),
+        }
+    }
+
+    #[inline]
+    pub fn num_planes(self) -> usize {
+        match self {
+            Resolution::Low    => 4,
+            Resolution::Medium => 2,
+            Resolution::High   => 1,
+        }
+    }
+
+    /// Pixel aspect ratio as (x, y) on a 4:3 screen.
+    #[inline]
+    pub fn pixel_aspect(self) -> (u8, u8) {
+        match self {
+            Resolution::Medium => (1, 2),
+            _ => (1, 1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StFileType {
+    NEOchrome,
+    Degas,
+    /// Degas Elite PackBits compressed.
+    DegasCompressed,
+}
+
+impl StFileType {
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        let extension = extension.to_ascii_lowercase();
+        match extension.as_str() {
+            "neo" => Some(StFileType::NEOchrome),
+            "pi1" | "pi2" | "pi3" => Some(StFileType::Degas),
+            "pc1" | "pc2" | "pc3" => Some(StFileType::DegasCompressed),
+            _ => None,
+        }
+    }
+}
+
+/// Read a NEOchrome or Degas image. Returns the image and the pixel aspect
+/// ratio.
+pub fn read_st_image(reader: &mut impl Read, file_type: StFileType) -> Result<(CycleImage, (u8, u8)), Error> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+
+    match file_type {
+        StFileType::NEOchrome => read_neochrome(&data),
+        StFileType::Degas | StFileType::DegasCompressed => read_degas(&data),
+    }
+}
+
+/// NEOchrome: 128 byte header followed by the uncompressed screen.
+fn read_neochrome(data: &[u8]) -> Result<(CycleImage, (u8, u8)), Error> {
+    const HEADER_SIZE: usize = 128;
+
+    if data.len() < HEADER_SIZE + ST_SCREEN_SIZE {
+        return Err(Error::new(format!("NEOchrome file too short: {} < {}", data.len(), HEADER_SIZE + ST_SCREEN_SIZE)));
+    }
+
+    let resolution = Resolution::from_value(read_u16be(data, 2))?;
+    let palette = read_st_palette(&data[4..36]);
+    let image = decode_screen(&data[HEADER_SIZE..HEADER_SIZE + ST_SCREEN_SIZE], resolution, palette);
+
+    // Bit 15 of the limits marks them as valid, bit 15 of the speed turns
+    // the animation on. The low byte of the limits has the lower color in
+    // the upper and the upper color in the lower nibble. The low byte of the
+    // speed is the number of vertical blanks per step, negative to move the
+    // colors towards the lower end.
+    let limits = read_u16be(data, 48);
+    let speed = read_u16be(data, 50);
+    let mut cycles = Vec::new();
+    if limits & 0x8000 != 0 && speed & 0x8000 != 0 {
+        let low = ((limits >> 4) & 0xF) as u8;
+        let high = (limits & 0xF) as u8;
+        let vblanks = speed as u8 as i8;
+        if low < high && vblanks != 0 {
+            let mode = if vblanks < 0 { CycleMode::Reverse } else { CycleMode::Forward };
+            let delay_usec = vblanks.unsigned_abs() as u64 * 1_000_000 / ST_VBLANK_HZ;
+            cycles.push(Cycle::with_delay(low, high, delay_usec, mode));
+        }
+    }
+
+    Ok((CycleImage::new(None, image, cycles.into()), resolution.pixel_aspect()))
+}
+
+/// Degas: resolution, palette and screen, optionally followed by the Degas
+/// Elite color animation. Compressed files have bit 15 of the resolution
+/// set and each scan line PackBits compressed, one bit plane after another.
+fn read_degas(data: &[u8]) -> Result<(CycleImage, (u8, u8)), Error> {
+    const HEADER_SIZE: usize = 34;
+    const ANIMATION_SIZE: usize = 32;
+
+    if data.len() < HEADER_SIZE {
+        return Err(Error::new(format!("Degas file too short: {} < {}", data.len(), HEADER_SIZE)));
+    }
+
+    let resolution_value = read_u16be(data, 0);
+    let compressed = resolution_value & 0x8000 != 0;
+    let resolution = Resolution::from_value(resolution_value & 0x7FFF)?;
+    let palette = read_st_palette(&data[2..HEADER_SIZE]);
+
+    let (screen, animation) = if compressed {
+        let (screen, len) = unpack_screen(&data[HEADER_SIZE..], resolution)?;
+        (screen, data.get(HEADER_SIZE + len..HEADER_SIZE + len + ANIMATION_SIZE))
+    } else {
+        let Some(screen) = data.get(HEADER_SIZE..HEADER_SIZE + ST_SCREEN_SIZE) else {
+            return Err(Error::new(format!("Degas file too short: {} < {}", data.len(), HEADER_SIZE + ST_SCREEN_SIZE)));
+        };
+        let offset = HEADER_SIZE + ST_SCREEN_SIZE;
+        (screen.to_vec(), data.get(offset..offset + ANIMATION_SIZE))
+    };
+
+    let image = decode_screen(&screen, resolution, palette);
+
+    // Degas Elite: four channels given as tables of left (lower) colors,
+    // right (upper) colors, directions (0 = left, 1 = off, 2 = right) and
+    // delays (128 minus the 1/60 seconds per step).
+    let mut cycles = Vec::new();
+    if let Some(animation) = animation {
+        for channel in 0..4 {
+            let low = read_u16be(animation, channel * 2);
+            let high = read_u16be(animation, 8 + channel * 2);
+            let direction = read_u16be(animation, 16 + channel * 2);
+            let delay = read_u16be(animation, 24 + channel * 2) as i16;
+            let vblanks = 128 - delay as i64;
+
+            let mode = match direction {
+                0 => CycleMode::Reverse,
+                2 => CycleMode::Forward,
+                _ => continue,
+            };
+
+            if low < high && high < 16 && vblanks > 0 {
+                let delay_usec = vblanks as u64 * 1_000_000 / ST_VBLANK_HZ;
+                cycles.push(Cycle::with_delay(low as u8, high as u8, delay_usec, mode));
+            }
+        }
+    }
+
+    Ok((CycleImage::new(None, image, cycles.into()), resolution.pixel_aspect()))
+}
+
+/// Decompress a Degas Elite screen into the ST's interleaved layout. Returns
+/// the screen and the number of bytes read.
+fn unpack_screen(data: &[u8], resolution: Resolution) -> Result<(Vec<u8>, usize), Error> {
+    let (_, height) = resolution.size();
+    let num_planes = resolution.num_planes();
+    let line_len = ST_SCREEN_SIZE / height as usize;
+    let plane_line_len = line_len / num_planes;
+
+    let mut screen = vec![0u8; ST_SCREEN_SIZE];
+    let mut line = vec![0u8; line_len];
+    let mut pos = 0;
+
+    for y in 0..height as usize {
+        let mut line_pos = 0;
+        while line_pos < line_len {
+            let Some(&cmd) = data.get(pos) else {
+                return Err(Error::new("truncated Degas Elite compressed data"));
+            };
+            pos += 1;
+            if cmd < 128 {
+                let count = cmd as usize + 1;
+                let (Some(dest), Some(src)) = (line.get_mut(line_pos..line_pos + count), data.get(pos..pos + count)) else {
+                    return Err(Error::new("broken Degas Elite compression"));
+                };
+                dest.copy_from_slice(src);
+                pos += count;
+                line_pos += count;
+            } else if cmd > 128 {
+                let count = 257 - cmd as usize;
+                let (Some(dest), Some(&value)) = (line.get_mut(line_pos..line_pos + count), data.get(pos)) else {
+                    return Err(Error::new("broken Degas Elite compression"));
+                };
+                dest.fill(value);
+                pos += 1;
+                line_pos += count;
+            }
+        }
+
+        // planes one after another -> interleaved 16 bit words
+        let screen_line = &mut screen[y * line_len..(y + 1) * line_len];
+        for plane in 0..num_planes {
+            let plane_line = &line[plane * plane_line_len..(plane + 1) * plane_line_len];
+            for (word_index, word) in plane_line.chunks_exact(2).enumerate() {
+                let offset = (word_index * num_planes + plane) * 2;
+                screen_line[offset..offset + 2].copy_from_slice(word);
+            }
+        }
+    }
+
+    Ok((screen, pos))
+}
+
+/// The ST's screen is made of groups of 16 pixels, each stored as one 16 bit
+/// word per bit plane.
+fn decode_screen(screen: &[u8], resolution: Resolution, palette: Palette) -> IndexedImage {
+    let (width, height) = resolution.size();
+    let num_planes = resolution.num_planes();
+    let mut image = IndexedImage::new(width, height, palette);
+
+    for y in 0..height {
+        for x in 0..width {
+            let group_offset = ((y * width + x) / 16) as usize * num_planes * 2;
+            let bit = 15 - (x % 16);
+            let mut index = 0u8;
+            for plane in 0..num_planes {
+                let word = read_u16be(screen, group_offset + plane * 2);
+                index |= (((word >> bit) & 1) as u8) << plane;
+            }
+            image.set_index(x, y, index);
+        }
+    }
+
+    image
+}
+
+/// 16 words of `0x0RGB`. The ST has 3 bits per channel, the STE adds a
+/// fourth as the lowest bit, but stored in bit 3 of each nibble. If any
+/// color uses that bit the palette is taken as STE.
+fn read_st_palette(data: &[u8]) -> Palette {
+    let words: Vec<u16> = (0..16).map(|index| read_u16be(data, index * 2)).collect();
+    let is_ste = words.iter().any(|word| word & 0x888 != 0);
+
+    let mut palette = Palette::default();
+    for (index, word) in words.iter().enumerate() {
+        let channel = |shift: u16| {
+            let value = ((word >> shift) & 0xF) as u8;
+            if is_ste {
+                (((value & 7) << 1) | (value >> 3)) * 0x11
+            } else {
+                ((value & 7) as u32 * 255 / 7) as u8
+            }
+        };
+        palette[index as u8] = Rgb([channel(8), channel(4), channel(0)]);
+    }
+
+    palette
+}
+
+#[inline]
+fn read_u16be(data: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([data[offset], data[offset + 1]])
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ilbm::byte_run1, palette::CycleRate};
+
+    use super::*;
+
+    const RESOLUTIONS: [(Resolution, u16); 3] = [(Resolution::Low, 0), (Resolution::Medium, 1), (Resolution::High, 2)];
+
+    /// Noisy on the left, lines of one color on the right, so compression
+    /// needs both literals and runs.
+    fn test_index(x: u32, y: u32, width: u32, num_colors: u32) -> u8 {
+        if x < width / 2 {
+            ((x * 7 + y * 13 + x * y) % num_colors) as u8
+        } else {
+            (y % num_colors) as u8
+        }
+    }
+
+    /// One line of the test image as 16 bit words, one bit plane after another.
+    fn plane_words(resolution: Resolution, y: u32) -> Vec<Vec<u16>> {
+        let (width, _) = resolution.size();
+        let num_planes = resolution.num_planes();
+        (0..num_planes).map(|plane| {
+            (0..width / 16).map(|group| {
+                (0..16).fold(0u16, |word, bit| {
+                    let index = test_index(group * 16 + bit, y, width, 1 << num_planes);
+                    word | ((((index >> plane) & 1) as u16) << (15 - bit))
+                })
+            }).collect()
+        }).collect()
+    }
+
+    fn st_palette(words: &[u16; 16]) -> Vec<u8> {
+        words.iter().flat_map(|word| word.to_be_bytes()).collect()
+    }
+
+    fn test_palette() -> Vec<u8> {
+        st_palette(&[0x000, 0x777, 0x700, 0x070, 0x007, 0x123, 0x456, 0x765, 0, 0, 0, 0, 0, 0, 0, 0x111])
+    }
+
+    fn degas_animation(channels: [(u16, u16, u16, u16); 4]) -> Vec<u8> {
+        let mut animation = Vec::new();
+        for field in 0..4 {
+            for channel in channels {
+                let (low, high, direction, delay) = channel;
+                animation.extend_from_slice(&[low, high, direction, delay][field].to_be_bytes());
+            }
+        }
+        animation
+    }
+
+    fn degas_file(resolution: u16, compressed: bool, animation: &[u8]) -> Vec<u8> {
+        let resolution_value = if compressed { resolution | 0x8000 } else { resolution };
+        let resolution = Resolution::from_value(resolution).unwrap();
+        let (_, height) = resolution.size();
+
+        let mut file = resolution_value.to_be_bytes().to_vec();
+        file.extend_from_slice(&test_palette());
+        for y in 0..height {
+            let planes = plane_words(resolution, y);
+            if compressed {
+                // each bit plane line is compressed on its own
+                for words in planes {
+                    let line: Vec<u8> = words.iter().flat_map(|word| word.to_be_bytes()).collect();
+                    byte_run1(&line, &mut file);
+                }
+            } else {
+                for group in 0..planes[0].len() {
+                    for words in &planes {
+                        file.extend_from_slice(&words[group].to_be_bytes());
+                    }
+                }
+            }
+        }
+        file.extend_from_slice(animation);
+        file
+    }
+
+    fn assert_test_image(image: &CycleImage, resolution: Resolution, case: &str) {
+        let (width, height) = resolution.size();
+        let num_colors = 1 << resolution.num_planes();
+        assert_eq!((image.width(), image.height()), (width, height), "{case}");
+        for y in 0..height {
+            for x in 0..width {
+                assert_eq!(image.get_index(x, y), test_index(x, y, width, num_colors), "{case}, x: {x}, y: {y}");
+            }
+        }
+    }
+
+    fn cycles(image: &CycleImage) -> Vec<(u8, u8, CycleRate, CycleMode)> {
+        image.cycles().iter().map(|cycle| (cycle.low(), cycle.high(), cycle.rate(), cycle.mode())).collect()
+    }
+
+    #[test]
+    fn degas_all_resolutions() {
+        for (resolution, value) in RESOLUTIONS {
+            for (file_type, compressed) in [(StFileType::Degas, false), (StFileType::DegasCompressed, true)] {
+                let case = format!("{resolution:?}, compressed: {compressed}");
+                let file = degas_file(value, compressed, &[]);
+                if !compressed {
+                    assert_eq!(file.len(), 34 + ST_SCREEN_SIZE, "{case}");
+                }
+
+                let (image, aspect) = read_st_image(&mut &file[..], file_type).expect(&case);
+                assert_test_image(&image, resolution, &case);
+                assert_eq!(aspect, resolution.pixel_aspect(), "{case}");
+                assert_eq!(image.palette()[1], Rgb([255, 255, 255]), "{case}");
+                assert!(image.cycles().is_empty(), "{case}");
+            }
+        }
+    }
+
+    #[test]
+    fn degas_elite_animation() {
+        let animation = degas_animation([
+            // left, every 6/60 seconds
+            (2, 5, 0, 128 - 6),
+            // off
+            (6, 9, 1, 128 - 6),
+            // right, every 30/60 seconds
+            (10, 14, 2, 128 - 30),
+            // no delay
+            (0, 15, 2, 128),
+        ]);
+
+        for (file_type, compressed) in [(StFileType::Degas, false), (StFileType::DegasCompressed, true)] {
+            let file = degas_file(0, compressed, &animation);
+            let (image, _) = read_st_image(&mut &file[..], file_type).unwrap();
+            assert_test_image(&image, Resolution::Low, &format!("compressed: {compressed}"));
+            assert_eq!(cycles(&image), [
+                (2, 5, CycleRate::Delay(100_000), CycleMode::Reverse),
+                (10, 14, CycleRate::Delay(500_000), CycleMode::Forward),
+            ], "compressed: {compressed}");
+        }
+    }
+
+    fn neo_file(limits: u16, speed: u16) -> Vec<u8> {
+        let mut file = vec![0u8; 128];
+        file[2..4].copy_from_slice(&0u16.to_be_bytes());
+        file[4..36].copy_from_slice(&test_palette());
+        file[48..50].copy_from_slice(&limits.to_be_bytes());
+        file[50..52].copy_from_slice(&speed.to_be_bytes());
+        let degas = degas_file(0, false, &[]);
+        file.extend_from_slice(&degas[34..]);
+        file
+    }
+
+    #[test]
+    fn neochrome_animation() {
+        // lower color in the upper nibble, signed number of vertical blanks
+        for (speed, mode) in [(0x8000 | 12, CycleMode::Forward), (0x8000 | (-12i8 as u8 as u16), CycleMode::Reverse)] {
+            let file = neo_file(0x8000 | 0x39, speed);
+            let (image, aspect) = read_st_image(&mut &file[..], StFileType::NEOchrome).unwrap();
+            assert_test_image(&image, Resolution::Low, &format!("speed: {speed:#x}"));
+            assert_eq!(aspect, (1, 1));
+            assert_eq!(cycles(&image), [(3, 9, CycleRate::Delay(200_000), mode)], "speed: {speed:#x}");
+        }
+
+        // animation off, limits not valid, empty range
+        for (limits, speed) in [(0x8039, 12), (0x0039, 0x800C), (0x8093, 0x800C)] {
+            let file = neo_file(limits, speed);
+            let (image, _) = read_st_image(&mut &file[..], StFileType::NEOchrome).unwrap();
+            assert!(image.cycles().is_empty(), "limits: {limits:#x}, speed: {speed:#x}");
+        }
+    }
+
+    #[test]
+    fn st_and_ste_palettes() {
+        let palette = read_st_palette(&test_palette());
+        assert_eq!(palette[0], Rgb([0, 0, 0]));
+        assert_eq!(palette[1], Rgb([255, 255, 255]));
+        assert_eq!(palette[5], Rgb([36, 72, 109]));
+        assert_eq!(palette[7], Rgb([255, 218, 182]));
+
+        // the STE's lowest bit is bit 3 of each nibble
+        let palette = read_st_palette(&st_palette(&[0x000, 0xFFF, 0x800, 0x080, 0x008, 0x123, 0x777, 0, 0, 0, 0, 0, 0, 0, 0, 0]));
+        assert_eq!(palette[1], Rgb([255, 255, 255]));
+        assert_eq!(palette[2], Rgb([0x11, 0, 0]));
+        assert_eq!(palette[3], Rgb([0, 0x11, 0]));
+        assert_eq!(palette[4], Rgb([0, 0, 0x11]));
+        assert_eq!(palette[5], Rgb([0x22, 0x44, 0x66]));
+        assert_eq!(palette[6], Rgb([0xEE, 0xEE, 0xEE]));
+    }
+
+    #[test]
+    fn truncated_files() {
+        let file = degas_file(0, true, &[]);
+        assert!(read_st_image(&mut &file[..file.len() - 1], StFileType::DegasCompressed).is_err());
+
+        let file = degas_file(1, false, &[]);
+        assert!(read_st_image(&mut &file[..file.len() - 1], StFileType::Degas).is_err());
+
+        let file = neo_file(0, 0);
+        assert!(read_st_image(&mut &file[..file.len() - 1], StFileType::NEOchrome).is_err());
+    }
+}
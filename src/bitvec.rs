@@ -130,22 +130,25 @@ impl BitVec {
         self.bits.fill(byte);
     }
 
+    /// Append the first `bit_len` bits of `bytes`, lowest bit first.
     #[inline]
     pub fn extend_from_bytes(&mut self, bytes: &[u8], bit_len: usize) {
+        let bytes = &bytes[..bit_len.div_ceil(8)];
         let last_byte_bits = self.len % 8;
         if last_byte_bits == 0 {
-            self.bits.extend_from_slice(&bytes[..bit_len * 8]);
+            self.bits.extend_from_slice(bytes);
         } else {
             let empty_bits = 8 - last_byte_bits;
             let mask = 0xFFu8 >> empty_bits;
 
-            for &byte in &bytes[..bit_len * 8] {
+            for &byte in bytes {
                 let last = self.bits.last_mut().unwrap();
                 *last = (*last & mask) | (byte << last_byte_bits);
                 self.bits.push(byte >> empty_bits);
             }
         }
         self.len += bit_len;
+        self.bits.truncate(self.len.div_ceil(8));
     }
 
     #[inline]
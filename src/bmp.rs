@@ -0,0 +1,299 @@
+// color-cycle - render color cycle images
+// Copyright (C) 2025  Mathias Panzenböck
+// 
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+// 
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+// 
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Reader for palette based Windows/OS/2 BMP files (1, 4 and 8 bits per
+// pixel, uncompressed or RLE).
+
+use std::io::Read;
+
+use crate::{color::Rgb, error::Error, image::IndexedImage, palette::Palette};
+
+const FILE_HEADER_SIZE: usize = 14;
+const CORE_HEADER_SIZE: usize = 12;
+
+const BI_RGB: u32 = 0;
+const BI_RLE8: u32 = 1;
+const BI_RLE4: u32 = 2;
+
+pub fn read_bmp(reader: &mut impl Read) -> Result<IndexedImage, Error> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+
+    if data.len() < FILE_HEADER_SIZE + CORE_HEADER_SIZE || &data[..2] != b"BM" {
+        return Err(Error::new("not a BMP file"));
+    }
+
+    let data_offset = read_u32le(&data, 10) as usize;
+    let header_size = read_u32le(&data, FILE_HEADER_SIZE) as usize;
+
+    let width;
+    let height;
+    let bits_per_pixel;
+    let mut compression = BI_RGB;
+    let mut colors_used = 0;
+    let palette_entry_size;
+    if header_size == CORE_HEADER_SIZE {
+        // OS/2 BITMAPCOREHEADER
+        width = read_u16le(&data, 18) as i64;
+        height = read_u16le(&data, 20) as i64;
+        bits_per_pixel = read_u16le(&data, 24);
+        palette_entry_size = 3;
+    } else if header_size >= 40 && data.len() >= FILE_HEADER_SIZE + 40 {
+        width = read_u32le(&data, 18) as i32 as i64;
+        height = read_u32le(&data, 22) as i32 as i64;
+        bits_per_pixel = read_u16le(&data, 28);
+        compression = read_u32le(&data, 30);
+        colors_used = read_u32le(&data, 46) as usize;
+        palette_entry_size = 4;
+    } else {
+        return Err(Error::new(format!("unsupported BMP header size: {header_size}")));
+    }
+
+    if !matches!(bits_per_pixel, 1 | 4 | 8) {
+        return Err(Error::new(format!("BMP with {bits_per_pixel} bits per pixel is not palette based")));
+    }
+
+    // negative height means the rows are stored top down
+    let top_down = height < 0;
+    let width = width as u32;
+    let height = height.unsigned_abs() as u32;
+    if width == 0 || height == 0 || width > u16::MAX as u32 || height > u16::MAX as u32 {
+        return Err(Error::new(format!("unsupported BMP size: {width} x {height}")));
+    }
+
+    let max_colors = 1usize << bits_per_pixel;
+    let num_colors = if colors_used == 0 { max_colors } else { colors_used.min(max_colors) };
+    let palette_offset = FILE_HEADER_SIZE + header_size;
+    let mut palette = Palette::default();
+    for index in 0..num_colors {
+        let offset = palette_offset + index * palette_entry_size;
+        let Some(&[b, g, r]) = data.get(offset..offset + 3) else {
+            return Err(Error::new("truncated BMP palette"));
+        };
+        palette[index as u8] = Rgb([r, g, b]);
+    }
+
+    let Some(pixel_data) = data.get(data_offset..) else {
+        return Err(Error::new("truncated BMP file"));
+    };
+
+    let mut image = IndexedImage::new(width, height, palette);
+    match (compression, bits_per_pixel) {
+        (BI_RGB, _) => {
+            let row_len = (width as usize * bits_per_pixel as usize).div_ceil(32) * 4;
+            if pixel_data.len() < row_len * height as usize {
+                return Err(Error::new("truncated BMP pixel data"));
+            }
+            let pixels_per_byte = 8 / bits_per_pixel as u32;
+            let mask = ((1u16 << bits_per_pixel) - 1) as u8;
+            for (row_index, row) in pixel_data.chunks_exact(row_len).take(height as usize).enumerate() {
+                let y = if top_down { row_index as u32 } else { height - 1 - row_index as u32 };
+                for x in 0..width {
+                    let byte = row[(x / pixels_per_byte) as usize];
+                    let shift = 8 - bits_per_pixel as u32 * (x % pixels_per_byte + 1);
+                    image.set_index(x, y, (byte >> shift) & mask);
+                }
+            }
+        }
+        (BI_RLE8, 8) | (BI_RLE4, 4) => {
+            decode_rle(pixel_data, bits_per_pixel == 4, top_down, &mut image)?;
+        }
+        _ => {
+            return Err(Error::new(format!("unsupported BMP compression: {compression}")));
+        }
+    }
+
+    Ok(image)
+}
+
+/// RLE8 and RLE4: pairs of count and value, or 0 followed by an escape code:
+/// 0 end of line, 1 end of bitmap, 2 move by the following x and y, and
+/// anything else the number of literal pixels following, padded to 16 bits.
+/// RLE4 values hold two alternating pixels.
+fn decode_rle(data: &[u8], rle4: bool, top_down: bool, image: &mut IndexedImage) -> Result<(), Error> {
+    let width = image.width();
+    let height = image.height();
+    let mut x = 0u32;
+    let mut row = 0u32;
+    let mut pos = 0;
+
+    let mut put = |x: u32, row: u32, index: u8| {
+        if x < width && row < height {
+            let y = if top_down { row } else { height - 1 - row };
+            image.set_index(x, y, index);
+        }
+    };
+    let truncated = || Error::new("truncated BMP RLE data");
+
+    while pos + 1 < data.len() {
+        let count = data[pos];
+        let value = data[pos + 1];
+        pos += 2;
+
+        if count > 0 {
+            for index in 0..count {
+                let pixel = if rle4 {
+                    if index & 1 == 0 { value >> 4 } else { value & 0xF }
+                } else {
+                    value
+                };
+                put(x, row, pixel);
+                x += 1;
+            }
+            continue;
+        }
+
+        match value {
+            0 => {
+                x = 0;
+                row += 1;
+            }
+            1 => break,
+            2 => {
+                let Some(&[dx, dy]) = data.get(pos..pos + 2) else {
+                    return Err(truncated());
+                };
+                pos += 2;
+                x += dx as u32;
+                row += dy as u32;
+            }
+            count => {
+                let len = if rle4 { (count as usize).div_ceil(2) } else { count as usize };
+                let Some(literal) = data.get(pos..pos + len) else {
+                    return Err(truncated());
+                };
+                for index in 0..count as usize {
+                    let pixel = if rle4 {
+                        let byte = literal[index / 2];
+                        if index & 1 == 0 { byte >> 4 } else { byte & 0xF }
+                    } else {
+                        literal[index]
+                    };
+                    put(x, row, pixel);
+                    x += 1;
+                }
+                pos += len + (len & 1);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[inline]
+fn read_u16le(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+#[inline]
+fn read_u32le(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bmp_file(header: &[u8], palette: &[u8], pixel_data: &[u8]) -> Vec<u8> {
+        let data_offset = (FILE_HEADER_SIZE + header.len() + palette.len()) as u32;
+        let mut data = b"BM".to_vec();
+        data.extend_from_slice(&(data_offset + pixel_data.len() as u32).to_le_bytes());
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(&data_offset.to_le_bytes());
+        data.extend_from_slice(header);
+        data.extend_from_slice(palette);
+        data.extend_from_slice(pixel_data);
+        data
+    }
+
+    fn info_header(width: i32, height: i32, bits_per_pixel: u16, compression: u32, colors_used: u32) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.extend_from_slice(&40u32.to_le_bytes());
+        header.extend_from_slice(&width.to_le_bytes());
+        header.extend_from_slice(&height.to_le_bytes());
+        header.extend_from_slice(&1u16.to_le_bytes());
+        header.extend_from_slice(&bits_per_pixel.to_le_bytes());
+        header.extend_from_slice(&compression.to_le_bytes());
+        header.extend_from_slice(&[0; 12]);
+        header.extend_from_slice(&colors_used.to_le_bytes());
+        header.extend_from_slice(&[0; 4]);
+        header
+    }
+
+    /// BGRX entries with distinct colors.
+    fn test_palette(num_colors: usize, entry_size: usize) -> Vec<u8> {
+        (0..num_colors).flat_map(|index| {
+            let index = index as u8;
+            [index, 100, 255 - index, 0].into_iter().take(entry_size)
+        }).collect()
+    }
+
+    #[test]
+    fn uncompressed_bottom_up() {
+        // rows are padded to 32 bits, last row first
+        let pixel_data = [
+            0x45, 0xF0, 0, 0,
+            0x12, 0x30, 0, 0,
+        ];
+        let file = bmp_file(&info_header(3, 2, 4, BI_RGB, 0), &test_palette(16, 4), &pixel_data);
+
+        let image = read_bmp(&mut &file[..]).unwrap();
+        assert_eq!(image.size(), (3, 2));
+        assert_eq!(image.data(), &[1, 2, 3, 4, 5, 15]);
+        assert_eq!(image.palette()[2], Rgb([253, 100, 2]));
+    }
+
+    #[test]
+    fn rle8_top_down() {
+        let pixel_data = [
+            // literal 7 1 2, padded, then a run of one 5, end of line
+            0, 3, 7, 1, 2, 0,
+            1, 5,
+            0, 0,
+            // move 2 to the right, run of two 9, end of bitmap
+            0, 2, 2, 0,
+            2, 9,
+            0, 1,
+        ];
+        let file = bmp_file(&info_header(4, -2, 8, BI_RLE8, 10), &test_palette(10, 4), &pixel_data);
+
+        let image = read_bmp(&mut &file[..]).unwrap();
+        assert_eq!(image.data(), &[7, 1, 2, 5, 0, 0, 9, 9]);
+        assert_eq!(image.palette()[9], Rgb([246, 100, 9]));
+        assert_eq!(image.palette()[10], Rgb([0, 0, 0]));
+    }
+
+    #[test]
+    fn os2_core_header() {
+        let mut header = Vec::new();
+        header.extend_from_slice(&(CORE_HEADER_SIZE as u32).to_le_bytes());
+        header.extend_from_slice(&9u16.to_le_bytes());
+        header.extend_from_slice(&1u16.to_le_bytes());
+        header.extend_from_slice(&1u16.to_le_bytes());
+        header.extend_from_slice(&1u16.to_le_bytes());
+        let file = bmp_file(&header, &test_palette(2, 3), &[0b1011_0001, 0b1000_0000, 0, 0]);
+
+        let image = read_bmp(&mut &file[..]).unwrap();
+        assert_eq!(image.data(), &[1, 0, 1, 1, 0, 0, 0, 1, 1]);
+        assert_eq!(image.palette()[1], Rgb([254, 100, 1]));
+    }
+
+    #[test]
+    fn true_color_unsupported() {
+        let file = bmp_file(&info_header(1, 1, 24, BI_RGB, 0), &[], &[1, 2, 3, 0]);
+        assert!(read_bmp(&mut &file[..]).is_err());
+    }
+}
@@ -14,7 +14,7 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use std::{fmt::{Debug, Display}, ops::{Index, IndexMut}};
+use std::{fmt::{Debug, Display}, ops::{Index, IndexMut}, str::FromStr, sync::LazyLock};
 
 #[derive(Clone, Copy, PartialEq, Eq, Default, Hash)]
 #[repr(transparent)]
@@ -86,3 +86,210 @@ pub fn blend(c1: Rgb, c2: Rgb, mid: f64) -> Rgb {
 
     Rgb([r as u8, g as u8, b as u8])
 }
+
+/// Color space in which colors are interpolated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum BlendSpace {
+    /// Interpolate the gamma encoded sRGB values. Fast, but the midpoints
+    /// are too dark and muddy.
+    #[default]
+    Srgb,
+    /// Interpolate linear light RGB values.
+    Linear,
+    /// Interpolate in the perceptual OKLab color space.
+    Oklab,
+}
+
+impl BlendSpace {
+    #[inline]
+    pub fn next(self) -> Self {
+        match self {
+            BlendSpace::Srgb   => BlendSpace::Linear,
+            BlendSpace::Linear => BlendSpace::Oklab,
+            BlendSpace::Oklab  => BlendSpace::Srgb,
+        }
+    }
+
+    #[inline]
+    pub fn name(&self) -> &'static str {
+        match self {
+            BlendSpace::Srgb   => "srgb",
+            BlendSpace::Linear => "linear",
+            BlendSpace::Oklab  => "oklab",
+        }
+    }
+}
+
+impl Display for BlendSpace {
+    #[inline]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for BlendSpace {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "srgb"   => Ok(BlendSpace::Srgb),
+            "linear" => Ok(BlendSpace::Linear),
+            "oklab"  => Ok(BlendSpace::Oklab),
+            _ => Err(format!("illegal blend space: {value:?}, expected srgb, linear or oklab")),
+        }
+    }
+}
+
+static SRGB_TO_LINEAR: LazyLock<[f64; 256]> = LazyLock::new(|| {
+    let mut table = [0.0; 256];
+    for (value, linear) in table.iter_mut().enumerate() {
+        let value = value as f64 / 255.0;
+        *linear = if value <= 0.04045 {
+            value / 12.92
+        } else {
+            ((value + 0.055) / 1.055).powf(2.4)
+        };
+    }
+    table
+});
+
+#[inline]
+fn srgb_to_linear(value: u8) -> f64 {
+    SRGB_TO_LINEAR[value as usize]
+}
+
+#[inline]
+fn linear_to_srgb(value: f64) -> u8 {
+    let value = value.clamp(0.0, 1.0);
+    let value = if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    (value * 255.0).round() as u8
+}
+
+// see https://bottosson.github.io/posts/oklab/
+
+fn linear_to_oklab([r, g, b]: [f64; 3]) -> [f64; 3] {
+    let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+    let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+    let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+
+    [
+        0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+        1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+        0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+    ]
+}
+
+fn oklab_to_linear([l, a, b]: [f64; 3]) -> [f64; 3] {
+    let l_ = l + 0.3963377774 * a + 0.2158037573 * b;
+    let m_ = l - 0.1055613458 * a - 0.0638541728 * b;
+    let s_ = l - 0.0894841775 * a - 1.2914855480 * b;
+
+    let l = l_ * l_ * l_;
+    let m = m_ * m_ * m_;
+    let s = s_ * s_ * s_;
+
+    [
+         4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s,
+        -1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s,
+        -0.0041960863 * l - 0.7034186147 * m + 1.7076633010 * s,
+    ]
+}
+
+#[inline]
+fn lerp([a1, b1, c1]: [f64; 3], [a2, b2, c2]: [f64; 3], mid: f64) -> [f64; 3] {
+    let inv_mid = 1.0 - mid;
+    [
+        a1 * inv_mid + a2 * mid,
+        b1 * inv_mid + b2 * mid,
+        c1 * inv_mid + c2 * mid,
+    ]
+}
+
+/// Like [`blend()`], but interpolates in the given color space.
+pub fn blend_in(c1: Rgb, c2: Rgb, mid: f64, space: BlendSpace) -> Rgb {
+    if c1 == c2 {
+        return c1;
+    }
+
+    match space {
+        BlendSpace::Srgb => blend(c1, c2, mid),
+        BlendSpace::Linear => {
+            let rgb1 = c1.0.map(srgb_to_linear);
+            let rgb2 = c2.0.map(srgb_to_linear);
+
+            Rgb(lerp(rgb1, rgb2, mid).map(linear_to_srgb))
+        }
+        BlendSpace::Oklab => {
+            let lab1 = linear_to_oklab(c1.0.map(srgb_to_linear));
+            let lab2 = linear_to_oklab(c2.0.map(srgb_to_linear));
+
+            Rgb(oklab_to_linear(lerp(lab1, lab2, mid)).map(linear_to_srgb))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPACES: [BlendSpace; 3] = [BlendSpace::Srgb, BlendSpace::Linear, BlendSpace::Oklab];
+
+    fn colors() -> impl Iterator<Item = Rgb> {
+        (0..=255u8).step_by(5).flat_map(|r| [
+            Rgb([r, 0, 0]),
+            Rgb([r, r, r]),
+            Rgb([r, 255 - r, r / 3]),
+            Rgb([255, r, 128]),
+        ])
+    }
+
+    #[test]
+    fn blend_endpoints() {
+        for space in SPACES {
+            for c1 in colors() {
+                for c2 in [Rgb([0, 0, 0]), Rgb([255, 255, 255]), Rgb([12, 200, 99]), Rgb([255, 0, 255])] {
+                    assert_eq!(blend_in(c1, c2, 0.0, space), c1, "{space}: {c1} {c2}");
+                    assert_eq!(blend_in(c1, c2, 1.0, space), c2, "{space}: {c1} {c2}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn blend_midpoints() {
+        let black = Rgb([0, 0, 0]);
+        let white = Rgb([255, 255, 255]);
+        assert_eq!(blend_in(black, white, 0.5, BlendSpace::Srgb), Rgb([128, 128, 128]));
+        // half the light
+        assert_eq!(blend_in(black, white, 0.5, BlendSpace::Linear), Rgb([188, 188, 188]));
+
+        // half the lightness, which is an eighth of the light
+        assert_eq!(blend_in(black, white, 0.5, BlendSpace::Oklab), Rgb([99, 99, 99]));
+    }
+
+    #[test]
+    fn linear_lut_inverts() {
+        for value in 0..=255u8 {
+            let round_trip = linear_to_srgb(srgb_to_linear(value));
+            assert!(round_trip.abs_diff(value) <= 1, "{value} -> {round_trip}");
+        }
+        assert_eq!(srgb_to_linear(0), 0.0);
+        assert_eq!(srgb_to_linear(255), 1.0);
+        assert_eq!(linear_to_srgb(-0.5), 0);
+        assert_eq!(linear_to_srgb(1.5), 255);
+    }
+
+    #[test]
+    fn oklab_inverts() {
+        for color in colors() {
+            let round_trip = oklab_to_linear(linear_to_oklab(color.0.map(srgb_to_linear))).map(linear_to_srgb);
+            for (channel, value) in round_trip.into_iter().enumerate() {
+                assert!(value.abs_diff(color[channel]) <= 1, "{color} -> {}", Rgb(round_trip));
+            }
+        }
+    }
+}
@@ -0,0 +1,523 @@
+// color-cycle - render color cycle images
+// Copyright (C) 2025  Mathias Panzenböck
+// 
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+// 
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+// 
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Line based control protocol. Each request is a single line, either in the
+// form `<command> [<argument>]` or as a JSON object like
+// `{"command": "open", "argument": "/path/to/file.lbm"}`. Each response is a
+// single line JSON object with at least an `"ok"` field.
+
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::color::BlendSpace;
+use crate::hotkeys::Command;
+use crate::screenshot::ScreenshotFormat;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ControlCommand {
+    /// Any of the commands that can be bound to hotkeys.
+    Command(Command),
+    Open(PathBuf),
+    /// Go to file by 1-based index.
+    Goto(usize),
+    /// Set the time of day in milliseconds since midnight or `None` for the
+    /// current time.
+    SetTime(Option<u64>),
+    Blend(bool),
+    Dither(bool),
+    BlendSpace(BlendSpace),
+    Osd(bool),
+    Cover(bool),
+    FullScreen(bool),
+    /// Save a screenshot to the given path or to an automatically numbered
+    /// PNG file if `None`.
+    Screenshot(Option<PathBuf>),
+    Status,
+}
+
+fn parse_on_off(name: &str, arg: Option<&str>) -> Result<bool, String> {
+    match arg {
+        Some("on")  | Some("true")  | Some("1") => Ok(true),
+        Some("off") | Some("false") | Some("0") => Ok(false),
+        Some(arg) => Err(format!("illegal argument for command {name:?}: {arg:?}, expected \"on\" or \"off\"")),
+        None => Err(format!("command {name:?} requires an argument")),
+    }
+}
+
+impl ControlCommand {
+    pub fn parse(name: &str, arg: Option<&str>) -> Result<Self, String> {
+        match name {
+            "open" => {
+                let Some(path) = arg else {
+                    return Err(format!("command {name:?} requires an argument"));
+                };
+                Ok(ControlCommand::Open(path.into()))
+            }
+            "goto" => {
+                let Some(arg) = arg else {
+                    return Err(format!("command {name:?} requires an argument"));
+                };
+                match arg.parse() {
+                    Ok(0) | Err(_) => Err(format!("illegal argument for command {name:?}: {arg:?}")),
+                    Ok(index) => Ok(ControlCommand::Goto(index)),
+                }
+            }
+            "set-time" => {
+                match arg {
+                    None | Some("now") => Ok(ControlCommand::SetTime(None)),
+                    Some(arg) => Ok(ControlCommand::SetTime(Some(crate::parse_time_of_day(arg)?))),
+                }
+            }
+            "blend"       => Ok(ControlCommand::Blend(parse_on_off(name, arg)?)),
+            "dither"      => Ok(ControlCommand::Dither(parse_on_off(name, arg)?)),
+            "blend-space" => {
+                let Some(arg) = arg else {
+                    return Err(format!("command {name:?} requires an argument"));
+                };
+                Ok(ControlCommand::BlendSpace(arg.parse()?))
+            }
+            "osd"         => Ok(ControlCommand::Osd(parse_on_off(name, arg)?)),
+            "cover"       => Ok(ControlCommand::Cover(parse_on_off(name, arg)?)),
+            "full-screen" => Ok(ControlCommand::FullScreen(parse_on_off(name, arg)?)),
+            "screenshot" => {
+                match arg {
+                    None => Ok(ControlCommand::Screenshot(None)),
+                    Some(path) => {
+                        let path = PathBuf::from(path);
+                        if ScreenshotFormat::from_path(&path).is_none() {
+                            return Err(format!("unsupported screenshot file extension: {:?}, expected .png or .ilbm", path.to_string_lossy()));
+                        }
+                        Ok(ControlCommand::Screenshot(Some(path)))
+                    }
+                }
+            }
+            "status" => {
+                if arg.is_some() {
+                    return Err(format!("command {name:?} takes no argument"));
+                }
+                Ok(ControlCommand::Status)
+            }
+            _ => {
+                let command = if let Some(arg) = arg {
+                    format!("{name} {arg}")
+                } else {
+                    name.to_owned()
+                };
+                Ok(ControlCommand::Command(command.parse()?))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonRequest {
+    command: String,
+    #[serde(default)]
+    argument: Option<Value>,
+}
+
+impl FromStr for ControlCommand {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let line = line.trim();
+
+        if line.starts_with('{') {
+            let request: JsonRequest = serde_json::from_str(line)
+                .map_err(|err| format!("illegal JSON request: {err}"))?;
+
+            let arg = match &request.argument {
+                None | Some(Value::Null) => None,
+                Some(Value::String(arg)) => Some(arg.clone()),
+                Some(Value::Bool(true)) => Some("on".to_owned()),
+                Some(Value::Bool(false)) => Some("off".to_owned()),
+                Some(Value::Number(arg)) => Some(arg.to_string()),
+                Some(arg) => return Err(format!("illegal argument: {arg}")),
+            };
+
+            return Self::parse(&request.command, arg.as_deref());
+        }
+
+        // The argument is the whole rest of the line so paths may contain spaces.
+        let (name, arg) = match line.split_once(char::is_whitespace) {
+            Some((name, arg)) => (name, Some(arg.trim())),
+            None => (line, None),
+        };
+
+        if name.is_empty() {
+            return Err("empty command".to_owned());
+        }
+
+        Self::parse(name, arg)
+    }
+}
+
+#[derive(Debug)]
+pub struct Request {
+    command: ControlCommand,
+    reply: Sender<Value>,
+}
+
+impl Request {
+    #[inline]
+    pub fn command(&self) -> &ControlCommand {
+        &self.command
+    }
+
+    #[inline]
+    pub fn ok(self) {
+        self.reply(json!({ "ok": true }));
+    }
+
+    #[inline]
+    pub fn error(self, message: impl Into<String>) {
+        self.reply(json!({ "ok": false, "error": message.into() }));
+    }
+
+    #[inline]
+    pub fn reply(self, value: Value) {
+        // The client might already be gone.
+        let _ = self.reply.send(value);
+    }
+}
+
+/// Called after a request was queued, so the event loop can wake up.
+pub type Waker = Arc<dyn Fn() + Send + Sync>;
+
+pub struct ControlServer {
+    sender: Sender<Request>,
+    receiver: Receiver<Request>,
+    socket_path: Option<PathBuf>,
+    waker: Option<Waker>,
+}
+
+impl std::fmt::Debug for ControlServer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ControlServer")
+            .field("sender", &self.sender)
+            .field("receiver", &self.receiver)
+            .field("socket_path", &self.socket_path)
+            .field("waker", &self.waker.is_some())
+            .finish()
+    }
+}
+
+impl Default for ControlServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ControlServer {
+    pub fn new() -> Self {
+        let (sender, receiver) = channel();
+        Self { sender, receiver, socket_path: None, waker: None }
+    }
+
+    /// Has to be set before listening.
+    #[inline]
+    pub fn set_waker(&mut self, waker: impl Fn() + Send + Sync + 'static) {
+        self.waker = Some(Arc::new(waker));
+    }
+
+    #[inline]
+    pub fn try_recv(&self) -> Option<Request> {
+        self.receiver.try_recv().ok()
+    }
+
+    /// Read requests from stdin and write responses to stdout.
+    pub fn listen_stdin(&self) {
+        let sender = self.sender.clone();
+        let waker = self.waker.clone();
+        std::thread::spawn(move || {
+            handle_connection(std::io::stdin().lock(), std::io::stdout(), &sender, waker.as_ref());
+        });
+    }
+
+    #[cfg(unix)]
+    pub fn listen_unix(&mut self, path: &Path) -> std::io::Result<()> {
+        use std::os::unix::fs::FileTypeExt;
+        use std::os::unix::net::{UnixListener, UnixStream};
+
+        // remove stale socket of a previous run, but don't take over the
+        // socket of a running instance
+        if let Ok(meta) = std::fs::symlink_metadata(path) && meta.file_type().is_socket() {
+            if UnixStream::connect(path).is_ok() {
+                return Err(std::io::Error::new(std::io::ErrorKind::AddrInUse,
+                    format!("address in use: {path:?}")));
+            }
+            std::fs::remove_file(path)?;
+        }
+
+        let listener = UnixListener::bind(path)?;
+        self.socket_path = Some(path.to_owned());
+
+        let sender = self.sender.clone();
+        let waker = self.waker.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let sender = sender.clone();
+                        let waker = waker.clone();
+                        std::thread::spawn(move || {
+                            let reader = match stream.try_clone() {
+                                Ok(reader) => reader,
+                                Err(err) => {
+                                    eprintln!("ERROR: control socket: {err}");
+                                    return;
+                                }
+                            };
+                            handle_connection(BufReader::new(reader), stream, &sender, waker.as_ref());
+                        });
+                    }
+                    Err(err) => {
+                        eprintln!("ERROR: control socket: {err}");
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub fn listen_unix(&mut self, _path: &Path) -> std::io::Result<()> {
+        Err(std::io::Error::new(std::io::ErrorKind::Unsupported,
+            "control sockets are only supported on Unix"))
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        if let Some(path) = &self.socket_path {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+fn handle_connection(reader: impl BufRead, mut writer: impl Write, sender: &Sender<Request>, waker: Option<&Waker>) {
+    for line in reader.lines() {
+        let Ok(line) = line else {
+            break;
+        };
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match line.parse() {
+            Ok(command) => {
+                let (reply, response) = channel();
+                if sender.send(Request { command, reply }).is_err() {
+                    break;
+                }
+                if let Some(waker) = waker {
+                    waker();
+                }
+                match response.recv() {
+                    Ok(response) => response,
+                    Err(_) => break,
+                }
+            }
+            Err(err) => json!({ "ok": false, "error": err }),
+        };
+
+        if writeln!(writer, "{response}").and_then(|_| writer.flush()).is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(unix)]
+    use std::os::unix::net::UnixListener;
+
+    use super::*;
+
+    fn parse(line: &str) -> Result<ControlCommand, String> {
+        line.parse()
+    }
+
+    #[test]
+    fn open() {
+        assert_eq!(parse("open /tmp/my images/sunset.lbm"), Ok(ControlCommand::Open("/tmp/my images/sunset.lbm".into())));
+        assert_eq!(parse("  open   spaced  name.gif  "), Ok(ControlCommand::Open("spaced  name.gif".into())));
+        assert!(parse("open").is_err());
+    }
+
+    #[test]
+    fn goto() {
+        assert_eq!(parse("goto 1"), Ok(ControlCommand::Goto(1)));
+        assert_eq!(parse("goto 42"), Ok(ControlCommand::Goto(42)));
+        for line in ["goto 0", "goto -1", "goto two", "goto 1.5", "goto 99999999999999999999999", "goto"] {
+            assert!(parse(line).is_err(), "{line:?}");
+        }
+    }
+
+    #[test]
+    fn set_time() {
+        assert_eq!(parse("set-time 18:30"), Ok(ControlCommand::SetTime(Some((18 * 60 + 30) * 60 * 1000))));
+        assert_eq!(parse("set-time 06:00:15"), Ok(ControlCommand::SetTime(Some((6 * 60 * 60 + 15) * 1000))));
+        assert_eq!(parse("set-time now"), Ok(ControlCommand::SetTime(None)));
+        assert_eq!(parse("set-time"), Ok(ControlCommand::SetTime(None)));
+        for line in ["set-time 24:00", "set-time 12:60", "set-time 18", "set-time 1:2:3:4", "set-time noon", "set-time -1:00"] {
+            assert!(parse(line).is_err(), "{line:?}");
+        }
+    }
+
+    #[test]
+    fn on_off() {
+        for arg in ["on", "true", "1"] {
+            assert_eq!(parse(&format!("blend {arg}")), Ok(ControlCommand::Blend(true)));
+        }
+        for arg in ["off", "false", "0"] {
+            assert_eq!(parse(&format!("blend {arg}")), Ok(ControlCommand::Blend(false)));
+        }
+        assert_eq!(parse("blend yes"), Err("illegal argument for command \"blend\": \"yes\", expected \"on\" or \"off\"".to_owned()));
+        assert_eq!(parse("blend"), Err("command \"blend\" requires an argument".to_owned()));
+
+        assert_eq!(parse("dither on"), Ok(ControlCommand::Dither(true)));
+        assert_eq!(parse("osd off"), Ok(ControlCommand::Osd(false)));
+        assert_eq!(parse("cover on"), Ok(ControlCommand::Cover(true)));
+        assert_eq!(parse("full-screen off"), Ok(ControlCommand::FullScreen(false)));
+        assert_eq!(parse("blend-space oklab"), Ok(ControlCommand::BlendSpace(BlendSpace::Oklab)));
+        assert!(parse("blend-space cmyk").is_err());
+    }
+
+    #[test]
+    fn screenshot() {
+        assert_eq!(parse("screenshot"), Ok(ControlCommand::Screenshot(None)));
+        assert_eq!(parse("screenshot /tmp/shot one.png"), Ok(ControlCommand::Screenshot(Some("/tmp/shot one.png".into()))));
+        assert_eq!(parse("screenshot shot.ILBM"), Ok(ControlCommand::Screenshot(Some("shot.ILBM".into()))));
+        for line in ["screenshot shot.jpg", "screenshot shot", "screenshot shot.png.txt"] {
+            assert!(parse(line).is_err(), "{line:?}");
+        }
+    }
+
+    #[test]
+    fn status() {
+        assert_eq!(parse("status"), Ok(ControlCommand::Status));
+        assert_eq!(parse("{\"command\": \"status\"}"), Ok(ControlCommand::Status));
+        assert!(parse("status now").is_err());
+    }
+
+    #[test]
+    fn json() {
+        assert_eq!(parse(r#"{"command": "open", "argument": "/tmp/my images/a.lbm"}"#), Ok(ControlCommand::Open("/tmp/my images/a.lbm".into())));
+        assert_eq!(parse(r#"{"command": "blend", "argument": true}"#), Ok(ControlCommand::Blend(true)));
+        assert_eq!(parse(r#"{"command": "cover", "argument": false}"#), Ok(ControlCommand::Cover(false)));
+        assert_eq!(parse(r#"{"command": "goto", "argument": 3}"#), Ok(ControlCommand::Goto(3)));
+        assert_eq!(parse(r#"{"command": "set-time", "argument": null}"#), Ok(ControlCommand::SetTime(None)));
+        assert_eq!(parse(r#"{"command": "next-file"}"#), Ok(ControlCommand::Command(Command::NextFile)));
+        assert_eq!(parse(r#"{"command": "move-up", "argument": 10}"#), Ok(ControlCommand::Command(Command::MoveUp(10))));
+
+        assert!(parse(r#"{"command": "goto", "argument": 0}"#).is_err());
+        assert!(parse(r#"{"command": "goto", "argument": 1.5}"#).is_err());
+        assert!(parse(r#"{"command": "open", "argument": ["a", "b"]}"#).is_err());
+        assert!(parse(r#"{"command": "open", "argument": {"path": "a"}}"#).is_err());
+        for line in [r#"{"command": "open""#, r#"{"argument": "a"}"#, r#"{"command": 1}"#, "{"] {
+            let err = parse(line).unwrap_err();
+            assert!(err.starts_with("illegal JSON request: "), "{line:?}: {err}");
+        }
+    }
+
+    #[test]
+    fn hotkey_commands() {
+        assert_eq!(parse("toggle-blend"), Ok(ControlCommand::Command(Command::ToggleBlend)));
+        assert_eq!(parse("open-file 2"), Ok(ControlCommand::Command(Command::OpenFile(2))));
+        assert_eq!(parse("time-forward 60"), Ok(ControlCommand::Command(Command::TimeForward(60))));
+        assert!(parse("toggle-blend on").is_err());
+        assert!(parse("open-file 0").is_err());
+        assert_eq!(parse("frobnicate"), Err("unknown command: \"frobnicate\"".to_owned()));
+        assert_eq!(parse("   "), Err("empty command".to_owned()));
+    }
+
+    #[test]
+    fn connection() {
+        let (sender, receiver) = channel::<Request>();
+        let responder = std::thread::spawn(move || {
+            for request in receiver {
+                match request.command() {
+                    ControlCommand::Status => request.reply(json!({ "ok": true, "file": "a.lbm" })),
+                    ControlCommand::Goto(2) => request.ok(),
+                    _ => request.error("no such file"),
+                }
+            }
+        });
+
+        let input = "status\n\ngoto 2\n{\"command\": \"goto\", \"argument\": 9}\nblend maybe\n";
+        let mut output = Vec::new();
+        handle_connection(input.as_bytes(), &mut output, &sender, None);
+        drop(sender);
+        responder.join().unwrap();
+
+        let lines: Vec<Value> = String::from_utf8(output).unwrap().lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines, [
+            json!({ "ok": true, "file": "a.lbm" }),
+            json!({ "ok": true }),
+            json!({ "ok": false, "error": "no such file" }),
+            json!({ "ok": false, "error": "illegal argument for command \"blend\": \"maybe\", expected \"on\" or \"off\"" }),
+        ]);
+    }
+
+    #[cfg(unix)]
+    fn socket_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("color-cycle-{}-{name}.sock", std::process::id()))
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn socket_in_use() {
+        let path = socket_path("in-use");
+        let _ = std::fs::remove_file(&path);
+        let _listener = UnixListener::bind(&path).unwrap();
+
+        let mut server = ControlServer::new();
+        let err = server.listen_unix(&path).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::AddrInUse);
+        drop(server);
+
+        // the socket of the running instance is left alone
+        assert!(path.exists());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stale_socket() {
+        let path = socket_path("stale");
+        let _ = std::fs::remove_file(&path);
+        drop(UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+
+        let mut server = ControlServer::new();
+        server.listen_unix(&path).unwrap();
+        assert!(std::os::unix::net::UnixStream::connect(&path).is_ok());
+
+        drop(server);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,126 @@
+// color-cycle - render color cycle images
+// Copyright (C) 2025  Mathias Panzenböck
+// 
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+// 
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+// 
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Software CRT emulation applied to the converted RGB24 pixels.
+
+/// Every pixel becomes a block of this many pixels in both directions.
+pub const CRT_SCALE: u32 = 3;
+
+/// Brightness (of 256) of the sub-pixel columns of the aperture grille for
+/// their own and for the other color channels.
+const MASK_BRIGHT: u32 = 320;
+const MASK_DIM: u32 = 176;
+
+/// Brightness (of 256) of the rows of a block, the last is the gap between
+/// scanlines.
+const SCANLINE_BRIGHTNESS: [u32; CRT_SCALE as usize] = [256, 256, 112];
+
+/// How much (of 256) of the blurred brightness spills into the pixel.
+const BLOOM: u32 = 48;
+
+/// Render `src` (`width` by `height` RGB24 pixels) `CRT_SCALE` times as large
+/// into `dest` with an aperture grille shadow mask, dark gaps between the
+/// scanlines and a slight horizontal bloom.
+pub fn apply_crt(src: &[u8], src_pitch: usize, width: u32, height: u32, dest: &mut [u8], dest_pitch: usize) {
+    let width = width as usize;
+    let scale = CRT_SCALE as usize;
+
+    for y in 0..height as usize {
+        let src_row = &src[y * src_pitch..y * src_pitch + width * 3];
+
+        for x in 0..width {
+            let left  = x.saturating_sub(1);
+            let right = (x + 1).min(width - 1);
+
+            let mut rgb = [0u32; 3];
+            let mut glow = [0u32; 3];
+            for channel in 0..3 {
+                let value = src_row[x * 3 + channel] as u32;
+                let blurred = (src_row[left * 3 + channel] as u32 + 2 * value + src_row[right * 3 + channel] as u32) / 4;
+                rgb[channel] = value;
+                // squared, so that mostly bright pixels bloom
+                glow[channel] = blurred * blurred * BLOOM / (255 * 256);
+            }
+
+            for (row_index, &brightness) in SCANLINE_BRIGHTNESS.iter().enumerate() {
+                let row_offset = (y * scale + row_index) * dest_pitch + x * scale * 3;
+                for column in 0..scale {
+                    let pixel_offset = row_offset + column * 3;
+                    for channel in 0..3 {
+                        let mask = if column == channel { MASK_BRIGHT } else { MASK_DIM };
+                        let value = rgb[channel] * mask / 256 * brightness / 256 + glow[channel];
+                        dest[pixel_offset + channel] = value.min(255) as u8;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCALE: usize = CRT_SCALE as usize;
+
+    fn crt(src: &[u8], width: u32, height: u32) -> Vec<u8> {
+        let dest_pitch = width as usize * SCALE * 3;
+        let mut dest = vec![0u8; dest_pitch * height as usize * SCALE];
+        apply_crt(src, width as usize * 3, width, height, &mut dest, dest_pitch);
+        dest
+    }
+
+    #[test]
+    fn black_stays_black() {
+        assert!(crt(&[0; 2 * 3 * 3], 2, 3).iter().all(|&value| value == 0));
+    }
+
+    #[test]
+    fn white_pixel() {
+        // 255 * 320 / 256 = 318 and 255 * 176 / 256 = 175, plus a bloom
+        // of 255 * 255 * 48 / (255 * 256) = 47
+        let dest = crt(&[255, 255, 255], 1, 1);
+        assert_eq!(dest, [
+            255, 222, 222,  222, 255, 222,  222, 222, 255,
+            255, 222, 222,  222, 255, 222,  222, 222, 255,
+            // scanline gap at 112 / 256
+            186, 123, 123,  123, 186, 123,  123, 123, 186,
+        ]);
+    }
+
+    #[test]
+    fn bloom_spills_into_neighbors() {
+        let dest = crt(&[255, 255, 255, 0, 0, 0, 0, 0, 0], 3, 1);
+        // (255 + 0 + 0) / 4 = 63 blurred, 63 * 63 * 48 / (255 * 256) = 2
+        assert!(dest[SCALE * 3..2 * SCALE * 3].iter().all(|&value| value == 2));
+        assert!(dest[2 * SCALE * 3..3 * SCALE * 3].iter().all(|&value| value == 0));
+    }
+
+    #[test]
+    fn pitches() {
+        // rows with padding, which is neither read nor written
+        let src = [0, 0, 0, 99, 40, 80, 120, 99];
+        let dest_pitch = SCALE * 3 + 1;
+        let mut dest = vec![7u8; dest_pitch * 2 * SCALE];
+        apply_crt(&src, 4, 1, 2, &mut dest, dest_pitch);
+
+        for y in 0..2 * SCALE {
+            let row = &dest[y * dest_pitch..(y + 1) * dest_pitch];
+            assert_eq!(row[SCALE * 3], 7);
+            assert_eq!(row[..SCALE * 3].iter().any(|&value| value != 0), y >= SCALE, "row {y}");
+        }
+    }
+}
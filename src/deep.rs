@@ -0,0 +1,513 @@
+// color-cycle - render color cycle images
+// Copyright (C) 2025  Mathias Panzenböck
+// 
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+// 
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+// 
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Reader for TVPaint's IFF DEEP true-color images.
+
+use std::io::{Read, Seek};
+
+use crate::{color::Rgb, ilbm::{read_i16be, read_u16be, read_u32be, read_u8, Error, ErrorKind, Result}, image::RgbImage};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Compression {
+    None,
+    RunLength,
+}
+
+/// Global information.
+#[derive(Debug)]
+pub struct DGBL {
+    display_width: u16,
+    display_height: u16,
+    compression: u16,
+    x_aspect: u8,
+    y_aspect: u8,
+}
+
+impl DGBL {
+    pub const SIZE: u32 = 8;
+
+    #[inline]
+    pub fn display_width(&self) -> u16 {
+        self.display_width
+    }
+
+    #[inline]
+    pub fn display_height(&self) -> u16 {
+        self.display_height
+    }
+
+    #[inline]
+    pub fn compression(&self) -> u16 {
+        self.compression
+    }
+
+    #[inline]
+    pub fn x_aspect(&self) -> u8 {
+        self.x_aspect
+    }
+
+    #[inline]
+    pub fn y_aspect(&self) -> u8 {
+        self.y_aspect
+    }
+
+    pub fn read<R>(reader: &mut R, chunk_len: u32) -> Result<Self>
+    where R: Read + Seek {
+        if chunk_len < Self::SIZE {
+            return Err(Error::new(ErrorKind::BrokenFile,
+                format!("truncated DGBL chunk: {} < {}", chunk_len, Self::SIZE)));
+        }
+
+        let display_width = read_u16be(reader)?;
+        let display_height = read_u16be(reader)?;
+        let compression = read_u16be(reader)?;
+        let x_aspect = read_u8(reader)?;
+        let y_aspect = read_u8(reader)?;
+
+        if chunk_len > Self::SIZE {
+            reader.seek_relative((chunk_len - Self::SIZE) as i64)?;
+        }
+
+        Ok(Self { display_width, display_height, compression, x_aspect, y_aspect })
+    }
+}
+
+/// Pixel elements, in the order they are stored in each pixel.
+#[derive(Debug)]
+pub struct DPEL {
+    elements: Vec<(u16, u16)>,
+}
+
+impl DPEL {
+    pub const RED:   u16 = 1;
+    pub const GREEN: u16 = 2;
+    pub const BLUE:  u16 = 3;
+    pub const ALPHA: u16 = 4;
+
+    /// Pairs of element type and bit depth.
+    #[inline]
+    pub fn elements(&self) -> &[(u16, u16)] {
+        &self.elements
+    }
+
+    pub fn read<R>(reader: &mut R, chunk_len: u32) -> Result<Self>
+    where R: Read + Seek {
+        if chunk_len < 4 {
+            return Err(Error::new(ErrorKind::BrokenFile,
+                format!("truncated DPEL chunk: {} < 4", chunk_len)));
+        }
+
+        let count = read_u32be(reader)?;
+        let size = count as u64 * 4 + 4;
+        if size > chunk_len as u64 {
+            return Err(Error::new(ErrorKind::BrokenFile,
+                format!("truncated DPEL chunk: {} < {}", chunk_len, size)));
+        }
+
+        let mut elements = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let element_type = read_u16be(reader)?;
+            let bit_depth = read_u16be(reader)?;
+            elements.push((element_type, bit_depth));
+        }
+
+        if chunk_len as u64 > size {
+            reader.seek_relative(chunk_len as i64 - size as i64)?;
+        }
+
+        Ok(Self { elements })
+    }
+}
+
+/// Location of the following DBOD chunk.
+#[derive(Debug, Clone, Copy)]
+pub struct DLOC {
+    width: u16,
+    height: u16,
+    x: i16,
+    y: i16,
+}
+
+impl DLOC {
+    pub const SIZE: u32 = 8;
+
+    #[inline]
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    #[inline]
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    #[inline]
+    pub fn x(&self) -> i16 {
+        self.x
+    }
+
+    #[inline]
+    pub fn y(&self) -> i16 {
+        self.y
+    }
+
+    pub fn read<R>(reader: &mut R, chunk_len: u32) -> Result<Self>
+    where R: Read + Seek {
+        if chunk_len < Self::SIZE {
+            return Err(Error::new(ErrorKind::BrokenFile,
+                format!("truncated DLOC chunk: {} < {}", chunk_len, Self::SIZE)));
+        }
+
+        let width = read_u16be(reader)?;
+        let height = read_u16be(reader)?;
+        let x = read_i16be(reader)?;
+        let y = read_i16be(reader)?;
+
+        if chunk_len > Self::SIZE {
+            reader.seek_relative((chunk_len - Self::SIZE) as i64)?;
+        }
+
+        Ok(Self { width, height, x, y })
+    }
+}
+
+#[derive(Debug)]
+pub struct DEEP {
+    header: DGBL,
+    image: RgbImage,
+}
+
+impl DEEP {
+    #[inline]
+    pub fn header(&self) -> &DGBL {
+        &self.header
+    }
+
+    #[inline]
+    pub fn image(&self) -> &RgbImage {
+        &self.image
+    }
+
+    #[inline]
+    pub fn into_image(self) -> RgbImage {
+        self.image
+    }
+
+    pub fn read<R>(reader: &mut R) -> Result<DEEP>
+    where R: Read + Seek {
+        let mut fourcc = [0u8; 4];
+        reader.read_exact(&mut fourcc)?;
+
+        if fourcc != *b"FORM" {
+            return Err(Error::new(ErrorKind::UnsupportedFileFormat,
+                format!("illegal FOURCC: {:?} {:?}", &fourcc, String::from_utf8_lossy(&fourcc))));
+        }
+
+        let main_chunk_len = read_u32be(reader)?;
+        reader.read_exact(&mut fourcc)?;
+        if fourcc != *b"DEEP" {
+            return Err(Error::new(ErrorKind::UnsupportedFileFormat,
+                format!("unsupported file format: {:?} {:?}", &fourcc, String::from_utf8_lossy(&fourcc))));
+        }
+
+        let mut header: Option<DGBL> = None;
+        let mut dpel: Option<DPEL> = None;
+        let mut dloc: Option<DLOC> = None;
+        let mut image: Option<RgbImage> = None;
+
+        let mut pos = 4;
+        while pos < main_chunk_len {
+            reader.read_exact(&mut fourcc)?;
+            let chunk_len = read_u32be(reader)?;
+
+            match &fourcc {
+                b"DGBL" => {
+                    let dgbl = DGBL::read(reader, chunk_len)?;
+                    image = Some(RgbImage::new(dgbl.display_width() as u32, dgbl.display_height() as u32));
+                    header = Some(dgbl);
+                }
+                b"DPEL" => {
+                    dpel = Some(DPEL::read(reader, chunk_len)?);
+                }
+                b"DLOC" => {
+                    dloc = Some(DLOC::read(reader, chunk_len)?);
+                }
+                b"DBOD" => {
+                    let (Some(header), Some(dpel), Some(image)) = (&header, &dpel, &mut image) else {
+                        return Err(Error::new(ErrorKind::BrokenFile,
+                            "DGBL or DPEL chunk not found before DBOD chunk"));
+                    };
+                    let dloc = dloc.unwrap_or(DLOC {
+                        width: header.display_width(),
+                        height: header.display_height(),
+                        x: 0,
+                        y: 0,
+                    });
+                    read_body(reader, chunk_len, header, dpel, &dloc, image)?;
+                }
+                _ => {
+                    // skip unknown chunk, including DCHG/TVDC of animations
+                    reader.seek_relative(chunk_len.into())?;
+                }
+            }
+
+            if chunk_len & 1 != 0 {
+                // Chunks are always padded to an even number of bytes.
+                read_u8(reader)?;
+                pos += 1;
+            }
+
+            pos = pos.checked_add(8)
+                .and_then(|pos| pos.checked_add(chunk_len))
+                .ok_or_else(|| Error::new(ErrorKind::BrokenFile,
+                    format!("chunk length too big: {chunk_len}")))?;
+        }
+
+        let (Some(header), Some(image)) = (header, image) else {
+            return Err(Error::new(ErrorKind::BrokenFile, "DGBL chunk missing"));
+        };
+
+        Ok(Self { header, image })
+    }
+}
+
+/// Decode a DBOD chunk and draw it at the location given by `dloc`.
+fn read_body<R>(reader: &mut R, chunk_len: u32, header: &DGBL, dpel: &DPEL, dloc: &DLOC, image: &mut RgbImage) -> Result<()>
+where R: Read + Seek {
+    let compression = match header.compression() {
+        0 => Compression::None,
+        1 => Compression::RunLength,
+        compression => {
+            return Err(Error::new(ErrorKind::UnsupportedFileFormat,
+                format!("unsupported DEEP compression: {compression}")));
+        }
+    };
+
+    // byte offsets of red, green, blue and alpha in a pixel
+    let mut offsets = [None; 4];
+    for (index, &(element_type, bit_depth)) in dpel.elements().iter().enumerate() {
+        if bit_depth != 8 {
+            return Err(Error::new(ErrorKind::UnsupportedFileFormat,
+                format!("unsupported DEEP element bit depth: {bit_depth}")));
+        }
+        if (DPEL::RED..=DPEL::ALPHA).contains(&element_type) {
+            offsets[element_type as usize - 1] = Some(index);
+        }
+    }
+    let pixel_len = dpel.elements().len();
+    if pixel_len == 0 {
+        return Err(Error::new(ErrorKind::BrokenFile, "DPEL chunk without elements"));
+    }
+
+    // the chunk length comes from the file, so only allocate what is there
+    let mut data = Vec::new();
+    reader.take(chunk_len as u64).read_to_end(&mut data)?;
+    if data.len() < chunk_len as usize {
+        return Err(Error::new(ErrorKind::BrokenFile,
+            format!("truncated DBOD chunk: {} < {}", data.len(), chunk_len)));
+    }
+
+    let width = dloc.width() as usize;
+    let height = dloc.height() as usize;
+    let size = width * height * pixel_len;
+    if size == 0 {
+        return Ok(());
+    }
+
+    let data = match compression {
+        Compression::None => {
+            if data.len() < size {
+                return Err(Error::new(ErrorKind::BrokenFile,
+                    format!("truncated DBOD chunk: {} < {}", data.len(), size)));
+            }
+            data
+        }
+        Compression::RunLength => {
+            // ByteRun1 with whole pixels instead of bytes, not limited to rows
+            let mut pixels = Vec::with_capacity(size);
+            let mut pos = 0;
+            while pixels.len() < size && pos < data.len() {
+                let cmd = data[pos] as i8;
+                pos += 1;
+                if cmd >= 0 {
+                    let len = (cmd as usize + 1) * pixel_len;
+                    let Some(literal) = data.get(pos..pos + len) else {
+                        return Err(Error::new(ErrorKind::BrokenFile, "truncated compressed DBOD chunk"));
+                    };
+                    pixels.extend_from_slice(literal);
+                    pos += len;
+                } else if cmd != -128 {
+                    let Some(pixel) = data.get(pos..pos + pixel_len) else {
+                        return Err(Error::new(ErrorKind::BrokenFile, "truncated compressed DBOD chunk"));
+                    };
+                    for _ in 0..(1 - cmd as isize) {
+                        pixels.extend_from_slice(pixel);
+                    }
+                    pos += pixel_len;
+                }
+            }
+            // missing pixels are black
+            pixels.resize(size, 0);
+            pixels
+        }
+    };
+
+    let component = |pixel: &[u8], index: usize| offsets[index].map_or(0, |offset| pixel[offset]);
+    for (y, row) in data[..size].chunks_exact(width * pixel_len).enumerate() {
+        let dest_y = dloc.y() as i64 + y as i64;
+        if dest_y < 0 || dest_y >= image.height() as i64 {
+            continue;
+        }
+        for (x, pixel) in row.chunks_exact(pixel_len).enumerate() {
+            let dest_x = dloc.x() as i64 + x as i64;
+            if dest_x < 0 || dest_x >= image.width() as i64 {
+                continue;
+            }
+            let mut rgb = [component(pixel, 0), component(pixel, 1), component(pixel, 2)];
+            if offsets[3].is_some() {
+                let alpha = component(pixel, 3) as u32;
+                rgb = rgb.map(|value| (value as u32 * alpha / 255) as u8);
+            }
+            image.set_pixel(dest_x as u32, dest_y as u32, Rgb(rgb));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn chunk(fourcc: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = fourcc.to_vec();
+        chunk.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        chunk.extend_from_slice(data);
+        if data.len() & 1 != 0 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn deep_file(width: u16, height: u16, compression: u16, elements: &[u16], chunks: &[Vec<u8>]) -> Vec<u8> {
+        let mut dgbl = Vec::new();
+        dgbl.extend_from_slice(&width.to_be_bytes());
+        dgbl.extend_from_slice(&height.to_be_bytes());
+        dgbl.extend_from_slice(&compression.to_be_bytes());
+        dgbl.extend_from_slice(&[1, 1]);
+
+        let mut dpel = (elements.len() as u32).to_be_bytes().to_vec();
+        for &element_type in elements {
+            dpel.extend_from_slice(&element_type.to_be_bytes());
+            dpel.extend_from_slice(&8u16.to_be_bytes());
+        }
+
+        let mut body = b"DEEP".to_vec();
+        body.extend_from_slice(&chunk(b"DGBL", &dgbl));
+        body.extend_from_slice(&chunk(b"DPEL", &dpel));
+        for chunk in chunks {
+            body.extend_from_slice(chunk);
+        }
+
+        chunk(b"FORM", &body)
+    }
+
+    fn pixels(image: &RgbImage) -> Vec<[u8; 3]> {
+        (0..image.height())
+            .flat_map(|y| (0..image.width()).map(move |x| image.get_pixel(x, y).0))
+            .collect()
+    }
+
+    #[test]
+    fn uncompressed_element_order() {
+        // blue, red, green
+        let file = deep_file(2, 2, 0, &[DPEL::BLUE, DPEL::RED, DPEL::GREEN], &[
+            chunk(b"DBOD", &[
+                3, 1, 2,  6, 4, 5,
+                9, 7, 8,  12, 10, 11,
+            ]),
+        ]);
+
+        let deep = DEEP::read(&mut Cursor::new(&file)).unwrap();
+        assert_eq!(pixels(deep.image()), [[1, 2, 3], [4, 5, 6], [7, 8, 9], [10, 11, 12]]);
+    }
+
+    #[test]
+    fn location_is_clipped() {
+        let mut dloc = Vec::new();
+        for value in [2u16, 1, 2, 1] {
+            dloc.extend_from_slice(&value.to_be_bytes());
+        }
+        let file = deep_file(3, 2, 0, &[DPEL::RED, DPEL::GREEN, DPEL::BLUE], &[
+            chunk(b"DLOC", &dloc),
+            chunk(b"DBOD", &[1, 2, 3,  4, 5, 6]),
+        ]);
+
+        let deep = DEEP::read(&mut Cursor::new(&file)).unwrap();
+        assert_eq!(pixels(deep.image()), [
+            [0, 0, 0], [0, 0, 0], [0, 0, 0],
+            [0, 0, 0], [0, 0, 0], [1, 2, 3],
+        ]);
+    }
+
+    #[test]
+    fn run_length_with_alpha() {
+        let file = deep_file(3, 2, 1, &[DPEL::RED, DPEL::GREEN, DPEL::BLUE, DPEL::ALPHA], &[
+            chunk(b"DBOD", &[
+                // pixel repeated 3 times
+                (-2i8) as u8, 200, 100, 50, 255,
+                // no-op
+                0x80,
+                // 2 literal pixels, the last one is missing and stays black
+                1, 255, 255, 255, 51,  10, 20, 30, 0,
+            ]),
+        ]);
+
+        let deep = DEEP::read(&mut Cursor::new(&file)).unwrap();
+        assert_eq!(pixels(deep.image()), [
+            [200, 100, 50], [200, 100, 50], [200, 100, 50],
+            [51, 51, 51], [0, 0, 0], [0, 0, 0],
+        ]);
+    }
+
+    #[test]
+    fn truncated_body() {
+        let mut file = deep_file(2, 2, 0, &[DPEL::RED, DPEL::GREEN, DPEL::BLUE], &[]);
+        let form_len = u32::from_be_bytes([file[4], file[5], file[6], file[7]]) + 8 + 6;
+        file[4..8].copy_from_slice(&form_len.to_be_bytes());
+        file.extend_from_slice(b"DBOD");
+        file.extend_from_slice(&0x7FFF_FFF0u32.to_be_bytes());
+        file.extend_from_slice(&[1, 2, 3, 4, 5, 6]);
+
+        let error = DEEP::read(&mut Cursor::new(&file)).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::BrokenFile);
+    }
+
+    #[test]
+    fn chunk_length_overflow() {
+        let mut file = Vec::new();
+        file.extend_from_slice(b"FORM");
+        file.extend_from_slice(&u32::MAX.to_be_bytes());
+        file.extend_from_slice(b"DEEP");
+        file.extend_from_slice(b"XXXX");
+        file.extend_from_slice(&(u32::MAX - 5).to_be_bytes());
+
+        let error = DEEP::read(&mut Cursor::new(&file)).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::BrokenFile);
+    }
+}
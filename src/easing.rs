@@ -0,0 +1,223 @@
+// color-cycle - render color cycle images
+// Copyright (C) 2025  Mathias Panzenböck
+// 
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+// 
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+// 
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::{fmt::Display, str::FromStr};
+
+/// How the palettes of a Living Worlds timeline are blended between two
+/// timeline events.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Easing {
+    /// Blend evenly over the whole time between the events.
+    #[default]
+    Linear,
+    /// Start and end the transition slowly.
+    Smoothstep,
+    /// Keep the previous palette for the given fraction (0.0 to 1.0) of the
+    /// time between the events, then blend linearly.
+    Hold(f64),
+    /// Keep the previous palette and blend linearly during the given number
+    /// of milliseconds before the next event.
+    Fade(u64),
+}
+
+impl Easing {
+    /// Map the linear progress `mid` (0.0 to 1.0) of a transition lasting
+    /// `span` milliseconds to the blend factor.
+    pub fn apply(&self, mid: f64, span: u64) -> f64 {
+        let mid = mid.clamp(0.0, 1.0);
+        match *self {
+            Easing::Linear => mid,
+            Easing::Smoothstep => mid * mid * (3.0 - 2.0 * mid),
+            Easing::Hold(hold) => {
+                if hold >= 1.0 {
+                    if mid >= 1.0 { 1.0 } else { 0.0 }
+                } else if mid <= hold {
+                    0.0
+                } else {
+                    (mid - hold) / (1.0 - hold)
+                }
+            }
+            Easing::Fade(duration) => {
+                if duration == 0 {
+                    if mid >= 1.0 { 1.0 } else { 0.0 }
+                } else if duration >= span {
+                    mid
+                } else {
+                    let remaining = (1.0 - mid) * span as f64;
+                    1.0 - (remaining / duration as f64).min(1.0)
+                }
+            }
+        }
+    }
+}
+
+impl Display for Easing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Easing::Linear => "linear".fmt(f),
+            Easing::Smoothstep => "smoothstep".fmt(f),
+            Easing::Hold(hold) => write!(f, "hold:{hold}"),
+            Easing::Fade(duration) => {
+                let secs = duration / 1000;
+                if duration % 1000 != 0 {
+                    write!(f, "fade:{}", *duration as f64 / 1000.0)
+                } else if secs % (60 * 60) == 0 {
+                    write!(f, "fade:{}h", secs / (60 * 60))
+                } else if secs % 60 == 0 {
+                    write!(f, "fade:{}m", secs / 60)
+                } else {
+                    write!(f, "fade:{secs}s")
+                }
+            }
+        }
+    }
+}
+
+/// Parse `N`, `Ns`, `Nm` or `Nh` into milliseconds.
+fn parse_duration(value: &str) -> Option<u64> {
+    let (number, factor) = if let Some(number) = value.strip_suffix('h') {
+        (number, 60.0 * 60.0 * 1000.0)
+    } else if let Some(number) = value.strip_suffix('m') {
+        (number, 60.0 * 1000.0)
+    } else if let Some(number) = value.strip_suffix('s') {
+        (number, 1000.0)
+    } else {
+        (value, 1000.0)
+    };
+
+    let number: f64 = number.trim().parse().ok()?;
+    if !number.is_finite() || number < 0.0 {
+        return None;
+    }
+
+    Some((number * factor).round() as u64)
+}
+
+impl FromStr for Easing {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        let (name, arg) = match value.split_once(':') {
+            Some((name, arg)) => (name.trim(), Some(arg.trim())),
+            None => (value, None),
+        };
+
+        match (name.to_ascii_lowercase().as_str(), arg) {
+            ("linear", None) => Ok(Easing::Linear),
+            ("smoothstep", None) => Ok(Easing::Smoothstep),
+            ("hold", Some(arg)) => {
+                match arg.parse::<f64>() {
+                    Ok(hold) if (0.0..=1.0).contains(&hold) => Ok(Easing::Hold(hold)),
+                    _ => Err(format!("illegal easing: {value:?}, expected hold:FRACTION with a fraction between 0 and 1")),
+                }
+            }
+            ("fade", Some(arg)) => {
+                match parse_duration(arg) {
+                    Some(duration) => Ok(Easing::Fade(duration)),
+                    None => Err(format!("illegal easing: {value:?}, expected fade:DURATION like fade:30m")),
+                }
+            }
+            _ => Err(format!("illegal easing: {value:?}, expected linear, smoothstep, hold:FRACTION or fade:DURATION")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: u64 = 60 * 60 * 1000;
+
+    const EASINGS: [Easing; 9] = [
+        Easing::Linear,
+        Easing::Smoothstep,
+        Easing::Hold(0.0),
+        Easing::Hold(0.25),
+        Easing::Hold(1.0),
+        Easing::Fade(0),
+        Easing::Fade(HOUR / 2),
+        Easing::Fade(2 * HOUR),
+        Easing::Fade(10 * HOUR),
+    ];
+
+    #[test]
+    fn endpoints() {
+        for easing in EASINGS {
+            assert_eq!(easing.apply(0.0, 2 * HOUR), 0.0, "{easing}");
+            assert_eq!(easing.apply(1.0, 2 * HOUR), 1.0, "{easing}");
+            // out of range progress is clamped
+            assert_eq!(easing.apply(-0.5, 2 * HOUR), 0.0, "{easing}");
+            assert_eq!(easing.apply(1.5, 2 * HOUR), 1.0, "{easing}");
+        }
+    }
+
+    #[test]
+    fn monotonic() {
+        for easing in EASINGS {
+            let mut prev = 0.0;
+            for step in 0..=1000 {
+                let value = easing.apply(step as f64 / 1000.0, 2 * HOUR);
+                assert!(value >= prev && value <= 1.0, "{easing} at step {step}: {value} < {prev}");
+                prev = value;
+            }
+        }
+    }
+
+    #[test]
+    fn shapes() {
+        assert_eq!(Easing::Linear.apply(0.25, HOUR), 0.25);
+        assert_eq!(Easing::Smoothstep.apply(0.5, HOUR), 0.5);
+        assert!(Easing::Smoothstep.apply(0.1, HOUR) < 0.1);
+        assert!(Easing::Smoothstep.apply(0.9, HOUR) > 0.9);
+
+        assert_eq!(Easing::Hold(0.25).apply(0.25, HOUR), 0.0);
+        assert_eq!(Easing::Hold(0.25).apply(0.625, HOUR), 0.5);
+        assert_eq!(Easing::Hold(1.0).apply(0.999, HOUR), 0.0);
+
+        // the last half hour of two hours
+        let fade = Easing::Fade(HOUR / 2);
+        assert_eq!(fade.apply(0.75, 2 * HOUR), 0.0);
+        assert_eq!(fade.apply(0.875, 2 * HOUR), 0.5);
+        // longer than the transition is linear
+        assert_eq!(Easing::Fade(10 * HOUR).apply(0.25, 2 * HOUR), 0.25);
+        assert_eq!(Easing::Fade(0).apply(0.999, 2 * HOUR), 0.0);
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!("linear".parse(), Ok(Easing::Linear));
+        assert_eq!(" SmoothStep ".parse(), Ok(Easing::Smoothstep));
+        assert_eq!("hold:0.5".parse(), Ok(Easing::Hold(0.5)));
+        assert_eq!("hold: 1".parse(), Ok(Easing::Hold(1.0)));
+        assert_eq!("fade:90s".parse(), Ok(Easing::Fade(90 * 1000)));
+        assert_eq!("fade:30m".parse(), Ok(Easing::Fade(HOUR / 2)));
+        assert_eq!("fade:1.5h".parse(), Ok(Easing::Fade(3 * HOUR / 2)));
+        assert_eq!("fade:45".parse(), Ok(Easing::Fade(45 * 1000)));
+
+        for value in ["", "ease-in", "linear:1", "smoothstep:2", "hold", "hold:1.5", "hold:-0.1", "hold:x", "fade", "fade:-1m", "fade:1d", "fade:inf"] {
+            assert!(value.parse::<Easing>().is_err(), "{value:?}");
+        }
+    }
+
+    #[test]
+    fn display_round_trip() {
+        for easing in EASINGS.into_iter().chain([Easing::Fade(1500), Easing::Fade(90 * 1000)]) {
+            assert_eq!(easing.to_string().parse(), Ok(easing));
+        }
+        assert_eq!(Easing::Fade(HOUR / 2).to_string(), "fade:30m");
+    }
+}
@@ -16,7 +16,7 @@
 
 use std::fmt::Display;
 
-use sdl2::{render::TextureValueError, ttf::FontError, video::WindowBuildError, IntegerOrSdlError};
+use sdl2::{render::{TextureValueError, UpdateTextureError}, ttf::FontError, video::WindowBuildError, IntegerOrSdlError};
 
 #[derive(Debug)]
 pub struct Error {
@@ -81,6 +81,27 @@ impl From<serde_json::error::Error> for Error {
     }
 }
 
+impl From<png::EncodingError> for Error {
+    #[inline]
+    fn from(value: png::EncodingError) -> Self {
+        Self::with_source("PNG encoding error", Box::new(value))
+    }
+}
+
+impl From<png::DecodingError> for Error {
+    #[inline]
+    fn from(value: png::DecodingError) -> Self {
+        Self::with_source("PNG decoding error", Box::new(value))
+    }
+}
+
+impl From<gif::DecodingError> for Error {
+    #[inline]
+    fn from(value: gif::DecodingError) -> Self {
+        Self::with_source("GIF decoding error", Box::new(value))
+    }
+}
+
 impl From<TextureValueError> for Error {
     #[inline]
     fn from(value: TextureValueError) -> Self {
@@ -88,6 +109,13 @@ impl From<TextureValueError> for Error {
     }
 }
 
+impl From<UpdateTextureError> for Error {
+    #[inline]
+    fn from(value: UpdateTextureError) -> Self {
+        Self::with_source("Update texture error", Box::new(value))
+    }
+}
+
 impl From<FontError> for Error {
     #[inline]
     fn from(value: FontError) -> Self {
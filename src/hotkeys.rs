@@ -0,0 +1,676 @@
+// color-cycle - render color cycle images
+// Copyright (C) 2025  Mathias Panzenböck
+// 
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+// 
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+// 
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::{fmt::Display, fs::File, io::BufReader, path::Path, str::FromStr};
+
+use sdl2::{controller::Button, keyboard::{Keycode, Mod}};
+use serde::{de::{Error, Visitor}, Deserializer};
+
+use crate::screenshot::ScreenshotFormat;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Command {
+    Quit,
+    /// Close full-screen or quit program.
+    Exit,
+    ToggleBlend,
+    ToggleDither,
+    NextBlendSpace,
+    ToggleOsd,
+    ToggleTimeline,
+    NextScaleFilter,
+    ToggleCrt,
+    ToggleHud,
+    ToggleCover,
+    ToggleFullScreen,
+    ToggleFastForward,
+    NextFile,
+    PreviousFile,
+    /// Open file by 1-based index.
+    OpenFile(usize),
+    OpenLastFile,
+    IncreaseFps,
+    DecreaseFps,
+    /// Go back in time by the given number of minutes.
+    TimeBackward(u32),
+    /// Go forward in time by the given number of minutes.
+    TimeForward(u32),
+    CurrentTime,
+    ColumnSwap,
+    MoveUp(i32),
+    MoveDown(i32),
+    MoveLeft(i32),
+    MoveRight(i32),
+    MoveToLeft,
+    MoveToRight,
+    MoveToTop,
+    MoveToBottom,
+    Screenshot(ScreenshotFormat),
+}
+
+impl Command {
+    pub fn description(&self) -> String {
+        match self {
+            Command::Quit              => "Quit program".to_owned(),
+            Command::Exit              => "Close full-screen or quit program".to_owned(),
+            Command::ToggleBlend       => "Toggle blend mode".to_owned(),
+            Command::ToggleDither      => "Toggle dithered blend mode".to_owned(),
+            Command::NextBlendSpace    => "Switch blend color space (sRGB, linear RGB, OKLab)".to_owned(),
+            Command::ToggleOsd         => "Toggle On Screen Display".to_owned(),
+            Command::ToggleTimeline    => "Toggle timeline of Living Worlds".to_owned(),
+            Command::NextScaleFilter   => "Switch scale filter (nearest, Scale2x, Scale3x, xBR)".to_owned(),
+            Command::ToggleCrt         => "Toggle CRT emulation".to_owned(),
+            Command::ToggleHud         => "Toggle frame timing statistics".to_owned(),
+            Command::ToggleCover       => "Toggle zoom to cover/contain".to_owned(),
+            Command::ToggleFullScreen  => "Toggle full-screen".to_owned(),
+            Command::ToggleFastForward => format!("Toogle fast forward ({}x speed)", crate::FAST_FORWARD_SPEED),
+            Command::NextFile          => "Open next file".to_owned(),
+            Command::PreviousFile      => "Open previous file".to_owned(),
+            Command::OpenFile(index)   => format!("Open file {index}"),
+            Command::OpenLastFile      => "Open last file".to_owned(),
+            Command::IncreaseFps       => "Increase frames per second by 1".to_owned(),
+            Command::DecreaseFps       => "Decrease frames per second by 1".to_owned(),
+            Command::TimeBackward(1)   => "Go back in time by 1 minute".to_owned(),
+            Command::TimeBackward(m)   => format!("Go back in time by {m} minutes"),
+            Command::TimeForward(1)    => "Go forward in time by 1 minute".to_owned(),
+            Command::TimeForward(m)    => format!("Go forward in time by {m} minutes"),
+            Command::CurrentTime       => "Go to current time and continue normal progression".to_owned(),
+            Command::ColumnSwap        => "Reverse pixels in columns of 8.\n\
+                                           This is a hack fix for images that appear to be\n\
+                                           broken like that.".to_owned(),
+            Command::MoveUp(amount)    => format!("Move view-port up by {amount} pixel"),
+            Command::MoveDown(amount)  => format!("Move view-port down by {amount} pixel"),
+            Command::MoveLeft(amount)  => format!("Move view-port left by {amount} pixel"),
+            Command::MoveRight(amount) => format!("Move view-port right by {amount} pixel"),
+            Command::MoveToLeft        => "Move view-port to the left edge".to_owned(),
+            Command::MoveToRight       => "Move view-port to the right edge".to_owned(),
+            Command::MoveToTop         => "Move view-port to the top edge".to_owned(),
+            Command::MoveToBottom      => "Move view-port to the bottom edge".to_owned(),
+            Command::Screenshot(ScreenshotFormat::PNG)  => "Save screenshot as true-color PNG".to_owned(),
+            Command::Screenshot(ScreenshotFormat::ILBM) => "Save screenshot as ILBM with current palette and cycles".to_owned(),
+        }
+    }
+}
+
+impl Display for Command {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Command::Quit              => "quit".fmt(f),
+            Command::Exit              => "exit".fmt(f),
+            Command::ToggleBlend       => "toggle-blend".fmt(f),
+            Command::ToggleDither      => "toggle-dither".fmt(f),
+            Command::NextBlendSpace    => "next-blend-space".fmt(f),
+            Command::ToggleOsd         => "toggle-osd".fmt(f),
+            Command::ToggleTimeline    => "toggle-timeline".fmt(f),
+            Command::NextScaleFilter   => "next-scale-filter".fmt(f),
+            Command::ToggleCrt         => "toggle-crt".fmt(f),
+            Command::ToggleHud         => "toggle-hud".fmt(f),
+            Command::ToggleCover       => "toggle-cover".fmt(f),
+            Command::ToggleFullScreen  => "toggle-full-screen".fmt(f),
+            Command::ToggleFastForward => "toggle-fast-forward".fmt(f),
+            Command::NextFile          => "next-file".fmt(f),
+            Command::PreviousFile      => "previous-file".fmt(f),
+            Command::OpenFile(index)   => write!(f, "open-file {index}"),
+            Command::OpenLastFile      => "open-last-file".fmt(f),
+            Command::IncreaseFps       => "increase-fps".fmt(f),
+            Command::DecreaseFps       => "decrease-fps".fmt(f),
+            Command::TimeBackward(m)   => write!(f, "time-backward {m}"),
+            Command::TimeForward(m)    => write!(f, "time-forward {m}"),
+            Command::CurrentTime       => "current-time".fmt(f),
+            Command::ColumnSwap        => "column-swap".fmt(f),
+            Command::MoveUp(amount)    => write!(f, "move-up {amount}"),
+            Command::MoveDown(amount)  => write!(f, "move-down {amount}"),
+            Command::MoveLeft(amount)  => write!(f, "move-left {amount}"),
+            Command::MoveRight(amount) => write!(f, "move-right {amount}"),
+            Command::MoveToLeft        => "move-to-left".fmt(f),
+            Command::MoveToRight       => "move-to-right".fmt(f),
+            Command::MoveToTop         => "move-to-top".fmt(f),
+            Command::MoveToBottom      => "move-to-bottom".fmt(f),
+            Command::Screenshot(format) => write!(f, "screenshot {format}"),
+        }
+    }
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut parts = value.split_whitespace();
+        let Some(name) = parts.next() else {
+            return Err("empty command".to_owned());
+        };
+        let arg = parts.next();
+
+        if parts.next().is_some() {
+            return Err(format!("too many arguments for command: {value:?}"));
+        }
+
+        fn parse_arg<T: FromStr>(name: &str, arg: Option<&str>) -> Result<T, String> {
+            let Some(arg) = arg else {
+                return Err(format!("command {name:?} requires an argument"));
+            };
+            arg.parse().map_err(|_| format!("illegal argument for command {name:?}: {arg:?}"))
+        }
+
+        let command = match name {
+            "quit"                => Command::Quit,
+            "exit"                => Command::Exit,
+            "toggle-blend"        => Command::ToggleBlend,
+            "toggle-dither"       => Command::ToggleDither,
+            "next-blend-space"    => Command::NextBlendSpace,
+            "toggle-osd"          => Command::ToggleOsd,
+            "toggle-timeline"     => Command::ToggleTimeline,
+            "next-scale-filter"   => Command::NextScaleFilter,
+            "toggle-crt"          => Command::ToggleCrt,
+            "toggle-hud"          => Command::ToggleHud,
+            "toggle-cover"        => Command::ToggleCover,
+            "toggle-full-screen"  => Command::ToggleFullScreen,
+            "toggle-fast-forward" => Command::ToggleFastForward,
+            "next-file"           => Command::NextFile,
+            "previous-file"       => Command::PreviousFile,
+            "open-file" => {
+                let index = parse_arg(name, arg)?;
+                if index == 0 {
+                    return Err(format!("file index for command {name:?} starts at 1"));
+                }
+                return Ok(Command::OpenFile(index));
+            }
+            "open-last-file"      => Command::OpenLastFile,
+            "increase-fps"        => Command::IncreaseFps,
+            "decrease-fps"        => Command::DecreaseFps,
+            "time-backward"       => return Ok(Command::TimeBackward(parse_arg(name, arg)?)),
+            "time-forward"        => return Ok(Command::TimeForward(parse_arg(name, arg)?)),
+            "current-time"        => Command::CurrentTime,
+            "column-swap"         => Command::ColumnSwap,
+            "move-up"             => return Ok(Command::MoveUp(parse_arg(name, arg)?)),
+            "move-down"           => return Ok(Command::MoveDown(parse_arg(name, arg)?)),
+            "move-left"           => return Ok(Command::MoveLeft(parse_arg(name, arg)?)),
+            "move-right"          => return Ok(Command::MoveRight(parse_arg(name, arg)?)),
+            "move-to-left"        => Command::MoveToLeft,
+            "move-to-right"       => Command::MoveToRight,
+            "move-to-top"         => Command::MoveToTop,
+            "move-to-bottom"      => Command::MoveToBottom,
+            "screenshot"          => return Ok(Command::Screenshot(parse_arg(name, arg)?)),
+            _ => return Err(format!("unknown command: {name:?}")),
+        };
+
+        if arg.is_some() {
+            return Err(format!("command {name:?} takes no argument"));
+        }
+
+        Ok(command)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyBinding {
+    keycode: Keycode,
+    modifiers: u8,
+}
+
+impl KeyBinding {
+    pub const SHIFT: u8 = 1;
+    pub const CTRL:  u8 = 2;
+    pub const ALT:   u8 = 4;
+
+    #[inline]
+    pub fn new(keycode: Keycode, modifiers: u8) -> Self {
+        Self { keycode, modifiers }
+    }
+
+    pub fn from_event(keycode: Keycode, keymod: Mod) -> Self {
+        let mut modifiers = 0;
+        if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+            modifiers |= Self::SHIFT;
+        }
+        if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) {
+            modifiers |= Self::CTRL;
+        }
+        if keymod.intersects(Mod::LALTMOD | Mod::RALTMOD) {
+            modifiers |= Self::ALT;
+        }
+        Self { keycode, modifiers }
+    }
+
+    #[inline]
+    pub fn keycode(&self) -> Keycode {
+        self.keycode
+    }
+
+    #[inline]
+    pub fn modifiers(&self) -> u8 {
+        self.modifiers
+    }
+}
+
+impl Display for KeyBinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.modifiers & Self::CTRL != 0 {
+            "Ctrl+".fmt(f)?;
+        }
+        if self.modifiers & Self::ALT != 0 {
+            "Alt+".fmt(f)?;
+        }
+        if self.modifiers & Self::SHIFT != 0 {
+            "Shift+".fmt(f)?;
+        }
+        self.keycode.name().fmt(f)
+    }
+}
+
+impl FromStr for KeyBinding {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut modifiers = 0;
+        let mut rest = value.trim();
+
+        // Not just splitting on '+' so that "Ctrl++" and "Keypad +" work.
+        while let Some(index) = rest.find('+') {
+            if index + 1 == rest.len() {
+                break;
+            }
+            let modifier = match rest[..index].trim().to_ascii_lowercase().as_str() {
+                "shift" => Self::SHIFT,
+                "ctrl" | "control" => Self::CTRL,
+                "alt" => Self::ALT,
+                _ => break,
+            };
+            modifiers |= modifier;
+            rest = &rest[index + 1..];
+        }
+
+        let Some(keycode) = Keycode::from_name(rest.trim()) else {
+            return Err(format!("unknown key: {value:?}"));
+        };
+
+        Ok(Self { keycode, modifiers })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(KeyBinding),
+    Button(Button),
+}
+
+impl Binding {
+    const GAMEPAD_PREFIX: &str = "Gamepad ";
+}
+
+impl From<KeyBinding> for Binding {
+    #[inline]
+    fn from(value: KeyBinding) -> Self {
+        Binding::Key(value)
+    }
+}
+
+impl From<Button> for Binding {
+    #[inline]
+    fn from(value: Button) -> Self {
+        Binding::Button(value)
+    }
+}
+
+impl Display for Binding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Binding::Key(key) => key.fmt(f),
+            Binding::Button(button) => write!(f, "{}{}", Self::GAMEPAD_PREFIX, button.string()),
+        }
+    }
+}
+
+impl FromStr for Binding {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        let prefix_len = Self::GAMEPAD_PREFIX.len();
+        if value.len() > prefix_len && value[..prefix_len].eq_ignore_ascii_case(Self::GAMEPAD_PREFIX) {
+            // uses the same names as SDL game controller mappings, e.g. "dpup" or "leftshoulder"
+            let Some(button) = Button::from_string(value[prefix_len..].trim()) else {
+                return Err(format!("unknown gamepad button: {value:?}"));
+            };
+            return Ok(Binding::Button(button));
+        }
+
+        Ok(Binding::Key(value.parse()?))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Hotkeys {
+    bindings: Vec<(Binding, Command)>,
+}
+
+impl Default for Hotkeys {
+    fn default() -> Self {
+        let mut hotkeys = Self { bindings: Vec::new() };
+        let none = 0;
+        let shift = KeyBinding::SHIFT;
+        let ctrl = KeyBinding::CTRL;
+
+        for (keycode, modifiers, command) in [
+            (Keycode::B,      none,  Command::ToggleBlend),
+            (Keycode::B,      shift, Command::ToggleDither),
+            (Keycode::L,      none,  Command::NextBlendSpace),
+            (Keycode::Q,      none,  Command::Quit),
+            (Keycode::ESCAPE, none,  Command::Exit),
+            (Keycode::O,      none,  Command::ToggleOsd),
+            (Keycode::T,      none,  Command::ToggleTimeline),
+            (Keycode::X,      none,  Command::NextScaleFilter),
+            (Keycode::R,      none,  Command::ToggleCrt),
+            (Keycode::F3,     none,  Command::ToggleHud),
+            (Keycode::C,      none,  Command::ToggleCover),
+            (Keycode::N,      none,  Command::NextFile),
+            (Keycode::P,      none,  Command::PreviousFile),
+            (Keycode::NUM_1,  none,  Command::OpenFile(1)),
+            (Keycode::KP_1,   none,  Command::OpenFile(1)),
+            (Keycode::NUM_2,  none,  Command::OpenFile(2)),
+            (Keycode::KP_2,   none,  Command::OpenFile(2)),
+            (Keycode::NUM_3,  none,  Command::OpenFile(3)),
+            (Keycode::KP_3,   none,  Command::OpenFile(3)),
+            (Keycode::NUM_4,  none,  Command::OpenFile(4)),
+            (Keycode::KP_4,   none,  Command::OpenFile(4)),
+            (Keycode::NUM_5,  none,  Command::OpenFile(5)),
+            (Keycode::KP_5,   none,  Command::OpenFile(5)),
+            (Keycode::NUM_6,  none,  Command::OpenFile(6)),
+            (Keycode::KP_6,   none,  Command::OpenFile(6)),
+            (Keycode::NUM_7,  none,  Command::OpenFile(7)),
+            (Keycode::KP_7,   none,  Command::OpenFile(7)),
+            (Keycode::NUM_8,  none,  Command::OpenFile(8)),
+            (Keycode::KP_8,   none,  Command::OpenFile(8)),
+            (Keycode::NUM_9,  none,  Command::OpenFile(9)),
+            (Keycode::KP_9,   none,  Command::OpenFile(9)),
+            (Keycode::NUM_0,  none,  Command::OpenLastFile),
+            (Keycode::KP_0,   none,  Command::OpenLastFile),
+            (Keycode::PLUS,   none,  Command::IncreaseFps),
+            (Keycode::KP_PLUS,  none, Command::IncreaseFps),
+            (Keycode::MINUS,    none, Command::DecreaseFps),
+            (Keycode::KP_MINUS, none, Command::DecreaseFps),
+            (Keycode::F,      none,  Command::ToggleFullScreen),
+            (Keycode::F11,    none,  Command::ToggleFullScreen),
+            (Keycode::W,      none,  Command::ToggleFastForward),
+            (Keycode::A,      none,  Command::TimeBackward(5)),
+            (Keycode::A,      shift, Command::TimeBackward(1)),
+            (Keycode::D,      none,  Command::TimeForward(5)),
+            (Keycode::D,      shift, Command::TimeForward(1)),
+            (Keycode::S,      none,  Command::CurrentTime),
+            (Keycode::I,      none,  Command::ColumnSwap),
+            (Keycode::UP,     none,  Command::MoveUp(1)),
+            (Keycode::DOWN,   none,  Command::MoveDown(1)),
+            (Keycode::LEFT,   none,  Command::MoveLeft(1)),
+            (Keycode::RIGHT,  none,  Command::MoveRight(1)),
+            (Keycode::UP,     ctrl,  Command::MoveUp(10)),
+            (Keycode::DOWN,   ctrl,  Command::MoveDown(10)),
+            (Keycode::LEFT,   ctrl,  Command::MoveLeft(10)),
+            (Keycode::RIGHT,  ctrl,  Command::MoveRight(10)),
+            (Keycode::HOME,   none,  Command::MoveToLeft),
+            (Keycode::END,    none,  Command::MoveToRight),
+            (Keycode::HOME,   ctrl,  Command::MoveToTop),
+            (Keycode::END,    ctrl,  Command::MoveToBottom),
+            (Keycode::F12,    none,  Command::Screenshot(ScreenshotFormat::PNG)),
+            (Keycode::F12,    shift, Command::Screenshot(ScreenshotFormat::ILBM)),
+        ] {
+            hotkeys.bind(KeyBinding::new(keycode, modifiers).into(), Some(command));
+        }
+
+        for (button, command) in [
+            (Button::DPadRight,     Command::NextFile),
+            (Button::DPadLeft,      Command::PreviousFile),
+            (Button::DPadUp,        Command::TimeForward(5)),
+            (Button::DPadDown,      Command::TimeBackward(5)),
+            (Button::RightShoulder, Command::TimeForward(1)),
+            (Button::LeftShoulder,  Command::TimeBackward(1)),
+            (Button::A,             Command::ToggleBlend),
+            (Button::B,             Command::ToggleCover),
+            (Button::X,             Command::ToggleOsd),
+            (Button::Y,             Command::ToggleFastForward),
+            (Button::Back,          Command::CurrentTime),
+            (Button::Start,         Command::ToggleFullScreen),
+        ] {
+            hotkeys.bind(button.into(), Some(command));
+        }
+
+        hotkeys
+    }
+}
+
+impl Hotkeys {
+    #[inline]
+    pub fn bindings(&self) -> &[(Binding, Command)] {
+        &self.bindings
+    }
+
+    /// Bind a key or button to a command, replacing any previous binding of
+    /// it. Passing `None` removes the binding.
+    pub fn bind(&mut self, key: Binding, command: Option<Command>) {
+        let index = self.bindings.iter().position(|(other, _)| *other == key);
+        match (index, command) {
+            (Some(index), Some(command)) => self.bindings[index].1 = command,
+            (Some(index), None) => { self.bindings.remove(index); }
+            (None, Some(command)) => self.bindings.push((key, command)),
+            (None, None) => {}
+        }
+    }
+
+    #[inline]
+    pub fn get_exact(&self, key: Binding) -> Option<Command> {
+        self.bindings.iter()
+            .find(|(other, _)| *other == key)
+            .map(|(_, command)| *command)
+    }
+
+    /// Look up the command for a key event. If there is no binding for the
+    /// exact modifier combination the modifiers are dropped one at a time,
+    /// Alt first and Ctrl last, down to the binding without modifiers. This
+    /// way Ctrl+Shift+Up still moves by the Ctrl+Up amount.
+    pub fn get(&self, keycode: Keycode, keymod: Mod) -> Option<Command> {
+        const SHIFT: u8 = KeyBinding::SHIFT;
+        const CTRL:  u8 = KeyBinding::CTRL;
+        const ALT:   u8 = KeyBinding::ALT;
+        const FALLBACK_ORDER: [u8; 8] = [
+            CTRL | SHIFT | ALT,
+            CTRL | SHIFT,
+            CTRL | ALT,
+            SHIFT | ALT,
+            CTRL,
+            SHIFT,
+            ALT,
+            0,
+        ];
+
+        let modifiers = KeyBinding::from_event(keycode, keymod).modifiers();
+        FALLBACK_ORDER.iter()
+            .filter(|&&fallback| fallback & !modifiers == 0)
+            .find_map(|&fallback| self.get_exact(KeyBinding::new(keycode, fallback).into()))
+    }
+
+    #[inline]
+    pub fn get_button(&self, button: Button) -> Option<Command> {
+        self.get_exact(button.into())
+    }
+
+    /// Load the default bindings and apply the bindings from the given JSON
+    /// file on top of them.
+    ///
+    /// The file is a JSON object mapping key names (e.g. `"Shift+A"`) to
+    /// commands (e.g. `"time-backward 1"`) or `null` to unbind a key.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, crate::error::Error> {
+        let file = File::open(path)?;
+        let reader = BufReader::new(file);
+        let hotkeys = serde_json::from_reader(reader)?;
+        Ok(hotkeys)
+    }
+
+    pub fn help(&self) -> String {
+        let mut commands: Vec<(String, Command)> = Vec::new();
+        for (key, command) in &self.bindings {
+            if let Some((keys, _)) = commands.iter_mut().find(|(_, other)| other == command) {
+                keys.push_str(" or ");
+                keys.push_str(&key.to_string());
+            } else {
+                commands.push((key.to_string(), *command));
+            }
+        }
+
+        let width = commands.iter().map(|(keys, _)| keys.chars().count()).max().unwrap_or(0) + 2;
+        let mut help = String::from("Hotkeys\n=======\n");
+
+        for (keys, command) in &commands {
+            let description = command.description();
+            let mut lines = description.lines();
+            help.push_str(&format!("{keys:width$}{}\n", lines.next().unwrap_or("")));
+            for line in lines {
+                help.push_str(&format!("{:width$}{line}\n", ""));
+            }
+        }
+
+        help
+    }
+}
+
+struct HotkeysVisitor;
+
+impl<'de> Visitor<'de> for HotkeysVisitor {
+    type Value = Hotkeys;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a map of key or gamepad button names to commands or null")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where A: serde::de::MapAccess<'de> {
+        let mut hotkeys = Hotkeys::default();
+
+        while let Some(key) = map.next_key::<String>()? {
+            let key: Binding = key.parse().map_err(Error::custom)?;
+            let command: Option<String> = map.next_value()?;
+            let command = match command {
+                Some(command) => Some(command.parse().map_err(Error::custom)?),
+                None => None,
+            };
+            hotkeys.bind(key, command);
+        }
+
+        Ok(hotkeys)
+    }
+}
+
+impl<'de> serde::de::Deserialize<'de> for Hotkeys {
+    #[inline]
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where D: Deserializer<'de> {
+        deserializer.deserialize_map(HotkeysVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all_commands() -> Vec<Command> {
+        let mut commands: Vec<Command> = Hotkeys::default().bindings().iter()
+            .map(|(_, command)| *command)
+            .collect();
+        commands.extend([
+            Command::OpenFile(12),
+            Command::TimeBackward(90),
+            Command::TimeForward(0),
+            Command::MoveUp(-3),
+            Command::MoveDown(0),
+            Command::MoveLeft(100),
+            Command::MoveRight(7),
+        ]);
+        commands
+    }
+
+    #[test]
+    fn command_round_trip() {
+        for command in all_commands() {
+            let name = command.to_string();
+            assert_eq!(name.parse::<Command>(), Ok(command), "{name}");
+        }
+        assert_eq!("  move-up   5 ".parse::<Command>(), Ok(Command::MoveUp(5)));
+    }
+
+    #[test]
+    fn illegal_commands() {
+        for value in ["", "bogus", "quit 1", "move-up", "move-up x", "open-file 0", "time-forward 1 2", "screenshot jpeg"] {
+            assert!(value.parse::<Command>().is_err(), "{value:?}");
+        }
+    }
+
+    #[test]
+    fn key_binding_round_trip() {
+        for (binding, _) in Hotkeys::default().bindings() {
+            let name = binding.to_string();
+            assert_eq!(name.parse::<Binding>(), Ok(*binding), "{name}");
+        }
+
+        let ctrl_shift_alt = KeyBinding::new(Keycode::A, KeyBinding::CTRL | KeyBinding::SHIFT | KeyBinding::ALT);
+        assert_eq!(ctrl_shift_alt.to_string(), "Ctrl+Alt+Shift+A");
+        assert_eq!("Ctrl+Alt+Shift+A".parse::<KeyBinding>(), Ok(ctrl_shift_alt));
+    }
+
+    #[test]
+    fn parse_key_binding() {
+        assert_eq!("shift + control+a".parse::<KeyBinding>(), Ok(KeyBinding::new(Keycode::A, KeyBinding::SHIFT | KeyBinding::CTRL)));
+        assert_eq!("Ctrl++".parse::<KeyBinding>(), Ok(KeyBinding::new(Keycode::PLUS, KeyBinding::CTRL)));
+        assert_eq!("Keypad +".parse::<KeyBinding>(), Ok(KeyBinding::new(Keycode::KP_PLUS, 0)));
+        assert_eq!("gamepad dpup".parse::<Binding>(), Ok(Binding::Button(Button::DPadUp)));
+        assert!("Hyper+A".parse::<KeyBinding>().is_err());
+        assert!("Gamepad bogus".parse::<Binding>().is_err());
+    }
+
+    #[test]
+    fn load_json() {
+        let hotkeys: Hotkeys = serde_json::from_str(r#"{
+            "Q": null,
+            "Ctrl+Q": "quit",
+            "A": "time-backward 30",
+            "Gamepad a": "next-file"
+        }"#).unwrap();
+
+        assert_eq!(hotkeys.get_exact(KeyBinding::new(Keycode::Q, 0).into()), None);
+        assert_eq!(hotkeys.get_exact(KeyBinding::new(Keycode::Q, KeyBinding::CTRL).into()), Some(Command::Quit));
+        assert_eq!(hotkeys.get_exact(KeyBinding::new(Keycode::A, 0).into()), Some(Command::TimeBackward(30)));
+        assert_eq!(hotkeys.get_button(Button::A), Some(Command::NextFile));
+
+        // untouched defaults stay
+        assert_eq!(hotkeys.get_exact(KeyBinding::new(Keycode::A, KeyBinding::SHIFT).into()), Some(Command::TimeBackward(1)));
+        assert_eq!(hotkeys.get_exact(KeyBinding::new(Keycode::ESCAPE, 0).into()), Some(Command::Exit));
+
+        assert!(serde_json::from_str::<Hotkeys>(r#"{"Q": "bogus"}"#).is_err());
+        assert!(serde_json::from_str::<Hotkeys>(r#"{"Bogus Key": "quit"}"#).is_err());
+    }
+
+    #[test]
+    fn modifier_fallback() {
+        let hotkeys = Hotkeys::default();
+        let ctrl_shift = Mod::LCTRLMOD | Mod::RSHIFTMOD;
+
+        assert_eq!(hotkeys.get(Keycode::UP, Mod::NOMOD), Some(Command::MoveUp(1)));
+        assert_eq!(hotkeys.get(Keycode::UP, ctrl_shift), Some(Command::MoveUp(10)));
+        assert_eq!(hotkeys.get(Keycode::RIGHT, ctrl_shift | Mod::LALTMOD), Some(Command::MoveRight(10)));
+        assert_eq!(hotkeys.get(Keycode::HOME, ctrl_shift), Some(Command::MoveToTop));
+        assert_eq!(hotkeys.get(Keycode::END, ctrl_shift), Some(Command::MoveToBottom));
+        assert_eq!(hotkeys.get(Keycode::A, ctrl_shift), Some(Command::TimeBackward(1)));
+        assert_eq!(hotkeys.get(Keycode::B, Mod::LALTMOD), Some(Command::ToggleBlend));
+        assert_eq!(hotkeys.get(Keycode::G, Mod::NOMOD), None);
+    }
+}
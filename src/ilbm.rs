@@ -16,9 +16,9 @@
 
 // See: https://moddingwiki.shikadi.net/wiki/LBM_Format
 
-use std::{fmt::Display, io::{Read, Seek}, mem::MaybeUninit};
+use std::{fmt::Display, io::{Read, Seek, Write}, mem::MaybeUninit};
 
-use crate::{bitvec::BitVec, color::Rgb, image::{CycleImage, IndexedImage}, palette::{Cycle, Palette}};
+use crate::{bitvec::BitVec, color::Rgb, image::{CycleImage, IndexedImage, RgbImage}, palette::{Cycle, CycleMode, CycleRate, Palette}};
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum ErrorKind {
@@ -226,6 +226,10 @@ impl BMHD {
 pub enum FileType {
     ILBM,
     PBM,
+    /// Impulse Turbo Silver 12 bit true-color.
+    RGBN,
+    /// Impulse Turbo Silver 24 bit true-color.
+    RGB8,
 }
 
 impl Display for FileType {
@@ -234,6 +238,8 @@ impl Display for FileType {
         match self {
             FileType::ILBM => "ILBM".fmt(f),
             FileType::PBM  => "PBM".fmt(f),
+            FileType::RGBN => "RGBN".fmt(f),
+            FileType::RGB8 => "RGB8".fmt(f),
         }
     }
 }
@@ -247,6 +253,7 @@ pub struct ILBM {
     cmap: Option<CMAP>,
     crngs: Vec<CRNG>,
     ccrts: Vec<CCRT>,
+    cymds: Vec<CYMD>,
 }
 
 impl ILBM {
@@ -287,6 +294,18 @@ impl ILBM {
         &self.ccrts
     }
 
+    #[inline]
+    pub fn cymds(&self) -> &[CYMD] {
+        &self.cymds
+    }
+
+    /// Deep ILBM, RGBN or RGB8. The body contains RGB24 pixels instead of
+    /// palette indices.
+    #[inline]
+    pub fn is_true_color(&self) -> bool {
+        matches!(self.file_type, FileType::RGBN | FileType::RGB8) || self.header.num_planes() > 8
+    }
+
     pub fn can_read<R>(reader: &mut R) -> bool
     where R: Read + Seek {
         let mut fourcc = [0u8; 4];
@@ -310,7 +329,7 @@ impl ILBM {
             return false;
         }
 
-        if fourcc != *b"ILBM" && fourcc != *b"PBM " {
+        if !matches!(&fourcc, b"ILBM" | b"PBM " | b"RGBN" | b"RGB8") {
             return false;
         }
 
@@ -341,6 +360,12 @@ impl ILBM {
             b"PBM " => {
                 file_type = FileType::PBM;
             }
+            b"RGBN" => {
+                file_type = FileType::RGBN;
+            }
+            b"RGB8" => {
+                file_type = FileType::RGB8;
+            }
             _ => {
                 return Err(Error::new(ErrorKind::UnsupportedFileFormat,
                     format!("unsupported file format: {:?} {:?}", &fourcc, String::from_utf8_lossy(&fourcc))));
@@ -352,6 +377,7 @@ impl ILBM {
         let mut cmap = None;
         let mut crngs = Vec::new();
         let mut ccrts = Vec::new();
+        let mut cymds = Vec::new();
         let mut camg = None;
 
         // eprintln!("type: {file_type}");
@@ -382,6 +408,9 @@ impl ILBM {
                 b"CCRT" => {
                     ccrts.push(CCRT::read(reader, chunk_len)?);
                 }
+                b"CYMD" => {
+                    cymds.push(CYMD::read(reader, chunk_len)?);
+                }
                 b"CAMG" => {
                     camg = Some(CAMG::read(reader, chunk_len)?);
                     // eprintln!("{:?}", camg.as_ref().unwrap());
@@ -437,6 +466,7 @@ impl ILBM {
             cmap,
             crngs,
             ccrts,
+            cymds,
         })
     }
 
@@ -461,6 +491,7 @@ impl ILBM {
     }
 }
 
+/// Palette indices, or RGB24 pixels for true-color images.
 #[derive(Debug)]
 pub struct BODY {
     pixels: Vec<u8>,
@@ -481,25 +512,33 @@ impl BODY {
     pub fn read<R>(reader: &mut R, chunk_len: u32, file_type: FileType, header: &BMHD) -> Result<Self>
     where R: Read + Seek {
         let num_planes = header.num_planes() as usize;
-        match num_planes {
-            1 | 4 | 8 => {}
+        match (file_type, num_planes) {
+            (FileType::ILBM, 1..=8 | 24 | 32) | (FileType::PBM, 1..=8) => {}
+            (FileType::RGBN | FileType::RGB8, _) => {
+                return Self::read_rgbn(reader, chunk_len, file_type, header);
+            }
             _ => {
-                if file_type != FileType::ILBM || num_planes > 8 {
-                    return Err(Error::new(ErrorKind::BrokenFile,
-                        format!("unsupported number of bit planes: {num_planes}")));
-                }
+                return Err(Error::new(ErrorKind::BrokenFile,
+                    format!("unsupported number of bit planes: {num_planes}")));
             }
         }
         // eprintln!("file_type: {file_type}, header: {:?}", header);
         let plane_len = (header.width() as usize + 15) / 16 * 2;
-        let mut line_len = num_planes * plane_len;
+        // ILBM rows are one bit plane after another, each padded to 16 bits.
+        // PBM rows are one byte per pixel no matter the number of planes,
+        // padded to an even number of bytes. A mask plane follows either.
+        let mut line_len = match file_type {
+            FileType::PBM => (header.width() as usize + 1) & !1,
+            _ => num_planes * plane_len,
+        };
         if header.mask() == 1 {
             line_len += plane_len;
         }
         let mut line = vec![0u8; line_len].into_boxed_slice();
 
         let data_len = header.height() as usize * line_len;
-        let mut pixels = Vec::with_capacity(header.width() as usize * header.height() as usize * ((num_planes + 7) / 8));
+        let pixels_len = header.width() as usize * header.height() as usize * if num_planes > 8 { 3 } else { 1 };
+        let mut pixels = Vec::with_capacity(pixels_len);
         let mut mask = if header.mask() == 1 {
             Some(BitVec::with_capacity(header.width() as usize * header.height() as usize))
         } else {
@@ -507,67 +546,51 @@ impl BODY {
         };
 
         fn decode_line(pixels: &mut Vec<u8>, mask: &mut Option<BitVec>, line: &[u8], width: u16, plane_len: usize, num_planes: usize, file_type: FileType) {
-            match file_type {
-                FileType::ILBM => {
+            let mask_offset = match file_type {
+                FileType::ILBM | FileType::RGBN | FileType::RGB8 => {
                     for x in 0..width {
                         let byte_offset = (x / 8) as usize;
                         let bit_offset = x % 8;
-                        let mut value = 0u8;
+                        let mut value = 0u32;
                         for plane_index in 0..num_planes {
                             let byte_index = plane_len * plane_index + byte_offset;
                             let bit = (line[byte_index] >> (7 - bit_offset)) & 1;
-                            value |= bit << plane_index;
-                        }
-                        pixels.push(value);
-                    }
-                }
-                FileType::PBM => {
-                    // TODO: test 1 and 4 bits
-                    match num_planes {
-                        1 => {
-                            // XXX: don't know about the bit order!
-                            for byte in &line[..(width / 8) as usize] {
-                                pixels.extend_from_slice(&[
-                                    byte & 1,
-                                    (byte >> 1) & 1,
-                                    (byte >> 2) & 1,
-                                    (byte >> 3) & 1,
-                                    (byte >> 4) & 1,
-                                    (byte >> 5) & 1,
-                                    (byte >> 6) & 1,
-                                    (byte >> 7) & 1,
-                                ]);
-                            }
-                            let rem = width % 8;
-                            if rem > 0 {
-                                let byte = line[(width / 8) as usize];
-                                for bit_index in 0..rem {
-                                    pixels.push((byte >> bit_index) & 1);
-                                }
-                            }
+                            value |= (bit as u32) << plane_index;
                         }
-                        4 => {
-                            // XXX: don't know about the nibble order!
-                            for byte in &line[..(width / 2) as usize] {
+                        if num_planes > 8 {
+                            // deep ILBM: 8 planes each of red, green, blue and maybe alpha
+                            let [r, g, b, a] = value.to_le_bytes();
+                            if num_planes == 32 {
+                                let a = a as u32;
                                 pixels.extend_from_slice(&[
-                                    byte & 0xF,
-                                    (byte >> 4),
+                                    (r as u32 * a / 255) as u8,
+                                    (g as u32 * a / 255) as u8,
+                                    (b as u32 * a / 255) as u8,
                                 ]);
+                            } else {
+                                pixels.extend_from_slice(&[r, g, b]);
                             }
-                        }
-                        8 => {
-                            pixels.extend_from_slice(&line[..width as usize]);
-                        }
-                        _ => {
-                            panic!("unhandled num_planes values: {num_planes}");
+                        } else {
+                            pixels.push(value as u8);
                         }
                     }
+                    plane_len * num_planes
                 }
-            }
+                FileType::PBM => {
+                    let row = &line[..width as usize];
+                    if num_planes < 8 {
+                        let value_mask = (1u8 << num_planes) - 1;
+                        pixels.extend(row.iter().map(|value| value & value_mask));
+                    } else {
+                        pixels.extend_from_slice(row);
+                    }
+                    (width as usize + 1) & !1
+                }
+            };
             if let Some(mask) = mask {
-                let byte_index = plane_len * num_planes;
-                let input = &line[byte_index..];
-                mask.extend_from_bytes(input, width as usize);
+                // bit planes start with the highest bit
+                let input: Vec<u8> = line[mask_offset..].iter().map(|byte| byte.reverse_bits()).collect();
+                mask.extend_from_bytes(&input, width as usize);
             }
         }
 
@@ -592,13 +615,6 @@ impl BODY {
                 // compressed
                 let mut read_len = 0;
 
-                // XXX: Why only here and not also in uncompressed?
-                // There must be some other bug?
-                let line_len = match file_type {
-                    FileType::PBM => header.width() as usize,
-                    _ => line_len,
-                };
-
                 for _y in 0..header.height() {
                     let mut pos = 0;
                     // eprintln!(">>> row: {_y}");
@@ -663,6 +679,11 @@ impl BODY {
             2 => {
                 // VDAT compression
                 // See: https://www.atari-wiki.com/index.php?title=IFF_file_format
+                if num_planes > 8 {
+                    return Err(Error::new(ErrorKind::UnsupportedFileFormat,
+                        format!("unsupported number of bit planes for VDAT compression: {num_planes}")));
+                }
+
                 let width  = header.width()  as usize;
                 let height = header.height() as usize;
 
@@ -752,11 +773,9 @@ impl BODY {
                         let x = (word_index / height) * 16 + 8 * (byte_index & 1);
                         let y = word_index % height;
 
-                        for bit in 0..8 {
+                        // columns are padded to 16 pixels
+                        for bit in 0..8.min(width.saturating_sub(x)) {
                             let pixel_index = y * width + x + bit;
-                            if pixel_index >= pixels.len() {
-                                break;
-                            }
                             pixels[pixel_index] |= ((value >> (7 - bit)) & 1) << plane_index;
                         }
                     }
@@ -779,6 +798,74 @@ impl BODY {
             mask,
         })
     }
+
+    /// RGBN and RGB8 bodies are run length encoded pixels. Each is a 16 bit
+    /// word `RRRRGGGGBBBBNCCC` (RGBN) or a 32 bit word `RRRRRRRRGGGGGGGGBBBBBBBBNCCCCCCC`
+    /// (RGB8), where `N` is the genlock bit and `C` the repeat count. If
+    /// the count is 0 it is in the next byte, and if that is 0 as well in
+    /// the 16 bit word after that.
+    fn read_rgbn<R>(reader: &mut R, chunk_len: u32, file_type: FileType, header: &BMHD) -> Result<Self>
+    where R: Read + Seek {
+        if header.compression() != 4 {
+            return Err(Error::new(ErrorKind::UnsupportedFileFormat,
+                format!("unsupported compression flag for {file_type}: {}", header.compression())));
+        }
+
+        let size = header.width() as usize * header.height() as usize;
+        let mut pixels = Vec::with_capacity(size * 3);
+        let mut read_len = 0usize;
+
+        while pixels.len() < size * 3 && read_len < chunk_len as usize {
+            let rgb;
+            let mut count;
+            if file_type == FileType::RGBN {
+                let value = read_u16be(reader)?;
+                read_len += 2;
+                let r = (value >> 12) as u8;
+                let g = ((value >> 8) & 0xF) as u8;
+                let b = ((value >> 4) & 0xF) as u8;
+                rgb = [r * 0x11, g * 0x11, b * 0x11];
+                count = (value & 0x7) as usize;
+            } else {
+                let value = read_u32be(reader)?;
+                read_len += 4;
+                let [r, g, b, n] = value.to_be_bytes();
+                rgb = [r, g, b];
+                count = (n & 0x7F) as usize;
+            }
+
+            if count == 0 {
+                count = read_u8(reader)? as usize;
+                read_len += 1;
+                if count == 0 {
+                    count = read_u16be(reader)? as usize;
+                    read_len += 2;
+                }
+            }
+
+            let count = count.min(size - pixels.len() / 3);
+            for _ in 0..count {
+                pixels.extend_from_slice(&rgb);
+            }
+        }
+
+        if read_len > chunk_len as usize {
+            return Err(Error::new(ErrorKind::BrokenFile,
+                format!("truncated {file_type} BODY chunk: {} < {}", chunk_len, read_len)));
+        }
+
+        if read_len < chunk_len as usize {
+            reader.seek_relative((chunk_len as usize - read_len) as i64)?;
+        }
+
+        // missing pixels are black
+        pixels.resize(size * 3, 0);
+
+        Ok(Self {
+            pixels,
+            mask: None,
+        })
+    }
 }
 
 #[derive(Debug)]
@@ -986,10 +1073,90 @@ impl CCRT {
     }
 }
 
+/// Mode of a cycle that `CRNG` and `CCRT` chunks can't express. This is a
+/// private chunk of this program, so ILBM screenshots keep ping-pong and sine
+/// cycles. Other programs skip it and animate the range forward.
+#[derive(Debug)]
+pub struct CYMD {
+    low: u8,
+    high: u8,
+    mode: u8,
+}
+
+impl CYMD {
+    pub const SIZE: u32 = 4;
+
+    #[inline]
+    pub fn low(&self) -> u8 {
+        self.low
+    }
+
+    #[inline]
+    pub fn high(&self) -> u8 {
+        self.high
+    }
+
+    /// Value of the mode as in the `reverse` field of Canvas Cycle JSON files.
+    #[inline]
+    pub fn mode(&self) -> u8 {
+        self.mode
+    }
+
+    pub fn read<R>(reader: &mut R, chunk_len: u32) -> Result<Self>
+    where R: Read + Seek {
+        if chunk_len < Self::SIZE {
+            return Err(Error::new(ErrorKind::BrokenFile,
+                format!("truncated CYMD chunk: {} < {}", chunk_len, Self::SIZE)));
+        }
+
+        let low = read_u8(reader)?;
+        let high = read_u8(reader)?;
+        let mode = read_u8(reader)?;
+        let _padding = read_u8(reader)?;
+
+        if chunk_len > Self::SIZE {
+            reader.seek_relative((chunk_len - Self::SIZE).into())?;
+        }
+
+        Ok(Self {
+            low,
+            high,
+            mode,
+        })
+    }
+}
+
+impl TryFrom<ILBM> for RgbImage {
+    type Error = Error;
+
+    fn try_from(ilbm: ILBM) -> std::result::Result<Self, Self::Error> {
+        let header = ilbm.header();
+        let width  = header.width()  as u32;
+        let height = header.height() as u32;
+
+        if !ilbm.is_true_color() {
+            return Err(Error::new(ErrorKind::UnsupportedFileFormat,
+                format!("{} with {} bit planes is not a true-color image", ilbm.file_type(), header.num_planes())));
+        }
+
+        let Some(body) = ilbm.body() else {
+            return Ok(RgbImage::new(width, height));
+        };
+
+        RgbImage::from_buffer(width, height, body.pixels().into())
+            .ok_or_else(|| Error::new(ErrorKind::BrokenFile, "image buffer is too small for given width/height"))
+    }
+}
+
 impl TryFrom<ILBM> for CycleImage {
     type Error = Error;
 
     fn try_from(ilbm: ILBM) -> std::result::Result<Self, Self::Error> {
+        if ilbm.is_true_color() {
+            return Err(Error::new(ErrorKind::UnsupportedFileFormat,
+                format!("{} with {} bit planes is a true-color image", ilbm.file_type(), ilbm.header().num_planes())));
+        }
+
         // convert ILBM to LivingWorld
         let header = ilbm.header();
         let width  = header.width()  as u32;
@@ -1016,6 +1183,9 @@ impl TryFrom<ILBM> for CycleImage {
             if crng.low() < crng.high() && crng.rate() > 0 {
                 let flags = crng.flags();
                 if flags & 1 != 0 {
+                    // Only active (bit 0) and reverse (bit 1) are documented
+                    // and no paint program is known to store other modes in
+                    // the flags. Other modes are read from CYMD chunks.
                     if flags > 3 {
                         eprintln!("Warning: Unsupported CRNG flags: {crng:?}");
                     }
@@ -1024,7 +1194,7 @@ impl TryFrom<ILBM> for CycleImage {
                         crng.low(),
                         crng.high(),
                         crng.rate().into(),
-                        flags & 2 != 0
+                        if flags & 2 != 0 { CycleMode::Reverse } else { CycleMode::Forward }
                     ));
                 } else if flags != 0 {
                     eprintln!("Warning: Unsupported CRNG flags: {crng:?}");
@@ -1034,40 +1204,176 @@ impl TryFrom<ILBM> for CycleImage {
 
         for ccrt in ilbm.ccrts() {
             if ccrt.direction() != 0 && ccrt.low() < ccrt.high() {
-                let usec = ccrt.delay_sec() as u64 * 1000_000 + ccrt.delay_usec() as u64;
-
-                // 1s / 60 = 16384x
-                // 1s * 1000_000 = ?x
-
-                // 16384s / 60 = 1x
-
-                // Is this correct?
+                // CCRT (Graphicraft) stores the "# seconds between changing
+                // colors plus # microseconds between changing colors".
                 // See: https://moddingwiki.shikadi.net/wiki/LBM_Format#CCRT:_Colour_cycling
-
-                // XXX: This is just a value I've came up with so I get the same rate in NightFlight.iff as in NightFlight.ilbm
-                //      No idea if this is any correct? Also had to reverse the direction than what I thought it should be.
-                let rate = usec * 8903 / 1000_000;
-                //eprintln!("sec: {}, usec: {} -> rate: {}", ccrt.delay_sec(), ccrt.delay_usec(), rate);
-
-                if ccrt.direction() < -1 || ccrt.direction() > 1 {
-                    eprintln!("Warning: Unsupported CCRT direction: {ccrt:?}");
-                }
-
-                if rate > 0 {
-                    cycles.push(Cycle::new(
+                let usec = ccrt.delay_sec() as u64 * 1_000_000 + ccrt.delay_usec() as u64;
+
+                // The direction is documented as "1 = cycle forward (1, 2, 3).
+                // -1 = cycle backwards (3, 2, 1)": each register shows the
+                // colors of the following registers one after another, so
+                // the colors move towards the lower end of the range. That is
+                // reverse in CRNG terms. NightFlight.iff (CCRT) matches
+                // NightFlight.ilbm (CRNG) this way.
+                if usec > 0 {
+                    cycles.push(Cycle::with_delay(
                         ccrt.low(),
                         ccrt.high(),
-                        rate as u32,
-                        ccrt.direction() > 0,
+                        usec,
+                        if ccrt.direction() > 0 { CycleMode::Reverse } else { CycleMode::Forward },
                     ));
                 }
             }
         }
 
+        for cymd in ilbm.cymds() {
+            let Some(mode) = CycleMode::from_value(cymd.mode().into()) else {
+                eprintln!("Warning: Unsupported cycle mode: {cymd:?}");
+                continue;
+            };
+
+            // the CRNG or CCRT chunk of the range was written as forward cycle
+            if let Some(cycle) = cycles.iter_mut().find(|cycle|
+                    cycle.low() == cymd.low() && cycle.high() == cymd.high() && cycle.mode() == CycleMode::Forward) {
+                *cycle = cycle.with_mode(mode);
+            }
+        }
+
         Ok(CycleImage::new(None, indexed_image, cycles.into()))
     }
 }
 
+/// Write an image as a ByteRun1 compressed ILBM file with 8 bit planes.
+/// The cycles are written as `CRNG` or `CCRT` chunks, depending on how their
+/// rate is given. Both only know forward and reverse, so cycles with other
+/// modes are written as forward cycles followed by a [`CYMD`] chunk.
+pub fn write_ilbm<W>(writer: &mut W, image: &IndexedImage, cycles: &[Cycle]) -> Result<()>
+where W: Write {
+    let width  = image.width();
+    let height = image.height();
+
+    if width > u16::MAX as u32 || height > u16::MAX as u32 {
+        return Err(Error::new(ErrorKind::UnsupportedFileFormat,
+            format!("image too big for ILBM: {width} x {height}")));
+    }
+
+    let num_planes = 8;
+    let mut data = Vec::new();
+    data.extend_from_slice(b"ILBM");
+
+    let mut bmhd = Vec::with_capacity(BMHD::SIZE as usize);
+    bmhd.extend_from_slice(&(width as u16).to_be_bytes());
+    bmhd.extend_from_slice(&(height as u16).to_be_bytes());
+    bmhd.extend_from_slice(&0i16.to_be_bytes()); // x_origin
+    bmhd.extend_from_slice(&0i16.to_be_bytes()); // y_origin
+    bmhd.push(num_planes as u8);
+    bmhd.push(0); // mask
+    bmhd.push(1); // compression
+    bmhd.push(0); // flags
+    bmhd.extend_from_slice(&0u16.to_be_bytes()); // trans_color
+    bmhd.push(1); // x_aspect
+    bmhd.push(1); // y_aspect
+    bmhd.extend_from_slice(&(width as i16).to_be_bytes()); // page_width
+    bmhd.extend_from_slice(&(height as i16).to_be_bytes()); // page_height
+    write_chunk(&mut data, b"BMHD", &bmhd);
+
+    let mut cmap = Vec::with_capacity(256 * 3);
+    for Rgb(rgb) in image.palette().0.iter() {
+        cmap.extend_from_slice(rgb);
+    }
+    write_chunk(&mut data, b"CMAP", &cmap);
+
+    for cycle in cycles {
+        let reverse = cycle.mode() == CycleMode::Reverse;
+        match cycle.rate() {
+            CycleRate::Rate(rate) => {
+                let mut crng = Vec::with_capacity(CRNG::SIZE as usize);
+                let flags: u16 = if reverse { 3 } else { 1 };
+                crng.extend_from_slice(&0u16.to_be_bytes()); // padding
+                crng.extend_from_slice(&(rate.min(u16::MAX as u32) as u16).to_be_bytes());
+                crng.extend_from_slice(&flags.to_be_bytes());
+                crng.push(cycle.low());
+                crng.push(cycle.high());
+                write_chunk(&mut data, b"CRNG", &crng);
+            }
+            CycleRate::Delay(usec) => {
+                let mut ccrt = Vec::with_capacity(CCRT::SIZE as usize);
+                let direction: i16 = if reverse { 1 } else { -1 };
+                let delay_sec = (usec / 1_000_000).min(u32::MAX as u64) as u32;
+                let delay_usec = (usec % 1_000_000) as u32;
+                ccrt.extend_from_slice(&direction.to_be_bytes());
+                ccrt.push(cycle.low());
+                ccrt.push(cycle.high());
+                ccrt.extend_from_slice(&delay_sec.to_be_bytes());
+                ccrt.extend_from_slice(&delay_usec.to_be_bytes());
+                ccrt.extend_from_slice(&0u16.to_be_bytes()); // padding
+                write_chunk(&mut data, b"CCRT", &ccrt);
+            }
+        }
+
+        if !matches!(cycle.mode(), CycleMode::Forward | CycleMode::Reverse) {
+            let cymd = [cycle.low(), cycle.high(), cycle.mode().value() as u8, 0];
+            write_chunk(&mut data, b"CYMD", &cymd);
+        }
+    }
+
+    let plane_len = (width as usize).div_ceil(16) * 2;
+    let mut plane = vec![0u8; plane_len];
+    let mut body = Vec::new();
+    for y in 0..height {
+        for plane_index in 0..num_planes {
+            plane.fill(0);
+            for x in 0..width {
+                let bit = (image.get_index(x, y) >> plane_index) & 1;
+                plane[x as usize / 8] |= bit << (7 - x % 8);
+            }
+            byte_run1(&plane, &mut body);
+        }
+    }
+    write_chunk(&mut data, b"BODY", &body);
+
+    writer.write_all(b"FORM")?;
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(&data)?;
+
+    Ok(())
+}
+
+fn write_chunk(data: &mut Vec<u8>, fourcc: &[u8; 4], chunk: &[u8]) {
+    data.extend_from_slice(fourcc);
+    data.extend_from_slice(&(chunk.len() as u32).to_be_bytes());
+    data.extend_from_slice(chunk);
+    if chunk.len() & 1 != 0 {
+        data.push(0);
+    }
+}
+
+pub fn byte_run1(input: &[u8], output: &mut Vec<u8>) {
+    let mut index = 0;
+    while index < input.len() {
+        let value = input[index];
+        let mut run_len = 1;
+        while run_len < 128 && index + run_len < input.len() && input[index + run_len] == value {
+            run_len += 1;
+        }
+
+        if run_len > 1 {
+            output.push((257 - run_len) as u8);
+            output.push(value);
+            index += run_len;
+        } else {
+            let start = index;
+            index += 1;
+            while index < input.len() && index - start < 128 &&
+                  !(index + 1 < input.len() && input[index] == input[index + 1]) {
+                index += 1;
+            }
+            output.push((index - start - 1) as u8);
+            output.extend_from_slice(&input[start..index]);
+        }
+    }
+}
+
 #[inline]
 pub fn read_u8(reader: &mut impl Read) -> Result<u8> {
     let mut buf = MaybeUninit::<[u8; 1]>::uninit();
@@ -1115,3 +1421,434 @@ pub fn read_i16be(reader: &mut impl Read) -> Result<i16> {
     let buf = unsafe { buf.assume_init_ref() };
     Ok(i16::from_be_bytes(*buf))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn chunk(fourcc: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = Vec::new();
+        write_chunk(&mut chunk, fourcc, data);
+        chunk
+    }
+
+    fn bmhd(width: u16, height: u16, num_planes: u8, mask: u8, compression: u8) -> Vec<u8> {
+        let mut bmhd = Vec::new();
+        bmhd.extend_from_slice(&width.to_be_bytes());
+        bmhd.extend_from_slice(&height.to_be_bytes());
+        bmhd.extend_from_slice(&[0, 0, 0, 0, num_planes, mask, compression, 0, 0, 0, 1, 1]);
+        bmhd.extend_from_slice(&width.to_be_bytes());
+        bmhd.extend_from_slice(&height.to_be_bytes());
+        chunk(b"BMHD", &bmhd)
+    }
+
+    fn form(form_type: &[u8; 4], chunks: &[Vec<u8>]) -> Vec<u8> {
+        let data: Vec<u8> = form_type.iter().copied().chain(chunks.concat()).collect();
+        let mut file = b"FORM".to_vec();
+        file.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        file.extend_from_slice(&data);
+        file
+    }
+
+    fn read_cycle_image(file: &[u8]) -> CycleImage {
+        let ilbm = ILBM::read(&mut Cursor::new(file)).unwrap();
+        ilbm.try_into().unwrap()
+    }
+
+    /// 16 x 1 pixels, 16 colors, all pixels 0 and the given cycle chunks.
+    fn cycles_ilbm(cycle_chunks: &[Vec<u8>]) -> CycleImage {
+        let cmap: Vec<u8> = (0..16u8).flat_map(|index| [index * 16, 0, 255 - index]).collect();
+        let mut chunks = vec![bmhd(16, 1, 4, 0, 0), chunk(b"CMAP", &cmap), chunk(b"BODY", &[0; 8])];
+        chunks.extend_from_slice(cycle_chunks);
+        read_cycle_image(&form(b"ILBM", &chunks))
+    }
+
+    fn ccrt(direction: i16, low: u8, high: u8, delay_sec: u32, delay_usec: u32) -> Vec<u8> {
+        let mut ccrt = direction.to_be_bytes().to_vec();
+        ccrt.extend_from_slice(&[low, high]);
+        ccrt.extend_from_slice(&delay_sec.to_be_bytes());
+        ccrt.extend_from_slice(&delay_usec.to_be_bytes());
+        ccrt.extend_from_slice(&[0, 0]);
+        chunk(b"CCRT", &ccrt)
+    }
+
+    fn palette_at(image: &CycleImage, now: f64) -> Palette {
+        let mut palette = image.palette().clone();
+        palette.apply_cycles(image.cycles(), now);
+        palette
+    }
+
+    #[test]
+    fn ccrt_forward_steps_after_delay() {
+        // one step every half second, colors move towards lower registers
+        let image = cycles_ilbm(&[ccrt(1, 2, 5, 0, 500_000)]);
+        let colors = image.palette().clone();
+        assert_eq!(image.cycles().len(), 1);
+        assert_eq!(image.cycles()[0].rate(), CycleRate::Delay(500_000));
+
+        assert_eq!(palette_at(&image, 0.0), colors);
+        assert_eq!(palette_at(&image, 0.49), colors);
+
+        let palette = palette_at(&image, 0.51);
+        assert_eq!([palette[2], palette[3], palette[4], palette[5]], [colors[3], colors[4], colors[5], colors[2]]);
+        assert_eq!(palette[1], colors[1]);
+        assert_eq!(palette[6], colors[6]);
+
+        let palette = palette_at(&image, 1.01);
+        assert_eq!([palette[2], palette[3], palette[4], palette[5]], [colors[4], colors[5], colors[2], colors[3]]);
+
+        // 4 colors, back where it started after 4 steps
+        assert_eq!(palette_at(&image, 2.01), colors);
+    }
+
+    #[test]
+    fn ccrt_backward_with_seconds() {
+        // one step every 2.25 seconds, colors move towards higher registers
+        let image = cycles_ilbm(&[ccrt(-1, 8, 10, 2, 250_000)]);
+        let colors = image.palette().clone();
+
+        assert_eq!(palette_at(&image, 2.24), colors);
+
+        let palette = palette_at(&image, 2.26);
+        assert_eq!([palette[8], palette[9], palette[10]], [colors[10], colors[8], colors[9]]);
+
+        let palette = palette_at(&image, 4.51);
+        assert_eq!([palette[8], palette[9], palette[10]], [colors[9], colors[10], colors[8]]);
+    }
+
+    fn crng(rate: u16, flags: u16, low: u8, high: u8) -> Vec<u8> {
+        let mut crng = vec![0, 0];
+        crng.extend_from_slice(&rate.to_be_bytes());
+        crng.extend_from_slice(&flags.to_be_bytes());
+        crng.extend_from_slice(&[low, high]);
+        chunk(b"CRNG", &crng)
+    }
+
+    #[test]
+    fn crng_documented_flags_only() {
+        let image = cycles_ilbm(&[
+            crng(2800, 1, 1, 4),
+            crng(2800, 3, 5, 8),
+            // undocumented bits are ignored, other modes need a CYMD chunk
+            crng(2800, 0b1101, 9, 12),
+            // not active
+            crng(2800, 2, 13, 15),
+        ]);
+        let modes: Vec<_> = image.cycles().iter().map(Cycle::mode).collect();
+        assert_eq!(modes, [CycleMode::Forward, CycleMode::Reverse, CycleMode::Forward]);
+    }
+
+    #[test]
+    fn cymd_sets_mode() {
+        let image = cycles_ilbm(&[
+            crng(2800, 1, 1, 4),
+            chunk(b"CYMD", &[1, 4, 3, 0]),
+            ccrt(-1, 5, 8, 0, 250_000),
+            chunk(b"CYMD", &[5, 8, 5, 0]),
+            // only changes forward cycles of the same range
+            crng(2800, 3, 9, 12),
+            chunk(b"CYMD", &[9, 12, 4, 0]),
+            crng(2800, 1, 13, 15),
+            chunk(b"CYMD", &[13, 14, 4, 0]),
+        ]);
+        let modes: Vec<_> = image.cycles().iter().map(|cycle| (cycle.low(), cycle.mode())).collect();
+        assert_eq!(modes, [
+            (1, CycleMode::PingPong),
+            (9, CycleMode::Reverse),
+            (13, CycleMode::Forward),
+            (5, CycleMode::SineFull),
+        ]);
+    }
+
+    #[test]
+    fn write_modes() {
+        let image = IndexedImage::new(3, 2, Palette::default());
+        let cycles = [
+            Cycle::new(1, 4, 2800, CycleMode::Reverse),
+            Cycle::new(5, 8, 2800, CycleMode::PingPong),
+            Cycle::with_delay(9, 12, 250_000, CycleMode::Reverse),
+            Cycle::with_delay(13, 15, 250_000, CycleMode::SineFull),
+        ];
+        let mut file = Vec::new();
+        write_ilbm(&mut file, &image, &cycles).unwrap();
+
+        // other programs see forward cycles
+        let ilbm = ILBM::read(&mut Cursor::new(&file)).unwrap();
+        let flags: Vec<_> = ilbm.crngs().iter().map(CRNG::flags).collect();
+        assert_eq!(flags, [3, 1]);
+        let directions: Vec<_> = ilbm.ccrts().iter().map(CCRT::direction).collect();
+        assert_eq!(directions, [1, -1]);
+        let modes: Vec<_> = ilbm.cymds().iter().map(|cymd| (cymd.low(), cymd.mode())).collect();
+        assert_eq!(modes, [(5, 3), (13, 5)]);
+
+        let image: CycleImage = ilbm.try_into().unwrap();
+        let cycles: Vec<_> = image.cycles().iter().map(|cycle| (cycle.low(), cycle.rate(), cycle.mode())).collect();
+        assert_eq!(cycles, [
+            (1, CycleRate::Rate(2800), CycleMode::Reverse),
+            (5, CycleRate::Rate(2800), CycleMode::PingPong),
+            (9, CycleRate::Delay(250_000), CycleMode::Reverse),
+            (13, CycleRate::Delay(250_000), CycleMode::SineFull),
+        ]);
+    }
+
+    #[test]
+    fn write_round_trip() {
+        // odd width, so the rows of the bit planes are padded
+        let (width, height) = (19, 15);
+        let mut palette = Palette::default();
+        for index in 0..=255u8 {
+            palette[index] = Rgb([index, index.wrapping_mul(7), 255 - index]);
+        }
+        let mut image = IndexedImage::new(width, height, palette);
+        for y in 0..height {
+            for x in 0..width {
+                image.set_index(x, y, (x * 31 + y * 17 + x * y) as u8);
+            }
+        }
+        let cycles = [
+            Cycle::new(16, 31, 2800, CycleMode::Forward),
+            Cycle::new(32, 39, 1400, CycleMode::Reverse),
+            Cycle::with_delay(40, 47, 1_250_000, CycleMode::Forward),
+            Cycle::new(48, 63, 700, CycleMode::Sine),
+        ];
+
+        let mut file = Vec::new();
+        write_ilbm(&mut file, &image, &cycles).unwrap();
+        let read_image = read_cycle_image(&file);
+
+        assert_eq!(read_image.indexed_image().size(), (width, height));
+        assert_eq!(read_image.indexed_image().data(), image.data());
+        assert_eq!(read_image.palette(), image.palette());
+
+        let mut read_cycles = read_image.cycles().to_vec();
+        read_cycles.sort_by_key(Cycle::low);
+        assert_eq!(read_cycles, cycles);
+    }
+
+    fn test_pixel(x: usize, y: usize, num_planes: u8) -> u8 {
+        ((x * 7 + y * 13 + x * y) % (1 << num_planes)) as u8
+    }
+
+    fn test_mask(x: usize, y: usize) -> bool {
+        !(x + y).is_multiple_of(3)
+    }
+
+    /// One bit plane row, padded to 16 bits, highest bit first.
+    fn plane_row(width: usize, bit: impl Fn(usize) -> bool) -> Vec<u8> {
+        let mut row = vec![0u8; width.div_ceil(16) * 2];
+        for x in 0..width {
+            if bit(x) {
+                row[x / 8] |= 0x80 >> (x % 8);
+            }
+        }
+        row
+    }
+
+    /// BODY in the given format, compressed one bit plane row (ILBM) or one
+    /// pixel row (PBM) at a time.
+    fn test_body(file_type: FileType, width: usize, height: usize, num_planes: u8, mask: bool, compression: u8) -> Vec<u8> {
+        let mut body = Vec::new();
+        for y in 0..height {
+            let mut rows = Vec::new();
+            if file_type == FileType::PBM {
+                let mut row: Vec<u8> = (0..width).map(|x| test_pixel(x, y, num_planes)).collect();
+                if !width.is_multiple_of(2) {
+                    row.push(0);
+                }
+                rows.push(row);
+            } else {
+                for plane_index in 0..num_planes {
+                    rows.push(plane_row(width, |x| (test_pixel(x, y, num_planes) >> plane_index) & 1 != 0));
+                }
+            }
+            if mask {
+                rows.push(plane_row(width, |x| test_mask(x, y)));
+            }
+
+            for row in rows {
+                if compression == 1 {
+                    byte_run1(&row, &mut body);
+                } else {
+                    body.extend_from_slice(&row);
+                }
+            }
+        }
+        body
+    }
+
+    #[test]
+    fn body_all_depths_widths_and_masks() {
+        for (file_type, form_type) in [(FileType::ILBM, b"ILBM"), (FileType::PBM, b"PBM ")] {
+            for width in [1, 7, 15, 16, 17, 33] {
+                for num_planes in 1..=8 {
+                    for mask in [false, true] {
+                        for compression in [0, 1] {
+                            let height = 5;
+                            let body = test_body(file_type, width, height, num_planes, mask, compression);
+                            let file = form(form_type, &[
+                                bmhd(width as u16, height as u16, num_planes, mask as u8, compression),
+                                chunk(b"BODY", &body),
+                            ]);
+                            let case = format!("{file_type} {width} x {height}, {num_planes} planes, mask: {mask}, compression: {compression}");
+
+                            let ilbm = ILBM::read(&mut Cursor::new(&file)).expect(&case);
+                            let body = ilbm.body().expect(&case);
+                            let expected: Vec<u8> = (0..height)
+                                .flat_map(|y| (0..width).map(move |x| test_pixel(x, y, num_planes)))
+                                .collect();
+                            assert_eq!(body.pixels(), &expected[..], "{case}");
+
+                            if mask {
+                                let body_mask = body.mask().expect(&case);
+                                assert_eq!(body_mask.len(), width * height, "{case}");
+                                for y in 0..height {
+                                    for x in 0..width {
+                                        assert_eq!(body_mask.get(y * width + x), Some(test_mask(x, y)), "{case}, x: {x}, y: {y}");
+                                    }
+                                }
+                            } else {
+                                assert!(body.mask().is_none(), "{case}");
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn body_vdat_width_not_divisible_by_16() {
+        let (width, height, num_planes): (usize, usize, u8) = (20, 3, 2);
+        let mut body = Vec::new();
+        for plane_index in 0..num_planes {
+            // 16 pixel wide columns of words, top to bottom
+            let mut words = Vec::new();
+            for column in 0..width.div_ceil(16) {
+                for y in 0..height {
+                    let row = plane_row(width, |x| (test_pixel(x, y, num_planes) >> plane_index) & 1 != 0);
+                    words.extend_from_slice(&row[column * 2..column * 2 + 2]);
+                }
+            }
+            let word_count = words.len() / 2;
+
+            // a copy command (-1) per word
+            let mut vdat = ((2 + word_count) as u16).to_be_bytes().to_vec();
+            vdat.extend(std::iter::repeat_n(0xFF, word_count));
+            vdat.extend_from_slice(&words);
+            body.extend_from_slice(&chunk(b"VDAT", &vdat));
+        }
+
+        let file = form(b"ILBM", &[
+            bmhd(width as u16, height as u16, num_planes, 0, 2),
+            chunk(b"BODY", &body),
+        ]);
+        let ilbm = ILBM::read(&mut Cursor::new(&file)).unwrap();
+        let expected: Vec<u8> = (0..height)
+            .flat_map(|y| (0..width).map(move |x| test_pixel(x, y, num_planes)))
+            .collect();
+        assert_eq!(ilbm.body().unwrap().pixels(), &expected[..]);
+    }
+
+    fn deep_pixel(x: usize, y: usize) -> [u8; 4] {
+        [(x * 37 + y) as u8, (y * 53 + x * x) as u8, (x * y * 11) as u8, (x * 17 + y * 29) as u8]
+    }
+
+    #[test]
+    fn body_deep() {
+        let (width, height) = (21, 3);
+        for num_planes in [24, 32] {
+            for compression in [0, 1] {
+                let mut body = Vec::new();
+                for y in 0..height {
+                    for plane_index in 0..num_planes {
+                        let row = plane_row(width, |x| (u32::from_le_bytes(deep_pixel(x, y)) >> plane_index) & 1 != 0);
+                        if compression == 1 {
+                            byte_run1(&row, &mut body);
+                        } else {
+                            body.extend_from_slice(&row);
+                        }
+                    }
+                }
+
+                let file = form(b"ILBM", &[
+                    bmhd(width as u16, height as u16, num_planes, 0, compression),
+                    chunk(b"BODY", &body),
+                ]);
+                let case = format!("{num_planes} planes, compression: {compression}");
+                let ilbm = ILBM::read(&mut Cursor::new(&file)).expect(&case);
+                assert!(ilbm.is_true_color(), "{case}");
+
+                let expected: Vec<u8> = (0..height)
+                    .flat_map(|y| (0..width).map(move |x| deep_pixel(x, y)))
+                    .flat_map(|[r, g, b, a]| if num_planes == 32 {
+                        // premultiplied alpha
+                        let a = a as u32;
+                        [(r as u32 * a / 255) as u8, (g as u32 * a / 255) as u8, (b as u32 * a / 255) as u8]
+                    } else {
+                        [r, g, b]
+                    })
+                    .collect();
+                assert_eq!(ilbm.body().expect(&case).pixels(), &expected[..], "{case}");
+            }
+        }
+    }
+
+    #[test]
+    fn body_rgbn_repeat_counts() {
+        // 7 x 3 pixels: repeat count in the word, in the next byte, in the
+        // next word, and one run that is longer than the rest of the image
+        let mut body = Vec::new();
+        body.extend_from_slice(&0xF0_03u16.to_be_bytes());
+        body.extend_from_slice(&0x0F_00u16.to_be_bytes());
+        body.push(5);
+        body.extend_from_slice(&0x00_F8u16.to_be_bytes());
+        body.push(0);
+        body.extend_from_slice(&10u16.to_be_bytes());
+        body.extend_from_slice(&0x84_27u16.to_be_bytes());
+
+        let file = form(b"RGBN", &[bmhd(7, 3, 13, 0, 4), chunk(b"BODY", &body)]);
+        let ilbm = ILBM::read(&mut Cursor::new(&file)).unwrap();
+        assert!(ilbm.is_true_color());
+
+        let mut expected = Vec::new();
+        for (rgb, count) in [([0xFF, 0, 0], 3), ([0, 0xFF, 0], 5), ([0, 0, 0xFF], 10), ([0x88, 0x44, 0x22], 3)] {
+            for _ in 0..count {
+                expected.extend_from_slice(&rgb);
+            }
+        }
+        assert_eq!(ilbm.body().unwrap().pixels(), &expected[..]);
+    }
+
+    #[test]
+    fn body_rgb8_repeat_counts() {
+        // the genlock bit is not part of the repeat count
+        let mut body = Vec::new();
+        body.extend_from_slice(&[10, 20, 30, 0x82]);
+        body.extend_from_slice(&[40, 50, 60, 0]);
+        body.push(130);
+        body.extend_from_slice(&[70, 80, 90, 0x80]);
+        body.push(0);
+        body.extend_from_slice(&300u16.to_be_bytes());
+
+        let file = form(b"RGB8", &[bmhd(30, 20, 25, 0, 4), chunk(b"BODY", &body)]);
+        let ilbm = ILBM::read(&mut Cursor::new(&file)).unwrap();
+
+        let mut expected = Vec::new();
+        // the last 600 - 432 pixels are missing and stay black
+        for (rgb, count) in [([10, 20, 30], 2), ([40, 50, 60], 130), ([70, 80, 90], 300), ([0, 0, 0], 600 - 432)] {
+            for _ in 0..count {
+                expected.extend_from_slice(&rgb);
+            }
+        }
+        assert_eq!(ilbm.body().unwrap().pixels(), &expected[..]);
+    }
+
+    #[test]
+    fn ccrt_not_cycling() {
+        // direction 0, no delay and a single color range don't cycle
+        let image = cycles_ilbm(&[ccrt(0, 2, 5, 0, 500_000), ccrt(1, 2, 5, 0, 0), ccrt(1, 3, 3, 0, 500_000)]);
+        assert!(image.cycles().is_empty());
+    }
+}
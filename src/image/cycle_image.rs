@@ -14,7 +14,7 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use crate::palette::{Cycle, Palette};
+use crate::palette::{cycles_period, Cycle, CyclePeriod, Palette, PaletteLoop};
 
 use super::IndexedImage;
 
@@ -50,6 +50,20 @@ impl CycleImage {
         &self.cycles
     }
 
+    /// Exact time after which the palette repeats when not blending, or
+    /// `None` if nothing cycles.
+    #[inline]
+    pub fn cycle_period(&self) -> Option<CyclePeriod> {
+        cycles_period(&self.cycles)
+    }
+
+    /// Precompute all distinct palette states of one [`CycleImage::cycle_period()`].
+    /// Returns `None` if nothing cycles or there are more than `max_states`.
+    #[inline]
+    pub fn palette_loop(&self, max_states: usize) -> Option<PaletteLoop> {
+        PaletteLoop::new(self.palette(), &self.cycles, max_states)
+    }
+
     #[inline]
     pub fn width(&self) -> u32 {
         self.indexed_image.width()
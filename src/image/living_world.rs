@@ -15,6 +15,10 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use super::CycleImage;
+use crate::easing::Easing;
+
+/// Length of a day in milliseconds.
+pub const DAY_DURATION: u64 = 24 * 60 * 60 * 1000;
 
 // render files from http://www.effectgames.com/demos/worlds/
 
@@ -23,13 +27,16 @@ pub struct LivingWorld {
     name: Option<String>,
     base: CycleImage,
     palettes: Box<[CycleImage]>,
+    palette_names: Box<[String]>,
     timeline: Box<[TimedEvent]>,
+    easing: Easing,
 }
 
 impl LivingWorld {
     #[inline]
-    pub fn new(name: Option<String>, base: CycleImage, palettes: Box<[CycleImage]>, timeline: Box<[TimedEvent]>) -> Self {
-        Self { name, base, palettes, timeline }
+    pub fn new(name: Option<String>, base: CycleImage, palettes: Box<[CycleImage]>, palette_names: Box<[String]>, timeline: Box<[TimedEvent]>) -> Self {
+        debug_assert_eq!(palettes.len(), palette_names.len());
+        Self { name, base, palettes, palette_names, timeline, easing: Easing::default() }
     }
 
     #[inline]
@@ -38,7 +45,9 @@ impl LivingWorld {
             name: None,
             base,
             palettes: Box::new([]),
+            palette_names: Box::new([]),
             timeline: Box::new([]),
+            easing: Easing::default(),
         }
     }
 
@@ -57,11 +66,82 @@ impl LivingWorld {
         &self.palettes
     }
 
+    /// Names of the palettes in the order they appear in the file.
+    #[inline]
+    pub fn palette_names(&self) -> &[String] {
+        &self.palette_names
+    }
+
+    #[inline]
+    pub fn palette_name(&self, index: usize) -> Option<&str> {
+        self.palette_names.get(index).map(String::as_str)
+    }
+
     #[inline]
     pub fn timeline(&self) -> &[TimedEvent] {
         &self.timeline
     }
 
+    /// Easing used for timeline events that don't specify their own.
+    #[inline]
+    pub fn easing(&self) -> Easing {
+        self.easing
+    }
+
+    #[inline]
+    pub fn set_easing(&mut self, easing: Easing) {
+        self.easing = easing;
+    }
+
+    /// Indices of the two palettes to blend between at the given time of day
+    /// in milliseconds since midnight and how far to blend from the first to
+    /// the second one. `None` if there is no timeline.
+    pub fn timeline_at(&self, time_of_day: u64) -> Option<(usize, usize, f64)> {
+        let last = self.timeline.last()?;
+        let first = self.timeline.first()?;
+
+        let mut palette1 = last.palette_index();
+        let mut palette2 = palette1;
+        let mut easing = first.easing();
+        let mut prev_time_of_day = 0;
+        let mut next_time_of_day = 0;
+
+        // TODO: binary search?
+        let mut found = false;
+        for event in &self.timeline {
+            prev_time_of_day = next_time_of_day;
+            next_time_of_day = event.time_of_day() as u64 * 1000;
+            palette1 = palette2;
+            palette2 = event.palette_index();
+            easing = event.easing();
+            if next_time_of_day > time_of_day {
+                found = true;
+                break;
+            }
+        }
+
+        if !found {
+            prev_time_of_day = next_time_of_day;
+            next_time_of_day = DAY_DURATION;
+            palette1 = palette2;
+            palette2 = first.palette_index();
+            easing = first.easing();
+        }
+
+        let current_span = next_time_of_day - prev_time_of_day;
+        let time_in_span = time_of_day - prev_time_of_day;
+        let easing = easing.unwrap_or(self.easing);
+
+        Some((palette1, palette2, easing.apply(time_in_span as f64 / current_span as f64, current_span)))
+    }
+
+    /// Same as [`LivingWorld::timeline_at()`], but returns the palettes.
+    #[inline]
+    pub fn palettes_at(&self, time_of_day: u64) -> Option<(&CycleImage, &CycleImage, f64)> {
+        let (palette1, palette2, mid) = self.timeline_at(time_of_day)?;
+        Some((&self.palettes[palette1], &self.palettes[palette2], mid))
+    }
+
     #[inline]
     pub fn into_base(self) -> CycleImage {
         self.base
@@ -81,6 +161,7 @@ impl From<CycleImage> for LivingWorld {
             value,
             Box::new([]),
             Box::new([]),
+            Box::new([]),
         )
     }
 }
@@ -97,12 +178,14 @@ pub struct TimedEvent {
     /// time of day in seconds since midnight
     time_of_day: u32,
     palette_index: usize,
+    /// easing of the transition to this event
+    easing: Option<Easing>,
 }
 
 impl TimedEvent {
     #[inline]
-    pub fn new(time_of_day: u32, palette_index: usize) -> Self {
-        Self { time_of_day, palette_index }
+    pub fn new(time_of_day: u32, palette_index: usize, easing: Option<Easing>) -> Self {
+        Self { time_of_day, palette_index, easing }
     }
 
     #[inline]
@@ -114,4 +197,9 @@ impl TimedEvent {
     pub fn palette_index(&self) -> usize {
         self.palette_index
     }
+
+    #[inline]
+    pub fn easing(&self) -> Option<Easing> {
+        self.easing
+    }
 }
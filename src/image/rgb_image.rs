@@ -0,0 +1,86 @@
+// color-cycle - render color cycle images
+// Copyright (C) 2025  Mathias Panzenböck
+// 
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+// 
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+// 
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::color::Rgb;
+
+/// True-color image that is displayed as is, without any color cycling.
+/// Pixels are stored as RGB24, row by row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RgbImage {
+    width: u32,
+    height: u32,
+    data: Box<[u8]>,
+}
+
+impl RgbImage {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            data: vec![0; width as usize * height as usize * 3].into(),
+        }
+    }
+
+    pub fn from_buffer(width: u32, height: u32, image: Box<[u8]>) -> Option<Self> {
+        let size = width as usize * height as usize * 3;
+        if image.len() < size {
+            return None;
+        }
+
+        Some(Self {
+            width,
+            height,
+            data: if image.len() > size { image[..size].into() } else { image },
+        })
+    }
+
+    #[inline]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[inline]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    #[inline]
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    #[inline]
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    #[inline]
+    pub fn pitch(&self) -> usize {
+        self.width as usize * 3
+    }
+
+    #[inline]
+    pub fn get_pixel(&self, x: u32, y: u32) -> Rgb {
+        let offset = (self.width as usize * y as usize + x as usize) * 3;
+        Rgb([self.data[offset], self.data[offset + 1], self.data[offset + 2]])
+    }
+
+    #[inline]
+    pub fn set_pixel(&mut self, x: u32, y: u32, Rgb(rgb): Rgb) {
+        let offset = (self.width as usize * y as usize + x as usize) * 3;
+        self.data[offset..offset + 3].copy_from_slice(&rgb);
+    }
+}
@@ -0,0 +1,421 @@
+// color-cycle - render color cycle images
+// Copyright (C) 2025  Mathias Panzenböck
+// 
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+// 
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+// 
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Import of palette based PNG, GIF, BMP and PCX images as drawn in common
+// pixel art tools. Their color cycles are read from a sidecar JSON file or
+// from a private PNG chunk.
+
+use std::{fs::File, io::Read, path::{Path, PathBuf}};
+
+use crate::{bmp::read_bmp, color::Rgb, error::Error, image::{CycleImage, IndexedImage}, palette::{Cycle, Palette}, pcx::read_pcx};
+
+/// Private PNG chunk holding the cycles in the same JSON format as the
+/// sidecar file. Not safe to copy, since the cycles depend on the palette.
+pub const PNG_CYCLES_CHUNK: [u8; 4] = *b"cyCL";
+
+/// Appended to the full image file name, e.g. `image.png.cycles.json`.
+pub const SIDECAR_SUFFIX: &str = ".cycles.json";
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+/// PCX has the longest header of the supported formats.
+const MAX_HEADER_SIZE: usize = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ImageFileType {
+    PNG,
+    GIF,
+    BMP,
+    PCX,
+}
+
+impl ImageFileType {
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        let extension = extension.to_ascii_lowercase();
+        match extension.as_str() {
+            "png" => Some(ImageFileType::PNG),
+            "gif" => Some(ImageFileType::GIF),
+            "bmp" => Some(ImageFileType::BMP),
+            "pcx" => Some(ImageFileType::PCX),
+            _ => None,
+        }
+    }
+}
+
+#[inline]
+pub fn is_sidecar(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name.to_string_lossy().to_ascii_lowercase().ends_with(SIDECAR_SUFFIX))
+}
+
+fn sidecar_path(path: &Path) -> PathBuf {
+    let mut sidecar_path = path.as_os_str().to_owned();
+    sidecar_path.push(SIDECAR_SUFFIX);
+    sidecar_path.into()
+}
+
+/// Whether a file found in a directory should be opened. True-color PNG and
+/// BMP files, like screenshots, can't be imported, so they are only opened if
+/// there is a sidecar file. Files that can't be read are opened, so the error
+/// is shown.
+pub fn is_importable(path: &Path, file_type: ImageFileType) -> bool {
+    if sidecar_path(path).is_file() {
+        return true;
+    }
+
+    let mut header = Vec::with_capacity(MAX_HEADER_SIZE);
+    match File::open(path) {
+        Ok(file) => {
+            if file.take(MAX_HEADER_SIZE as u64).read_to_end(&mut header).is_err() {
+                return true;
+            }
+        }
+        Err(_) => return true,
+    }
+
+    !is_true_color(&header, file_type)
+}
+
+/// Checks the file header, anything unknown is not considered true-color.
+fn is_true_color(header: &[u8], file_type: ImageFileType) -> bool {
+    match file_type {
+        ImageFileType::PNG => {
+            // IHDR is the first chunk, color type 3 is indexed
+            header.starts_with(&PNG_SIGNATURE) && header.get(12..16) == Some(b"IHDR") &&
+                header.get(25).is_some_and(|&color_type| color_type != 3)
+        }
+        ImageFileType::GIF => false,
+        ImageFileType::BMP => {
+            let Some(&[a, b, c, d]) = header.get(14..18) else {
+                return false;
+            };
+            let offset = if u32::from_le_bytes([a, b, c, d]) == 12 { 24 } else { 28 };
+            header.get(offset..offset + 2).is_some_and(|bits| u16::from_le_bytes([bits[0], bits[1]]) > 8)
+        }
+        ImageFileType::PCX => {
+            let (Some(&bits_per_pixel), Some(&num_planes)) = (header.get(3), header.get(65)) else {
+                return false;
+            };
+            bits_per_pixel as u32 * num_planes as u32 > 8
+        }
+    }
+}
+
+/// Read an indexed image from `reader` and its cycles from the sidecar file
+/// next to `path`. For PNG files without a sidecar the cycles are read from a
+/// `cyCL` chunk, if there is one.
+pub fn read_indexed_image(path: &Path, reader: &mut impl Read, file_type: ImageFileType) -> Result<CycleImage, Error> {
+    let (image, embedded_cycles) = match file_type {
+        ImageFileType::PNG => read_png(reader)?,
+        ImageFileType::GIF => (read_gif(reader)?, None),
+        ImageFileType::BMP => (read_bmp(reader)?, None),
+        ImageFileType::PCX => (read_pcx(reader)?, None),
+    };
+
+    let cycles = match read_sidecar(path)? {
+        Some(cycles) => cycles,
+        None => match embedded_cycles {
+            Some(json) => parse_cycles(&json)
+                .map_err(|err| Error::with_source("illegal cyCL chunk in PNG", Box::new(err)))?,
+            None => Box::new([]),
+        }
+    };
+
+    Ok(CycleImage::new(None, image, cycles))
+}
+
+fn read_sidecar(path: &Path) -> Result<Option<Box<[Cycle]>>, Error> {
+    let sidecar_path = sidecar_path(path);
+
+    let json = match std::fs::read(&sidecar_path) {
+        Ok(json) => json,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => {
+            return Err(Error::with_source(format!("error reading {sidecar_path:?}"), Box::new(err)));
+        }
+    };
+
+    match parse_cycles(&json) {
+        Ok(cycles) => Ok(Some(cycles)),
+        Err(err) => Err(Error::with_source(format!("error reading {sidecar_path:?}"), Box::new(err))),
+    }
+}
+
+/// The cycles are either an object with a `"cycles"` array, like in Canvas
+/// Cycle JSON files, or just the array.
+fn parse_cycles(json: &[u8]) -> Result<Box<[Cycle]>, serde_json::Error> {
+    let value: serde_json::Value = serde_json::from_slice(json)?;
+    match value {
+        serde_json::Value::Object(mut object) => {
+            let Some(cycles) = object.remove("cycles") else {
+                return Err(serde::de::Error::missing_field("cycles"));
+            };
+            serde_json::from_value(cycles)
+        }
+        value => serde_json::from_value(value),
+    }
+}
+
+/// Returns the image and the contents of the `cyCL` chunk.
+fn read_png(reader: &mut impl Read) -> Result<(IndexedImage, Option<Vec<u8>>), Error> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+
+    let mut decoder = png::Decoder::new(&data[..]);
+    decoder.set_transformations(png::Transformations::IDENTITY);
+    let mut png_reader = decoder.read_info()?;
+
+    let info = png_reader.info();
+    if info.color_type != png::ColorType::Indexed {
+        return Err(Error::new(format!("only indexed PNG files are supported, but color type is: {:?}", info.color_type)));
+    }
+
+    let mut palette = Palette::default();
+    if let Some(colors) = &info.palette {
+        for (index, rgb) in colors.chunks_exact(3).take(256).enumerate() {
+            palette[index as u8] = Rgb([rgb[0], rgb[1], rgb[2]]);
+        }
+    }
+
+    let mut buf = vec![0u8; png_reader.output_buffer_size()];
+    let output_info = png_reader.next_frame(&mut buf)?;
+    let width = output_info.width;
+    let height = output_info.height;
+    let bits_per_pixel = output_info.bit_depth as u32;
+    let pixels_per_byte = 8 / bits_per_pixel;
+    let mask = ((1u16 << bits_per_pixel) - 1) as u8;
+
+    let mut image = IndexedImage::new(width, height, palette);
+    for (y, line) in buf.chunks_exact(output_info.line_size).take(height as usize).enumerate() {
+        for x in 0..width {
+            let byte = line[(x / pixels_per_byte) as usize];
+            let shift = 8 - bits_per_pixel * (x % pixels_per_byte + 1);
+            image.set_index(x, y as u32, (byte >> shift) & mask);
+        }
+    }
+
+    Ok((image, find_png_chunk(&data, PNG_CYCLES_CHUNK)))
+}
+
+/// The png crate doesn't hand out unknown chunks, so walk them here.
+fn find_png_chunk(data: &[u8], chunk_type: [u8; 4]) -> Option<Vec<u8>> {
+    if !data.starts_with(&PNG_SIGNATURE) {
+        return None;
+    }
+
+    let mut pos = PNG_SIGNATURE.len();
+    while let Some(head) = data.get(pos..pos + 8) {
+        let len = u32::from_be_bytes([head[0], head[1], head[2], head[3]]) as usize;
+        let data_start = pos + 8;
+        let chunk_data = data.get(data_start..data_start.checked_add(len)?)?;
+        if head[4..8] == chunk_type {
+            return Some(chunk_data.to_vec());
+        }
+        if &head[4..8] == b"IEND" {
+            break;
+        }
+        // data + CRC
+        pos = data_start + len + 4;
+    }
+
+    None
+}
+
+/// Reads the first frame of a GIF, drawn onto the logical screen filled with
+/// the background color.
+fn read_gif(reader: &mut impl Read) -> Result<IndexedImage, Error> {
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::Indexed);
+    let mut decoder = options.read_info(reader)?;
+
+    let width = decoder.width() as u32;
+    let height = decoder.height() as u32;
+    let bg_color = decoder.bg_color().unwrap_or(0) as u8;
+    let global_palette = decoder.global_palette().map(<[u8]>::to_vec);
+
+    let Some(frame) = decoder.read_next_frame()? else {
+        return Err(Error::new("GIF file contains no image"));
+    };
+
+    let Some(colors) = frame.palette.as_deref().or(global_palette.as_deref()) else {
+        return Err(Error::new("GIF file has no palette"));
+    };
+
+    let mut palette = Palette::default();
+    for (index, rgb) in colors.chunks_exact(3).take(256).enumerate() {
+        palette[index as u8] = Rgb([rgb[0], rgb[1], rgb[2]]);
+    }
+
+    let mut image = IndexedImage::from_index(width, height, bg_color, palette);
+    let frame_width = frame.width as u32;
+    for (index, &pixel) in frame.buffer.iter().enumerate() {
+        if frame.transparent == Some(pixel) {
+            continue;
+        }
+        let x = frame.left as u32 + index as u32 % frame_width;
+        let y = frame.top as u32 + index as u32 / frame_width;
+        if x < width && y < height {
+            image.set_index(x, y, pixel);
+        }
+    }
+
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::palette::CycleMode;
+
+    use super::*;
+
+    fn png_chunk(chunk_type: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+        chunk.extend_from_slice(chunk_type);
+        chunk.extend_from_slice(data);
+        // the CRC isn't checked
+        chunk.extend_from_slice(&[0; 4]);
+        chunk
+    }
+
+    fn png_file(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = PNG_SIGNATURE.to_vec();
+        for chunk in chunks {
+            data.extend_from_slice(chunk);
+        }
+        data
+    }
+
+    fn encode_png(width: u32, height: u32, color_type: png::ColorType, bit_depth: png::BitDepth, data: &[u8], cycles: Option<&[u8]>) -> Vec<u8> {
+        let mut file = Vec::new();
+        let mut encoder = png::Encoder::new(&mut file, width, height);
+        encoder.set_color(color_type);
+        encoder.set_depth(bit_depth);
+        if color_type == png::ColorType::Indexed {
+            encoder.set_palette((0..4u8).flat_map(|index| [index, 10, 20]).collect::<Vec<_>>());
+        }
+        let mut writer = encoder.write_header().unwrap();
+        if let Some(cycles) = cycles {
+            writer.write_chunk(png::chunk::ChunkType(PNG_CYCLES_CHUNK), cycles).unwrap();
+        }
+        writer.write_image_data(data).unwrap();
+        writer.finish().unwrap();
+        file
+    }
+
+    #[test]
+    fn find_chunk() {
+        let ihdr = png_chunk(b"IHDR", &[0; 13]);
+        let iend = png_chunk(b"IEND", &[]);
+        let cycles = png_chunk(&PNG_CYCLES_CHUNK, b"[]");
+
+        let file = png_file(&[ihdr.clone(), cycles.clone(), iend.clone()]);
+        assert_eq!(find_png_chunk(&file, PNG_CYCLES_CHUNK).as_deref(), Some(&b"[]"[..]));
+
+        let file = png_file(&[ihdr.clone(), iend.clone(), cycles.clone()]);
+        assert_eq!(find_png_chunk(&file, PNG_CYCLES_CHUNK), None);
+
+        let mut file = png_file(std::slice::from_ref(&ihdr));
+        file.extend_from_slice(&cycles[..cycles.len() - 5]);
+        assert_eq!(find_png_chunk(&file, PNG_CYCLES_CHUNK), None);
+
+        assert_eq!(find_png_chunk(&cycles, PNG_CYCLES_CHUNK), None);
+    }
+
+    #[test]
+    fn indexed_png_with_cycles() {
+        let json = br#"{"cycles": [{ "low": 1, "high": 3, "rate": 2800, "reverse": 2 }]}"#;
+        // 2 bits per pixel, rows padded to whole bytes
+        let data = [0b00_01_10_11, 0b01_000000, 0b11_10_01_00, 0b00_000000];
+        let file = encode_png(5, 2, png::ColorType::Indexed, png::BitDepth::Two, &data, Some(json));
+
+        let (image, cycles) = read_png(&mut &file[..]).unwrap();
+        assert_eq!(image.data(), &[0, 1, 2, 3, 1, 3, 2, 1, 0, 0]);
+        assert_eq!(image.palette()[3], Rgb([3, 10, 20]));
+        assert_eq!(cycles.as_deref(), Some(&json[..]));
+
+        let cycles = parse_cycles(&cycles.unwrap()).unwrap();
+        assert_eq!(&cycles[..], &[Cycle::new(1, 3, 2800, CycleMode::Reverse)]);
+        assert!(!is_true_color(&file, ImageFileType::PNG));
+    }
+
+    #[test]
+    fn true_color_png() {
+        let file = encode_png(1, 1, png::ColorType::Rgb, png::BitDepth::Eight, &[1, 2, 3], None);
+        assert!(read_png(&mut &file[..]).is_err());
+        assert!(is_true_color(&file, ImageFileType::PNG));
+    }
+
+    #[test]
+    fn cycles_array() {
+        let cycles = parse_cycles(br#"[{ "low": 16, "high": 31, "rate": 1400 }]"#).unwrap();
+        assert_eq!(&cycles[..], &[Cycle::new(16, 31, 1400, CycleMode::Forward)]);
+        assert!(parse_cycles(br#"{"palette": []}"#).is_err());
+    }
+
+    #[test]
+    fn gif_frame_offset() {
+        let mut file = Vec::new();
+        {
+            let global_palette: Vec<u8> = (0..4u8).flat_map(|index| [index, 50, 60]).collect();
+            let mut encoder = gif::Encoder::new(&mut file, 4, 3, &global_palette).unwrap();
+            let frame = gif::Frame {
+                left: 1,
+                top: 1,
+                width: 2,
+                height: 2,
+                transparent: Some(3),
+                buffer: vec![1, 2, 3, 1].into(),
+                ..gif::Frame::default()
+            };
+            encoder.write_frame(&frame).unwrap();
+        }
+
+        let image = read_gif(&mut &file[..]).unwrap();
+        assert_eq!(image.size(), (4, 3));
+        assert_eq!(image.data(), &[
+            0, 0, 0, 0,
+            0, 1, 2, 0,
+            0, 0, 1, 0,
+        ]);
+        assert_eq!(image.palette()[2], Rgb([2, 50, 60]));
+    }
+
+    #[test]
+    fn true_color_headers() {
+        let mut bmp = vec![0u8; 54];
+        bmp[..2].copy_from_slice(b"BM");
+        bmp[14] = 40;
+        bmp[28] = 24;
+        assert!(is_true_color(&bmp, ImageFileType::BMP));
+        bmp[28] = 8;
+        assert!(!is_true_color(&bmp, ImageFileType::BMP));
+
+        // OS/2 header
+        bmp[14] = 12;
+        bmp[24] = 24;
+        assert!(is_true_color(&bmp, ImageFileType::BMP));
+
+        let mut pcx = vec![0u8; MAX_HEADER_SIZE];
+        pcx[0] = 0x0A;
+        pcx[3] = 8;
+        pcx[65] = 3;
+        assert!(is_true_color(&pcx, ImageFileType::PCX));
+        pcx[65] = 1;
+        assert!(!is_true_color(&pcx, ImageFileType::PCX));
+
+        assert!(!is_true_color(b"GIF89a", ImageFileType::GIF));
+        assert!(!is_true_color(&bmp[..20], ImageFileType::BMP));
+    }
+}
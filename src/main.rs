@@ -19,24 +19,40 @@ pub mod image;
 pub mod palette;
 pub mod read;
 pub mod ilbm;
+pub mod deep;
+pub mod atari_st;
+pub mod bmp;
+pub mod pcx;
+pub mod import;
 pub mod bitvec;
 pub mod error;
+pub mod easing;
+pub mod hotkeys;
+pub mod control;
+pub mod screenshot;
+pub mod sun;
+pub mod timeline;
+pub mod render;
+pub mod scale;
+pub mod crt;
+pub mod stats;
+pub mod pacing;
 
 use std::fmt::{Debug, Display, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 use std::fs::File;
-use std::io::{BufReader, Seek};
+use std::io::{BufRead, BufReader, Seek};
 use std::u64;
 
-use color::Rgb;
+use color::BlendSpace;
 use palette::Palette;
 use sdl2::event::{Event, WindowEvent};
-use sdl2::keyboard::{Keycode, Mod};
+use sdl2::mouse::MouseButton;
 use sdl2::messagebox::{MessageBoxButtonFlag, MessageBoxFlag};
 use sdl2::pixels::{Color, PixelFormatEnum};
 use sdl2::rect::Rect;
-use sdl2::render::TextureQuery;
+use sdl2::render::{Texture, TextureQuery};
 use sdl2::rwops::RWops;
 use sdl2::sys::SDL_WindowFlags;
 use sdl2::video::{FullscreenType, WindowPos};
@@ -45,17 +61,34 @@ use sdl2::video::{FullscreenType, WindowPos};
 use std::mem::MaybeUninit;
 
 use clap::Parser;
-use image::{CycleImage, IndexedImage, LivingWorld};
+use hotkeys::{Command, Hotkeys};
+use control::{ControlCommand, ControlServer};
+use screenshot::ScreenshotFormat;
+use easing::Easing;
+use sun::{Location, SunTimes};
+use timeline::DayGraph;
+use render::{ChangedPixelsRenderer, ConversionPool, ConversionStats};
+use scale::ScaleFilter;
+use crt::CRT_SCALE;
+use stats::FrameStats;
+use pacing::Pacing;
+use serde_json::json;
+use image::{living_world::DAY_DURATION, CycleImage, IndexedImage, LivingWorld, RgbImage};
 
 #[cfg(not(windows))]
 use libc;
 
 const MAX_FPS: u32 = 10_000;
-const TIME_STEP: u64 = 5 * 60 * 1000;
-const SMALL_TIME_STEP: u64 = 60 * 1000;
-const DAY_DURATION: u64 = 24 * 60 * 60 * 1000;
 const FAST_FORWARD_SPEED: u64 = 10_000;
 
+/// Files with these extensions are opened when a directory is passed.
+const SUPPORTED_EXTENSIONS: &[&str] = &["json", "lbm", "ilbm", "iff", "bbm", "deep", "rgbn", "rgb8",
+    "neo", "pi1", "pi2", "pi3", "pc1", "pc2", "pc3", "png", "gif", "bmp", "pcx"];
+
+/// Mouse motion directly after start is ignored in screensaver mode, since
+/// creating the window might produce such events.
+const SCREENSAVER_GRACE_PERIOD: Duration = Duration::from_secs(1);
+
 const HACK_FONT: &[u8] = include_bytes!("../assets/Hack-Regular.ttf");
 const APP_NAME: &str = "Color Cycle Viewer";
 
@@ -77,6 +110,15 @@ fn interruptable_sleep(duration: Duration) -> bool {
     }
 }
 
+/// Sleep until an event arrives or the timeout expires. The event is left in
+/// the queue for the event loop.
+fn wait_for_event(timeout: Duration) {
+    let timeout_ms = timeout.as_micros().div_ceil(1000).min(i32::MAX as u128) as i32;
+    if timeout_ms > 0 {
+        unsafe { sdl2::sys::SDL_WaitEventTimeout(std::ptr::null_mut(), timeout_ms); }
+    }
+}
+
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None, after_help = "\
 color-cycle  Copyright (C) 2025  Mathias Panzenböck
@@ -91,18 +133,81 @@ pub struct Args {
     #[arg(short, long, default_value_t = 60, value_parser = clap::value_parser!(u32).range(1..MAX_FPS as i64))]
     pub fps: u32,
 
+    /// How frames are paced.
+    ///
+    /// fixed renders at the given FPS. adaptive only renders when the palette
+    /// changed and sleeps until the next cycle step or timeline change, unless
+    /// blend mode, OSD messages or the HUD need regular frames. vsync renders
+    /// a frame for every refresh of the display, ignoring the FPS (only if the
+    /// driver supports vsync).
+    #[arg(long, default_value_t = Pacing::Fixed)]
+    pub pacing: Pacing,
+
     /// Enable blend mode.
     /// 
     /// This blends the animated color palette for smoother display.
     #[arg(short, long, default_value_t = false)]
     pub blend: bool,
 
+    /// Use an ordered dither pattern between the two adjacent colors instead
+    /// of mixed colors in blend mode.
+    ///
+    /// This keeps to the colors of the original palette. Implies --blend.
+    #[arg(short, long, default_value_t = false)]
+    pub dither: bool,
+
+    /// Color space used for blending.
+    ///
+    /// srgb is the fastest, linear (linear light RGB) and oklab (a perceptual
+    /// color space) avoid dark and muddy midpoints.
+    #[arg(long, default_value_t = BlendSpace::Srgb)]
+    pub blend_space: BlendSpace,
+
+    /// Pixel-art upscaling filter.
+    ///
+    /// nearest just lets SDL scale the pixels, scale2x (aka EPX), scale3x and
+    /// xbr (an xBR-like 2x filter) smooth edges. They work on the palette
+    /// indices, so the scaled image still color cycles.
+    #[arg(long, value_name = "FILTER", default_value_t = ScaleFilter::Nearest)]
+    pub scale_filter: ScaleFilter,
+
+    /// Emulate a CRT screen with scanlines, a shadow mask and slight bloom.
+    ///
+    /// This is done in software and renders the image three times as large.
+    #[arg(long, default_value_t = false)]
+    pub crt: bool,
+
+    /// Number of threads converting pixels on the CPU.
+    ///
+    /// The image is split into bands of rows, one per thread. 0 uses one
+    /// thread per CPU core, 1 converts on the main thread. Only used for
+    /// palette based images.
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pub threads: usize,
+
+    /// Print pixel conversion timing statistics when exiting.
+    #[arg(long, default_value_t = false)]
+    pub stats: bool,
+
     /// Enable On Screen Display.
     /// 
     /// Displays messages when changing things like blend mode or FPS.{n}
     #[arg(short, long, default_value_t = false)]
     pub osd: bool,
 
+    /// Show frame timing statistics.
+    ///
+    /// Actual FPS, frame time percentiles, time spent on palette computation,
+    /// pixel conversion and presenting the frame, and dropped frames.
+    #[arg(long, default_value_t = false)]
+    pub hud: bool,
+
+    /// Show the timeline of Living Worlds scenes.
+    ///
+    /// Click or drag on the timeline to jump to a time of day.
+    #[arg(short, long, default_value_t = false)]
+    pub timeline: bool,
+
     /// Start in fullscreen
     #[arg(short = 'F', long, default_value_t = false)]
     pub full_screen: bool,
@@ -120,57 +225,375 @@ pub struct Args {
     #[arg(long, default_value_t = false)]
     pub help_hotkeys: bool,
 
+    /// Print the palettes and timeline of the given Living Worlds files and exit.
+    #[arg(long, default_value_t = false)]
+    pub list_palettes: bool,
+
+    /// Print the exact time after which the color cycles repeat (without
+    /// blend mode) and the number of distinct palettes in that time, then exit.
+    ///
+    /// Useful for exporting seamless loops.
+    #[arg(long, default_value_t = false)]
+    pub cycle_period: bool,
+
+    /// Load hotkey bindings from a JSON file.
+    ///
+    /// The file contains a JSON object mapping key names to commands, e.g.
+    /// `{"Shift+Q": "quit", "Q": null}`. Bindings are applied on top of the
+    /// default bindings and `null` removes a binding.
+    #[arg(long)]
+    pub hotkeys: Option<PathBuf>,
+
+    /// Listen for control commands on this Unix domain socket.
+    ///
+    /// Commands are sent one per line, e.g. `open /path/to/file.lbm`,
+    /// `goto 2`, `set-time 18:30`, `blend on` or `status`. Any hotkey
+    /// command is accepted as well. Each command is answered with a single
+    /// line of JSON.
+    #[arg(long, value_name = "PATH")]
+    pub control_socket: Option<PathBuf>,
+
+    /// Read control commands from stdin and write responses to stdout.
+    ///
+    /// Uses the same protocol as --control-socket.
+    #[arg(long, default_value_t = false)]
+    pub control_stdin: bool,
+
+    /// Directory where screenshots are saved.
+    #[arg(long, value_name = "DIR", default_value = ".")]
+    pub screenshot_dir: PathBuf,
+
+    /// Run as a screensaver: quit on any key press, mouse button or mouse motion.
+    #[arg(long, default_value_t = false)]
+    pub screensaver: bool,
+
+    /// Run as XScreenSaver hack drawing to the whole screen.
+    ///
+    /// Implies --screensaver. Uses the window given by the XSCREENSAVER_WINDOW
+    /// environment variable if set, otherwise opens a full-screen window.
+    /// Can also be passed as -root.
+    #[arg(long, default_value_t = false)]
+    pub root: bool,
+
+    /// Render into an existing native window (e.g. an X11 window ID).
+    ///
+    /// Accepts decimal or hexadecimal (0x...) numbers. Defaults to the
+    /// XSCREENSAVER_WINDOW environment variable if set. Can also be passed as
+    /// -window-id.
+    #[arg(long, value_name = "ID", value_parser = parse_window_id)]
+    pub window_id: Option<u64>,
+
+    /// Start with a fixed time of day (HH:MM or HH:MM:SS).
+    ///
+    /// Pins Living Worlds scenes to this time instead of following the clock.
+    /// Press S (current-time) to follow the clock again. Since the time
+    /// doesn't move it can't be combined with --time-speed.
+    #[arg(long, value_name = "TIME", value_parser = parse_time_of_day, conflicts_with = "time_speed")]
+    pub time: Option<u64>,
+
+    /// Time zone of the simulated clock.
+    ///
+    /// Either UTC, a fixed offset like UTC+02:00 or -05:30, or (on Unix) a
+    /// time zone name like Europe/Vienna. Defaults to the local time zone.
+    #[arg(long, value_name = "TZ", value_parser = parse_time_zone)]
+    pub timezone: Option<TimeZone>,
+
+    /// Shift the simulated clock by this amount ([-]HH:MM or [-]HH:MM:SS).
+    #[arg(long, value_name = "OFFSET", value_parser = parse_time_offset, allow_hyphen_values = true)]
+    pub time_offset: Option<i64>,
+
+    /// Let the simulated clock run this many times faster than real time.
+    ///
+    /// E.g. 144 runs through a full day in ten minutes.
+    #[arg(long, value_name = "FACTOR", default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..=FAST_FORWARD_SPEED))]
+    pub time_speed: u64,
+
+    /// Follow the real daylight at this location (LATITUDE,LONGITUDE).
+    ///
+    /// Living Worlds timelines assume sunrise at 6:00 and sunset at 18:00.
+    /// With this option the timeline is stretched so that these match the
+    /// actual sunrise and sunset of the current date at the given location,
+    /// e.g. 48.21,16.37 for Vienna. Southern and western coordinates are
+    /// negative.
+    #[arg(long, value_name = "LAT,LON", allow_hyphen_values = true)]
+    pub location: Option<Location>,
+
+    /// Easing of palette transitions of Living Worlds timelines.
+    ///
+    /// One of linear, smoothstep, hold:FRACTION (keep the previous palette
+    /// for that fraction of the time, then blend) or fade:DURATION (blend
+    /// only during that time before the next timeline event, e.g. fade:30m).
+    /// Overrides the easing of the scene file, except for transitions that
+    /// specify their own easing.
+    #[arg(long)]
+    pub easing: Option<Easing>,
+
+    /// Automatically open the next file after this many seconds.
+    #[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub slideshow: Option<u64>,
+
     /// Path to a Canvas Cycle JSON file.
+    ///
+    /// Directories are expanded to the supported files they contain.
     #[arg(required = true)]
     pub paths: Vec<PathBuf>,
 }
 
+fn parse_window_id(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let res = if let Some(hex) = value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        u64::from_str_radix(hex, 16)
+    } else {
+        value.parse()
+    };
+    res.map_err(|err| format!("illegal window ID {value:?}: {err}"))
+}
+
+/// Expand directories to the supported files they contain, sorted by name.
+/// True-color PNG and BMP files without a sidecar file are skipped.
+fn expand_paths(paths: Vec<PathBuf>) -> std::io::Result<Vec<PathBuf>> {
+    let mut expanded = Vec::with_capacity(paths.len());
+    for path in paths {
+        if !path.is_dir() {
+            expanded.push(path);
+            continue;
+        }
+
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(&path)? {
+            let file = entry?.path();
+            let supported = file.extension().is_some_and(|ext| {
+                let ext = ext.to_string_lossy().to_ascii_lowercase();
+                SUPPORTED_EXTENSIONS.contains(&ext.as_str())
+            });
+            if supported && !import::is_sidecar(&file) && file.is_file() {
+                let file_type = file.extension()
+                    .and_then(|ext| import::ImageFileType::from_extension(&ext.to_string_lossy()));
+                if let Some(file_type) = file_type && !import::is_importable(&file, file_type) {
+                    eprintln!("Warning: {}: skipped, only palette based images are supported", file.to_string_lossy());
+                    continue;
+                }
+                files.push(file);
+            }
+        }
+        files.sort();
+        expanded.extend(files);
+    }
+    Ok(expanded)
+}
+
+/// Reduce an ILBM style pixel aspect ratio, `(1, 1)` if it is unknown.
+fn pixel_aspect(x_aspect: u8, y_aspect: u8) -> (u8, u8) {
+    if x_aspect != 0 && y_aspect != 0 && x_aspect != y_aspect {
+        if x_aspect.is_multiple_of(y_aspect) {
+            (x_aspect / y_aspect, 1)
+        } else if y_aspect.is_multiple_of(x_aspect) {
+            (1, y_aspect / x_aspect)
+        } else {
+            (x_aspect, y_aspect)
+        }
+    } else {
+        (1, 1)
+    }
+}
+
+/// Empty stand-in so true-color images can be shown like any other file.
+fn true_color_living_world(image: &RgbImage) -> LivingWorld {
+    CycleImage::new(None, IndexedImage::new(image.width(), image.height(), Palette::default()), Box::new([])).into()
+}
+
+/// Living World, true-color image and pixel aspect ratio of a file.
+type LoadedImage = (LivingWorld, Option<RgbImage>, (u8, u8));
+
+/// Read an ILBM, DEEP, Atari ST or Canvas Cycle/Living Worlds JSON file.
+/// Also returns the pixel aspect ratio. True-color images (deep ILBM, RGBN,
+/// RGB8 and DEEP) are returned separately together with an empty Living
+/// World of the same size. Atari ST images are recognized by the extension
+/// of `path`, all others by their content.
+fn read_living_world<R: BufRead + Seek>(path: &Path, reader: &mut R) -> Result<LoadedImage, error::Error> {
+    let st_file_type = path.extension()
+        .and_then(|extension| atari_st::StFileType::from_extension(&extension.to_string_lossy()));
+    if let Some(file_type) = st_file_type {
+        let (image, aspect) = atari_st::read_st_image(reader, file_type)?;
+        return Ok((image.into(), None, aspect));
+    }
+
+    let image_file_type = path.extension()
+        .and_then(|extension| import::ImageFileType::from_extension(&extension.to_string_lossy()));
+    if let Some(file_type) = image_file_type {
+        let image = import::read_indexed_image(path, reader, file_type)?;
+        return Ok((image.into(), None, (1, 1)));
+    }
+
+    match ilbm::ILBM::read(reader) {
+        Ok(ilbm) => {
+            let aspect = pixel_aspect(ilbm.header().x_aspect(), ilbm.header().y_aspect());
+            //let viewport_mode = ilbm.camg().map(CAMG::viewport_mode).unwrap_or(0);
+            //eprintln!("ILBM: file_type: {:?}, {:?}", ilbm.file_type(), ilbm.header());
+            //eprintln!("colors: {}", ilbm.cmap().map_or(0, |cmap| cmap.colors().len()));
+            //eprint!("viewport_mode: 0x{viewport_mode:x}");
+            //for &(flag, name) in &[
+            //    (CAMG::EHB, "EHB"),
+            //    (CAMG::HAM, "HAM"),
+            //    (CAMG::HIRES, "HIRES"),
+            //    (CAMG::LACE, "LACE"),
+            //] {
+            //    if viewport_mode & flag != 0 {
+            //        eprint!(" {name}");
+            //    }
+            //}
+            //eprintln!();
+            if ilbm.is_true_color() {
+                let image: RgbImage = ilbm.try_into()?;
+                return Ok((true_color_living_world(&image), Some(image), aspect));
+            }
+
+            let res: Result<CycleImage, _> = ilbm.try_into();
+            match res {
+                Ok(image) => Ok((image.into(), None, aspect)),
+                Err(err) => Err(err.into())
+            }
+        }
+        Err(err) => {
+            if err.kind() != ilbm::ErrorKind::UnsupportedFileFormat {
+                return Err(err.into());
+            }
+
+            reader.seek(std::io::SeekFrom::Start(0))?;
+            match deep::DEEP::read(reader) {
+                Ok(deep) => {
+                    let aspect = pixel_aspect(deep.header().x_aspect(), deep.header().y_aspect());
+                    let image = deep.into_image();
+                    return Ok((true_color_living_world(&image), Some(image), aspect));
+                }
+                Err(err) if err.kind() != ilbm::ErrorKind::UnsupportedFileFormat => {
+                    return Err(err.into());
+                }
+                Err(_) => {}
+            }
+
+            reader.seek(std::io::SeekFrom::Start(0))?;
+            match serde_json::from_reader(reader) {
+                Ok(image) => Ok((image, None, (1, 1))),
+                Err(err) => Err(err.into())
+            }
+        }
+    }
+}
+
 fn main() {
-    let args = Args::parse();
+    // XScreenSaver passes single dash options.
+    let args = Args::parse_from(std::env::args_os().filter_map(|arg| {
+        match arg.to_str() {
+            Some("-root") => Some("--root".into()),
+            Some("-window-id") => Some("--window-id".into()),
+            // run in a normal window, which is the default anyway
+            Some("-window") => None,
+            _ => Some(arg),
+        }
+    }));
+
+    let hotkeys = if let Some(path) = &args.hotkeys {
+        match Hotkeys::load(path) {
+            Ok(hotkeys) => hotkeys,
+            Err(err) => {
+                show_error(format_args!("{}: {}", path.to_string_lossy(), err));
+                std::process::exit(1);
+            }
+        }
+    } else {
+        Hotkeys::default()
+    };
 
     if args.help_hotkeys {
-        println!("\
-Hotkeys
-=======
-B                  Toggle blend mode
-Q                  Quit program
-Escape             Close full-screen or quit program
-O                  Toggle On Screen Display
-C                  Toggle zoom to cover/contain
-N                  Open next file
-P                  Open previous file
-1 to 9             Open file by index
-0                  Open last file
-+                  Increase frames per second by 1
--                  Decrease frames per second by 1
-F or F11           Toggle full-screen
-W                  Toogle fast forward ({FAST_FORWARD_SPEED}x speed)
-A                  Go back in time by 5 minutes
-Shift+A            Go back in time by 1 minute
-D                  Go forward in time by 5 minutes
-Shift+D            Go forward in time by 1 minute
-S                  Go to current time and continue normal progression
-I                  Reverse pixels in columns of 8.
-                   This is a hack fix for images that appear to be
-                   broken like that.
-Cursor Up          Move view-port up by 1 pixel
-Cursor Down        Move view-port down by 1 pixel
-Cursor Left        Move view-port left by 1 pixel
-Cursor Right       Move view-port right by 1 pixel
-Ctrl+Cursor Up     Move view-port up by 5 pixel
-Ctrl+Cursor Down   Move view-port down by 5 pixel
-Ctrl+Cursor Left   Move view-port left by 5 pixel
-Ctrl+Cursor Right  Move view-port right by 5 pixel");
+        print!("{}", hotkeys.help());
+        return;
+    }
+
+    if let Some(TimeZone::Named(name)) = &args.timezone && let Err(err) = set_time_zone(name) {
+        show_error(format_args!("--timezone: {err}"));
+        std::process::exit(1);
+    }
+
+    let paths = match expand_paths(args.paths) {
+        Ok(paths) => paths,
+        Err(err) => {
+            show_error(err);
+            std::process::exit(1);
+        }
+    };
+
+    if paths.is_empty() {
+        show_error("No supported files found.");
+        std::process::exit(1);
+    }
+
+    if args.list_palettes {
+        if let Err(err) = list_palettes(&paths) {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if args.cycle_period {
+        if let Err(err) = print_cycle_periods(&paths) {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
         return;
     }
 
+    let window_id = if args.window_id.is_some() {
+        args.window_id
+    } else if let Ok(value) = std::env::var("XSCREENSAVER_WINDOW") {
+        match parse_window_id(&value) {
+            Ok(window_id) => Some(window_id),
+            Err(err) => {
+                show_error(format_args!("XSCREENSAVER_WINDOW: {err}"));
+                std::process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+
     match ColorCycleViewer::new(ColorCycleViewerOptions {
         fps: args.fps,
-        blend: args.blend,
+        pacing: args.pacing,
+        blend: args.blend || args.dither,
+        dither: args.dither,
+        blend_space: args.blend_space,
+        threads: if args.threads == 0 {
+            std::thread::available_parallelism().map(|threads| threads.get()).unwrap_or(1)
+        } else {
+            args.threads
+        },
+        stats: args.stats,
+        scale_filter: args.scale_filter,
+        crt: args.crt,
         osd: args.osd,
-        full_screen: args.full_screen,
+        hud: args.hud,
+        timeline: args.timeline,
+        full_screen: args.full_screen || (args.root && window_id.is_none()),
         cover: args.cover,
-        paths: args.paths,
+        paths,
+        screensaver: args.screensaver || args.root,
+        window_id,
+        slideshow: args.slideshow.map(Duration::from_secs),
+        time: args.time,
+        utc_offset: match args.timezone {
+            Some(TimeZone::Fixed(offset)) => Some(offset),
+            _ => None,
+        },
+        time_offset: args.time_offset.unwrap_or(0),
+        time_speed: args.time_speed,
+        location: args.location,
+        easing: args.easing,
+        hotkeys,
+        control_socket: args.control_socket,
+        control_stdin: args.control_stdin,
+        screenshot_dir: args.screenshot_dir,
         ttf: &match sdl2::ttf::init() {
             Ok(ttf) => ttf,
             Err(err) => {
@@ -192,6 +615,84 @@ Ctrl+Cursor Right  Move view-port right by 5 pixel");
     }
 }
 
+fn list_palettes(paths: &[PathBuf]) -> Result<(), error::Error> {
+    for (index, path) in paths.iter().enumerate() {
+        if index > 0 {
+            println!();
+        }
+
+        let res = File::open(path)
+            .map_err(error::Error::from)
+            .and_then(|file| read_living_world(path, &mut BufReader::new(file)));
+        let (living_world, _, _) = match res {
+            Ok(living_world) => living_world,
+            Err(err) => {
+                return Err(error::Error::with_source(path.to_string_lossy().into_owned(), Box::new(err)));
+            }
+        };
+
+        if let Some(name) = living_world.name() {
+            println!("{}: {name}", path.to_string_lossy());
+        } else {
+            println!("{}:", path.to_string_lossy());
+        }
+
+        if living_world.palettes().is_empty() {
+            println!("  no palettes");
+            continue;
+        }
+
+        println!("  palettes:");
+        for (index, name) in living_world.palette_names().iter().enumerate() {
+            println!("    {:2}. {name}", index + 1);
+        }
+
+        println!("  timeline:");
+        for event in living_world.timeline() {
+            let (hours, mins) = get_hours_mins(event.time_of_day() as u64 * 1000);
+            let name = living_world.palette_name(event.palette_index()).unwrap_or("");
+            println!("    {hours:2}:{mins:02} {name}");
+        }
+    }
+
+    Ok(())
+}
+
+fn print_cycle_periods(paths: &[PathBuf]) -> Result<(), error::Error> {
+    fn print_cycle_period(label: &str, cycle_image: &CycleImage) {
+        let Some(period) = cycle_image.cycle_period() else {
+            println!("  {label}: no cycles");
+            return;
+        };
+
+        if let Some(palette_loop) = cycle_image.palette_loop(MAX_PALETTE_LOOP_STATES) {
+            println!("  {label}: {period}, {} palettes", palette_loop.len());
+        } else {
+            println!("  {label}: {period}, more than {MAX_PALETTE_LOOP_STATES} palettes");
+        }
+    }
+
+    for path in paths {
+        let res = File::open(path)
+            .map_err(error::Error::from)
+            .and_then(|file| read_living_world(path, &mut BufReader::new(file)));
+        let (living_world, _, _) = match res {
+            Ok(living_world) => living_world,
+            Err(err) => {
+                return Err(error::Error::with_source(path.to_string_lossy().into_owned(), Box::new(err)));
+            }
+        };
+
+        println!("{}:", path.to_string_lossy());
+        print_cycle_period("base", living_world.base());
+        for (name, palette) in living_world.palette_names().iter().zip(living_world.palettes()) {
+            print_cycle_period(name, palette);
+        }
+    }
+
+    Ok(())
+}
+
 fn show_error(message: impl Display) {
     let message = message.to_string();
     eprintln!("{}", &message);
@@ -207,11 +708,35 @@ fn show_error(message: impl Display) {
 
 struct ColorCycleViewerOptions<'font> {
     fps: u32,
+    pacing: Pacing,
     blend: bool,
+    dither: bool,
+    blend_space: BlendSpace,
+    threads: usize,
+    stats: bool,
+    scale_filter: ScaleFilter,
+    crt: bool,
     osd: bool,
+    hud: bool,
+    timeline: bool,
     paths: Vec<PathBuf>,
     full_screen: bool,
     cover: bool,
+    screensaver: bool,
+    window_id: Option<u64>,
+    slideshow: Option<Duration>,
+    time: Option<u64>,
+    /// Fixed UTC offset in seconds or `None` for the local time zone.
+    utc_offset: Option<i64>,
+    /// Milliseconds added to the clock.
+    time_offset: i64,
+    time_speed: u64,
+    location: Option<Location>,
+    easing: Option<Easing>,
+    hotkeys: Hotkeys,
+    control_socket: Option<PathBuf>,
+    control_stdin: bool,
+    screenshot_dir: PathBuf,
     ttf: &'font sdl2::ttf::Sdl2TtfContext,
 }
 
@@ -224,8 +749,11 @@ struct ColorCycleViewer<'font> {
     was_moved: bool,
     x: i32,
     y: i32,
+    start_ts: Instant,
+    conversion_pool: Option<ConversionPool>,
+    conversion_stats: ConversionStats,
+    frame_stats: FrameStats,
 
-    #[allow(unused)]
     sdl: sdl2::Sdl,
     font: Option<sdl2::ttf::Font<'font, 'static>>,
     font_size: u16,
@@ -233,26 +761,69 @@ struct ColorCycleViewer<'font> {
     video: sdl2::VideoSubsystem,
     canvas: sdl2::render::WindowCanvas,
     event_pump: sdl2::EventPump,
+    game_controller: sdl2::GameControllerSubsystem,
+    controllers: Vec<sdl2::controller::GameController>,
+    control: ControlServer,
 }
 
 const MESSAGE_DISPLAY_DURATION: Duration = Duration::from_secs(3);
+const HUD_UPDATE_INTERVAL: Duration = Duration::from_millis(250);
+/// Most palettes precomputed for a loop of the color cycles.
+const MAX_PALETTE_LOOP_STATES: usize = 4096;
 const ERROR_MESSAGE_DISPLAY_DURATION: Duration = Duration::from_secs(1000 * 365 * 24 * 60 * 60);
 
 impl<'font> ColorCycleViewer<'font> {
     pub fn new(options: ColorCycleViewerOptions<'font>) -> Result<ColorCycleViewer<'font>, error::Error> {
         let sdl = sdl2::init()?;
         let video = sdl.video()?;
-        let window = video
-            .window(APP_NAME, 640, 480)
-            .set_window_flags(if options.full_screen {
-                SDL_WindowFlags::SDL_WINDOW_FULLSCREEN_DESKTOP as u32
-            } else { 0 })
-            .position_centered()
-            .resizable()
-            .build()?;
+        let window = if let Some(window_id) = options.window_id {
+            let raw = unsafe { sdl2::sys::SDL_CreateWindowFrom(window_id as usize as *const std::ffi::c_void) };
+            if raw.is_null() {
+                return Err(error::Error::new(format!(
+                    "error creating window from native window 0x{window_id:x}: {}",
+                    sdl2::get_error())));
+            }
+            unsafe { sdl2::video::Window::from_ll(video.clone(), raw, std::ptr::null_mut()) }
+        } else {
+            video
+                .window(APP_NAME, 640, 480)
+                .set_window_flags(if options.full_screen {
+                    SDL_WindowFlags::SDL_WINDOW_FULLSCREEN_DESKTOP as u32
+                } else { 0 })
+                .position_centered()
+                .resizable()
+                .build()?
+        };
         let event_pump = sdl.event_pump()?;
+        let game_controller = sdl.game_controller()?;
+
+        sdl.mouse().show_cursor(options.timeline && !options.screensaver);
+
+        let mut control = ControlServer::new();
+        // wake the event loop when waiting for the next palette change
+        let event = sdl.event()?;
+        let wake_event_type = unsafe { event.register_event()? };
+        let event_sender = event.event_sender();
+        control.set_waker(move || {
+            let _ = event_sender.push_event(Event::User {
+                timestamp: 0,
+                window_id: 0,
+                type_: wake_event_type,
+                code: 0,
+                data1: std::ptr::null_mut(),
+                data2: std::ptr::null_mut(),
+            });
+        });
+
+        if let Some(path) = &options.control_socket && let Err(err) = control.listen_unix(path) {
+            return Err(error::Error::with_source(
+                format!("{}: error opening control socket", path.to_string_lossy()),
+                Box::new(err)));
+        }
 
-        sdl.mouse().show_cursor(false);
+        if options.control_stdin {
+            control.listen_stdin();
+        }
 
         let canvas = window.into_canvas()
             .accelerated()
@@ -260,12 +831,16 @@ impl<'font> ColorCycleViewer<'font> {
             .build()?;
 
         Ok(ColorCycleViewer {
+            current_time: options.time,
+            time_speed: options.time_speed,
             options,
-            current_time: None,
-            time_speed: 1,
             file_index: 0,
             x: 0,
             y: 0,
+            start_ts: Instant::now(),
+            conversion_pool: None,
+            conversion_stats: ConversionStats::default(),
+            frame_stats: FrameStats::new(),
 
             was_resized: false,
             was_moved: false,
@@ -275,6 +850,9 @@ impl<'font> ColorCycleViewer<'font> {
             video,
             canvas,
             event_pump,
+            game_controller,
+            controllers: Vec::new(),
+            control,
         })
     }
 
@@ -283,82 +861,51 @@ impl<'font> ColorCycleViewer<'font> {
         self.canvas.clear();
         self.canvas.present();
 
+        let mut open_request = None;
         loop {
-            match self.show_image()? {
+            match self.show_image(open_request.take())? {
                 Action::Goto(index) => {
                     self.file_index = index;
                 }
                 Action::Quit => {
+                    if self.options.stats {
+                        let threads = self.conversion_pool.as_ref().map(ConversionPool::threads).unwrap_or(1);
+                        eprintln!("Pixel conversion ({threads} threads): {}", self.conversion_stats);
+                    }
                     return Ok(());
                 }
-                Action::OpenFile(filename) => {
-                    self.options.paths.push(filename.into());
+                Action::OpenFile(path, request) => {
+                    self.options.paths.push(path);
                     self.file_index = self.options.paths.len() - 1;
+                    open_request = request;
                 }
             }
         }
     }
 
-    fn show_image(&mut self) -> Result<Action, error::Error> {
+    /// Show the current file until another one is opened or the program
+    /// quits. `open_request` is answered once the file is loaded.
+    fn show_image(&mut self, open_request: Option<control::Request>) -> Result<Action, error::Error> {
         let path = &self.options.paths[self.file_index];
 
         let filename = path.file_name().map(|f| f.to_string_lossy()).unwrap_or_else(|| path.to_string_lossy());
         self.canvas.window_mut().set_title(&format!("{filename} - {APP_NAME}")).log_error("window.set_title()");
 
-        let file = File::open(path)?;
-        let mut reader = BufReader::new(file);
         let mut x_aspect = 1;
         let mut y_aspect = 1;
-
-        let living_world: Result<LivingWorld, error::Error> = match ilbm::ILBM::read(&mut reader) {
-            Ok(ilbm) => {
-                let ilbm_x_aspect = ilbm.header().x_aspect();
-                let ilbm_y_aspect = ilbm.header().y_aspect();
-                if ilbm_x_aspect != 0 && ilbm_y_aspect != 0 && ilbm_x_aspect != ilbm_y_aspect {
-                    if ilbm_x_aspect % ilbm_y_aspect == 0 {
-                        x_aspect = ilbm_x_aspect / ilbm_y_aspect;
-                    } else if ilbm_y_aspect % ilbm_x_aspect == 0 {
-                        y_aspect = ilbm_y_aspect / ilbm_x_aspect;
-                    } else {
-                        x_aspect = ilbm_x_aspect;
-                        y_aspect = ilbm_y_aspect;
-                    }
-                }
-                //let viewport_mode = ilbm.camg().map(CAMG::viewport_mode).unwrap_or(0);
-                //eprintln!("ILBM: file_type: {:?}, {:?}", ilbm.file_type(), ilbm.header());
-                //eprintln!("colors: {}", ilbm.cmap().map_or(0, |cmap| cmap.colors().len()));
-                //eprint!("viewport_mode: 0x{viewport_mode:x}");
-                //for &(flag, name) in &[
-                //    (CAMG::EHB, "EHB"),
-                //    (CAMG::HAM, "HAM"),
-                //    (CAMG::HIRES, "HIRES"),
-                //    (CAMG::LACE, "LACE"),
-                //] {
-                //    if viewport_mode & flag != 0 {
-                //        eprint!(" {name}");
-                //    }
-                //}
-                //eprintln!();
-                let res: Result<CycleImage, _> = ilbm.try_into();
-                match res {
-                    Ok(image) => Ok(image.into()),
-                    Err(err) => Err(err.into())
-                }
+        let mut true_color_image = None;
+
+        let living_world = File::open(path).map_err(error::Error::from).and_then(|file| {
+            read_living_world(path, &mut BufReader::new(file))
+        }).map(|(mut living_world, rgb_image, (ilbm_x_aspect, ilbm_y_aspect))| {
+            x_aspect = ilbm_x_aspect;
+            y_aspect = ilbm_y_aspect;
+            true_color_image = rgb_image;
+            if let Some(easing) = self.options.easing {
+                living_world.set_easing(easing);
             }
-            Err(err) => {
-                if err.kind() != ilbm::ErrorKind::UnsupportedFileFormat {
-                    Err(err.into())
-                } else if let Err(err) = reader.seek(std::io::SeekFrom::Start(0)) {
-                    Err(err.into())
-                } else {
-                    match serde_json::from_reader(&mut reader) {
-                        Ok(image) => Ok(image),
-                        Err(err) => Err(err.into())
-                    }
-                }
-            }
-        };
-        drop(reader);
+            living_world
+        });
 
         let mut message = String::new();
         let mut message_end_ts = Instant::now();
@@ -369,10 +916,19 @@ impl<'font> ColorCycleViewer<'font> {
                     let _ = write!(message, " {filename}: image of size {} x {} ",
                         living_world.base().width(),
                         living_world.base().height());
+                    if let Some(request) = open_request {
+                        request.error(format!("{}: image of size {} x {}", path.to_string_lossy(),
+                            living_world.base().width(),
+                            living_world.base().height()));
+                    }
                     x_aspect = 1;
                     y_aspect = 1;
+                    true_color_image = None;
                     CycleImage::new(None, IndexedImage::new(640, 480, Palette::default()), Box::new([])).into()
                 } else {
+                    if let Some(request) = open_request {
+                        request.ok();
+                    }
                     if self.options.osd {
                         if let Some(name) = living_world.name() {
                             let _ = write!(message, " {name} ({filename}) ");
@@ -388,6 +944,9 @@ impl<'font> ColorCycleViewer<'font> {
             Err(err) => {
                 message_end_ts += ERROR_MESSAGE_DISPLAY_DURATION;
                 let _ = write!(message, " {filename}: {err} ");
+                if let Some(request) = open_request {
+                    request.error(format!("{}: {err}", path.to_string_lossy()));
+                }
                 x_aspect = 1;
                 y_aspect = 1;
                 CycleImage::new(None, IndexedImage::new(640, 480, Palette::default()), Box::new([])).into()
@@ -408,6 +967,20 @@ impl<'font> ColorCycleViewer<'font> {
         let mut blended_palette = cycle_image.palette().clone();
         let mut cycled_palette1 = blended_palette.clone();
         let mut cycled_palette2 = blended_palette.clone();
+        // second set of palettes one cycle step further for dithered blending
+        let mut next_blended_palette = blended_palette.clone();
+        let mut next_palette1 = blended_palette.clone();
+        let mut next_palette2 = blended_palette.clone();
+        let mut dither_thresholds1 = [0u8; 256];
+        let mut dither_thresholds2 = [0u8; 256];
+        let mut blended_dither_thresholds = [0u8; 256];
+        // Without blending the palette is periodic, so it doesn't need to be
+        // cycled each frame. Living Worlds blend palettes continuously.
+        let palette_loop = if living_world.timeline().is_empty() {
+            cycle_image.palette_loop(MAX_PALETTE_LOOP_STATES)
+        } else {
+            None
+        };
 
         let mut frame_duration = Duration::from_secs_f64(1.0 / (self.options.fps as f64));
 
@@ -415,46 +988,98 @@ impl<'font> ColorCycleViewer<'font> {
         let fixed_height = img_height * y_aspect as u32;
 
         let texture_creator = self.canvas.texture_creator();
-        let mut texture = texture_creator.create_texture(
-            PixelFormatEnum::RGB24,
-            sdl2::render::TextureAccess::Streaming,
-            img_width, img_height
-        )?;
+        let mut texture;
+        let mut scaled_image;
+        let mut changed_pixels;
+        let mut crt_texture;
+        let mut crt_source = Vec::new();
+        // The palette the pixels in the texture were converted with, and if
+        // that texture is the CRT one. Textures keep their pixels, so nothing
+        // needs to be converted or uploaded again while that doesn't change.
+        let mut texture_palette: Option<(Palette, bool)>;
+
+        // The image that is converted to pixels, upscaled by the scale filter.
+        // For true-color images it only defines the size.
+        macro_rules! display_image {
+            () => {
+                scaled_image.as_ref().unwrap_or(living_world.base().indexed_image())
+            };
+        }
+
+        // (Re-)create everything that depends on the displayed image.
+        macro_rules! update_display_image {
+            () => {
+                scaled_image = if true_color_image.is_some() {
+                    None
+                } else {
+                    self.options.scale_filter.scale(living_world.base().indexed_image())
+                };
+                let display_image = display_image!();
 
-        if !self.was_resized {
-            if self.canvas.window().fullscreen_state() == FullscreenType::Off {
-                // Guess if the window is approximately cnetered on the screen and
-                // if yes, then re-center after resizing.
-                let window = self.canvas.window_mut();
-                let display_mode = self.video.current_display_mode(window.display_index()?)?;
-                let (win_width, win_height) = window.size();
-                let (win_x, win_y) = window.position();
-                let expected_x = (display_mode.w - win_width  as i32) / 2;
-                let expected_y = (display_mode.h - win_height as i32) / 2;
-                let is_centered =
-                    (expected_x - win_x).abs() <= display_mode.w / 20 &&
-                    (expected_y - win_y).abs() <= display_mode.h / 20;
+                texture = texture_creator.create_texture(
+                    PixelFormatEnum::RGB24,
+                    sdl2::render::TextureAccess::Streaming,
+                    display_image.width(), display_image.height()
+                )?;
 
-                window.set_size(fixed_width, fixed_height).log_error("window.set_size()");
+                changed_pixels = ChangedPixelsRenderer::new(display_image);
 
-                if is_centered {
-                    window.set_position(WindowPos::Centered, WindowPos::Centered);
+                // the pool is only created once a palette based image is converted
+                if let Some(conversion_pool) = &mut self.conversion_pool {
+                    conversion_pool.set_image(display_image);
                 }
+
+                crt_texture = None;
+                texture_palette = None;
+            };
+        }
+
+        update_display_image!();
+
+        // never resize an embedded native window
+        if !self.was_resized && self.options.window_id.is_none() &&
+           self.canvas.window().fullscreen_state() == FullscreenType::Off {
+            // Guess if the window is approximately cnetered on the screen and
+            // if yes, then re-center after resizing.
+            let window = self.canvas.window_mut();
+            let display_mode = self.video.current_display_mode(window.display_index()?)?;
+            let (win_width, win_height) = window.size();
+            let (win_x, win_y) = window.position();
+            let expected_x = (display_mode.w - win_width  as i32) / 2;
+            let expected_y = (display_mode.h - win_height as i32) / 2;
+            let is_centered =
+                (expected_x - win_x).abs() <= display_mode.w / 20 &&
+                (expected_y - win_y).abs() <= display_mode.h / 20;
+
+            window.set_size(fixed_width, fixed_height).log_error("window.set_size()");
+
+            if is_centered {
+                window.set_position(WindowPos::Centered, WindowPos::Centered);
             }
         }
 
         let mut message_texture = None;
+        let mut day_graph: Option<DayGraph> = None;
+        let mut timeline_labels: Option<(u16, Vec<Texture>)> = None;
+        let mut scrubbing = false;
+        let mut hud_textures: Vec<Texture> = Vec::new();
+        let mut hud_update_ts = Instant::now();
+        let mut last_palette: Option<Palette> = None;
+        let mut message_visible = false;
 
         self.canvas.set_integer_scale(true).log_error("canvas.set_integer_scale(true)");
 
         let loop_start_ts = Instant::now();
+        self.frame_stats.restart();
 
         loop {
             let frame_start_ts = Instant::now();
+            let mut screenshot: Option<(Option<PathBuf>, ScreenshotFormat, Option<control::Request>)> = None;
+            let mut redraw = false;
             let mut time_of_day = if let Some(current_time) = self.current_time {
                 current_time
             } else {
-                get_time_of_day_msec(self.time_speed)
+                self.get_time_of_day_msec()
             };
 
             macro_rules! show_message {
@@ -470,288 +1095,685 @@ impl<'font> ColorCycleViewer<'font> {
                 };
             }
 
+            // A screenshot requested via the control interface is answered
+            // when it is saved, so it needs an answer when that won't happen.
+            macro_rules! cancel_screenshot {
+                ($message:expr) => {
+                    if let Some((_, _, Some(request))) = screenshot.take() {
+                        request.error($message);
+                    }
+                };
+            }
+
+            macro_rules! return_action {
+                ($action:expr) => {{
+                    cancel_screenshot!("screenshot canceled, the image was closed");
+                    return Ok($action);
+                }};
+            }
+
             // process input
-            while let Some(event) = self.event_pump.poll_event() {
-                match event {
-                    Event::Window { win_event, .. } => {
-                        match win_event {
-                            WindowEvent::Resized(_, _) => {
+            loop {
+                let (command, repeat) = if let Some(event) = self.event_pump.poll_event() {
+                    redraw = true;
+                    match event {
+                        Event::Window { win_event, .. } => {
+                            if let WindowEvent::Resized(_, _) = win_event {
                                 self.was_resized = true;
                             }
-                            _ => {}
-                        }
-                    }
-                    Event::Quit { .. } => {
-                        return Ok(Action::Quit);
-                    }
-                    Event::KeyDown { keycode, keymod, repeat, .. } => {
-                        if let Some(keycode) = keycode {
-                            match keycode {
-                                Keycode::Q => {
-                                    // quit
-                                    return Ok(Action::Quit);
-                                }
-                                Keycode::ESCAPE => {
-                                    let window = self.canvas.window_mut();
-                                    if window.fullscreen_state() == FullscreenType::Off {
-                                        return Ok(Action::Quit);
-                                    }
-                                    window.set_fullscreen(FullscreenType::Off)?;
-                                }
-                                Keycode::B => {
-                                    // toggle blend mode
-                                    self.options.blend = !self.options.blend;
-
-                                    show_message!("Blend Mode: {}", if self.options.blend { "Enabled" } else { "Disabled" });
-                                }
-                                Keycode::C => {
-                                    // toggle cover/contain
-                                    self.options.cover = !self.options.cover;
-
-                                    if self.options.cover {
-                                        show_message!("Zoom to cover");
-                                    } else {
-                                        show_message!("Zoom to contain");
-                                    }
-                                }
-                                Keycode::O => {
-                                    // toggle OSD
-                                    if self.options.osd {
-                                        show_message!("OSD: Disabled");
-                                        self.options.osd = false;
-                                    } else {
-                                        self.options.osd = true;
-                                        show_message!("OSD: Enabled");
-                                    }
-                                }
-                                Keycode::PLUS | Keycode::KP_PLUS => {
-                                    // increase FPS
-                                    if self.options.fps < MAX_FPS {
-                                        self.options.fps += 1;
-                                        frame_duration = Duration::from_secs_f64(1.0 / self.options.fps as f64);
-
-                                        show_message!("FPS: {}", self.options.fps);
-                                    }
-                                }
-                                Keycode::MINUS | Keycode::KP_MINUS => {
-                                    // decrease FPS
-                                    if self.options.fps > 1 {
-                                        self.options.fps -= 1;
-                                        frame_duration = Duration::from_secs_f64(1.0 / self.options.fps as f64);
-
-                                        show_message!("FPS: {}", self.options.fps);
-                                    }
-                                }
-                                Keycode::N => {
-                                    // next file
-                                    let new_index = self.file_index + 1;
-                                    if new_index >= self.options.paths.len() {
-                                        show_message!("Already at last file.");
-                                    } else {
-                                        return Ok(Action::Goto(new_index));
-                                    }
-                                }
-                                Keycode::P => {
-                                    // previous file
-                                    if self.file_index == 0 {
-                                        show_message!("Already at first file.");
-                                    } else {
-                                        return Ok(Action::Goto(self.file_index - 1));
-                                    }
-                                }
-                                Keycode::A => {
-                                    // back in time
-                                    let time_step = if keymod.bits() & SHIFT != 0 { SMALL_TIME_STEP } else { TIME_STEP };
-                                    let rem = time_of_day % time_step;
-                                    let new_time = time_of_day - rem;
-                                    if new_time == time_of_day {
-                                        if new_time < time_step {
-                                            time_of_day = DAY_DURATION - time_step;
-                                        } else {
-                                            time_of_day = new_time - time_step;
-                                        }
-                                    } else {
-                                        time_of_day = new_time;
-                                    }
-                                    self.time_speed = 1;
-                                    self.current_time = Some(time_of_day);
-                                    let (hours, mins) = get_hours_mins(time_of_day);
-                                    show_message!("{hours}:{mins:02}");
-                                }
-                                Keycode::D => {
-                                    // forward in time
-                                    let time_step = if keymod.bits() & SHIFT != 0 { SMALL_TIME_STEP } else { TIME_STEP };
-                                    let rem = time_of_day % time_step;
-                                    let new_time = time_of_day - rem + time_step;
-                                    if new_time >= DAY_DURATION {
-                                        time_of_day = 0;
-                                    } else {
-                                        time_of_day = new_time;
-                                    }
-                                    self.time_speed = 1;
-                                    self.current_time = Some(time_of_day);
-                                    let (hours, mins) = get_hours_mins(time_of_day);
-                                    show_message!("{hours}:{mins:02}");
-                                }
-                                Keycode::S => {
-                                    // to current time
-                                    self.time_speed = 1;
-                                    self.current_time = None;
-                                    time_of_day = get_time_of_day_msec(self.time_speed);
-                                    let (hours, mins) = get_hours_mins(time_of_day);
-                                    show_message!("{hours}:{mins:02}");
-                                }
-                                Keycode::F | Keycode::F11 => {
-                                    // toggle fullscreen
-                                    if !repeat {
-                                        let window = self.canvas.window_mut();
-                                        let value = match window.fullscreen_state() {
-                                            FullscreenType::Desktop | FullscreenType::True => FullscreenType::Off,
-                                            FullscreenType::Off => FullscreenType::Desktop,
-                                        };
-                                        window.set_fullscreen(value).log_error("window.set_fullscreen()");
-                                    }
-                                }
-                                Keycode::W => {
-                                    // toggle fast forward
-                                    if self.time_speed == 1 {
-                                        self.time_speed = FAST_FORWARD_SPEED;
-                                        self.current_time = None;
-                                        time_of_day = get_time_of_day_msec(self.time_speed);
-                                        show_message!("Fast Forward: ON");
-                                    } else {
-                                        self.time_speed = 1;
-                                        self.current_time = Some(time_of_day);
-                                        show_message!("Fast Forward: OFF");
-                                    }
-                                }
-                                Keycode::I => {
-                                    // ILBM column swap
-                                    living_world.column_swap();
-                                }
-                                Keycode::UP => {
-                                    self.move_y(get_move_amount(keymod) * y_aspect as i32);
-                                }
-                                Keycode::DOWN => {
-                                    self.move_y(-get_move_amount(keymod) * y_aspect as i32);
-                                }
-                                Keycode::LEFT => {
-                                    self.move_x(get_move_amount(keymod) * x_aspect as i32);
-                                }
-                                Keycode::RIGHT => {
-                                    self.move_x(-get_move_amount(keymod) * x_aspect as i32);
-                                }
-                                Keycode::HOME => {
-                                    if self.options.cover {
-                                        if keymod.bits() & CTRL != 0 {
-                                            self.y = 0;
-                                        } else {
-                                            self.x = 0;
-                                        }
-                                        self.was_moved = true;
-                                    }
-                                }
-                                Keycode::END => {
-                                    if self.options.cover {
-                                        if keymod.bits() & CTRL != 0 {
-                                            self.y = i32::MIN;
-                                        } else {
-                                            self.x = i32::MIN;
-                                        }
-                                        self.was_moved = true;
-                                    }
-                                }
-                                Keycode::KP_0 | Keycode::NUM_0 => {
-                                    return Ok(Action::Goto(self.options.paths.len() - 1));
-                                }
-                                Keycode::KP_1 | Keycode::NUM_1 => {
-                                    return Ok(Action::Goto(0));
-                                }
-                                _ => {
-                                    let index = if keycode.into_i32() >= Keycode::KP_2.into_i32() && keycode.into_i32() <= Keycode::KP_9.into_i32() {
-                                        keycode.into_i32() - Keycode::KP_1.into_i32()
-                                    } else if keycode.into_i32() >= Keycode::NUM_2.into_i32() && keycode.into_i32() <= Keycode::NUM_9.into_i32() {
-                                        keycode.into_i32() - Keycode::NUM_1.into_i32()
-                                    } else {
-                                        0
-                                    };
-
-                                    if index > 0 {
-                                        if index as usize >= self.options.paths.len() {
-                                            show_message!("Only {} files opened!", self.options.paths.len());
-                                        } else {
-                                            return Ok(Action::Goto(index as usize));
-                                        }
-                                    }
-                                }
+                            continue;
+                        }
+                        Event::Quit { .. } => {
+                            return_action!(Action::Quit);
+                        }
+                        Event::KeyDown { .. } |
+                        Event::MouseButtonDown { .. } |
+                        Event::ControllerButtonDown { .. } if self.options.screensaver => {
+                            return_action!(Action::Quit);
+                        }
+                        Event::MouseMotion { .. } if self.options.screensaver && self.start_ts.elapsed() > SCREENSAVER_GRACE_PERIOD => {
+                            return_action!(Action::Quit);
+                        }
+                        Event::KeyDown { keycode: Some(keycode), keymod, repeat, .. } => {
+                            let Some(command) = self.options.hotkeys.get(keycode, keymod) else {
+                                continue;
+                            };
+                            (command, repeat)
+                        }
+                        Event::ControllerButtonDown { button, .. } => {
+                            let Some(command) = self.options.hotkeys.get_button(button) else {
+                                continue;
+                            };
+                            (command, false)
+                        }
+                        Event::ControllerDeviceAdded { which, .. } => {
+                            match self.game_controller.open(which) {
+                                Ok(controller) => self.controllers.push(controller),
+                                Err(err) => eprintln!("ERROR: game_controller.open({which}): {err}"),
+                            }
+                            continue;
+                        }
+                        Event::ControllerDeviceRemoved { which, .. } => {
+                            self.controllers.retain(|controller| controller.instance_id() != which);
+                            continue;
+                        }
+                        Event::MouseButtonUp { mouse_btn: MouseButton::Left, .. } => {
+                            scrubbing = false;
+                            continue;
+                        }
+                        Event::MouseButtonDown { mouse_btn: MouseButton::Left, x, y, .. } |
+                        Event::MouseMotion { x, y, .. } if self.options.timeline && !living_world.timeline().is_empty() => {
+                            let (canvas_width, canvas_height) = self.canvas.output_size()?;
+                            let (window_width, window_height) = self.canvas.window().size();
+                            // window and canvas size differ on high DPI displays
+                            let x = (x as i64 * canvas_width as i64 / window_width.max(1) as i64) as i32;
+                            let y = (y as i64 * canvas_height as i64 / window_height.max(1) as i64) as i32;
+                            let rect = timeline::timeline_rect(canvas_width, canvas_height);
+
+                            if matches!(event, Event::MouseButtonDown { .. }) {
+                                scrubbing = rect.contains_point((x, y));
+                            }
+
+                            if scrubbing {
+                                time_of_day = timeline::x_to_time(rect, x);
+                                self.current_time = Some(time_of_day);
+                                self.time_speed = self.options.time_speed;
+                                show_message!("{}", self.describe_time(&living_world, time_of_day));
+                            }
+                            continue;
+                        }
+                        Event::DropFile { filename, .. } => {
+                            return_action!(Action::OpenFile(filename.into(), None));
+                        }
+                        _ => continue,
+                    }
+                } else if let Some(request) = self.control.try_recv() {
+                    redraw = true;
+                    match request.command().clone() {
+                        ControlCommand::Command(command) => {
+                            request.ok();
+                            (command, false)
+                        }
+                        ControlCommand::Open(path) => {
+                            // answered once the file is loaded
+                            return_action!(Action::OpenFile(path, Some(request)));
+                        }
+                        ControlCommand::Goto(index) => {
+                            if index > self.options.paths.len() {
+                                request.error(format!("only {} files opened", self.options.paths.len()));
+                                continue;
+                            }
+                            request.ok();
+                            return_action!(Action::Goto(index - 1));
+                        }
+                        ControlCommand::SetTime(time) => {
+                            self.time_speed = self.options.time_speed;
+                            self.current_time = time;
+                            time_of_day = time.unwrap_or_else(|| self.get_time_of_day_msec());
+                            request.ok();
+                            show_message!("{}", self.describe_time(&living_world, time_of_day));
+                            continue;
+                        }
+                        ControlCommand::Blend(value) => {
+                            request.ok();
+                            if value == self.options.blend {
+                                continue;
+                            }
+                            (Command::ToggleBlend, false)
+                        }
+                        ControlCommand::Dither(value) => {
+                            request.ok();
+                            if value == (self.options.blend && self.options.dither) {
+                                continue;
+                            }
+                            (Command::ToggleDither, false)
+                        }
+                        ControlCommand::BlendSpace(value) => {
+                            request.ok();
+                            self.options.blend_space = value;
+                            show_message!("Blend Space: {}", value);
+                            continue;
+                        }
+                        ControlCommand::Osd(value) => {
+                            request.ok();
+                            if value == self.options.osd {
+                                continue;
+                            }
+                            (Command::ToggleOsd, false)
+                        }
+                        ControlCommand::Cover(value) => {
+                            request.ok();
+                            if value == self.options.cover {
+                                continue;
+                            }
+                            (Command::ToggleCover, false)
+                        }
+                        ControlCommand::FullScreen(value) => {
+                            request.ok();
+                            if value == (self.canvas.window().fullscreen_state() != FullscreenType::Off) {
+                                continue;
+                            }
+                            (Command::ToggleFullScreen, false)
+                        }
+                        ControlCommand::Screenshot(path) => {
+                            let format = path.as_deref()
+                                .and_then(ScreenshotFormat::from_path)
+                                .unwrap_or(ScreenshotFormat::PNG);
+                            // saved after the palette of this frame is computed
+                            cancel_screenshot!("screenshot superseded by another screenshot");
+                            screenshot = Some((path, format, Some(request)));
+                            continue;
+                        }
+                        ControlCommand::Status => {
+                            let (hours, mins) = get_hours_mins(time_of_day);
+                            let sun_times = self.get_sun_times().map(|sun_times| {
+                                let (rise_hours, rise_mins) = get_hours_mins(sun_times.sunrise());
+                                let (set_hours, set_mins) = get_hours_mins(sun_times.sunset());
+                                json!({
+                                    "sunrise": format!("{rise_hours}:{rise_mins:02}"),
+                                    "sunset": format!("{set_hours}:{set_mins:02}"),
+                                })
+                            });
+                            let scene_time = match self.get_sun_times() {
+                                Some(sun_times) => sun_times.scene_time(time_of_day),
+                                None => time_of_day,
+                            };
+                            let timeline = living_world.timeline_at(scene_time).map(|(palette1, palette2, mid)| {
+                                json!({
+                                    "from": living_world.palette_name(palette1),
+                                    "to": living_world.palette_name(palette2),
+                                    "blend": mid,
+                                })
+                            });
+                            request.reply(json!({
+                                "ok": true,
+                                "file": self.options.paths[self.file_index].to_string_lossy(),
+                                "index": self.file_index + 1,
+                                "files": self.options.paths.len(),
+                                "name": living_world.name(),
+                                "width": img_width,
+                                "height": img_height,
+                                "time": format!("{hours}:{mins:02}"),
+                                "time_speed": self.time_speed,
+                                "fixed_time": self.current_time.is_some(),
+                                "sun": sun_times,
+                                "timeline": timeline,
+                                "palettes": living_world.palette_names(),
+                                "fps": self.options.fps,
+                                "pacing": self.options.pacing.name(),
+                                "actual_fps": self.frame_stats.fps(),
+                                "dropped_frames": self.frame_stats.dropped(),
+                                "blend": self.options.blend,
+                                "dither": self.options.dither,
+                                "conversion": {
+                                    "threads": self.conversion_pool.as_ref().map(ConversionPool::threads).unwrap_or(1),
+                                    "frames": self.conversion_stats.frames(),
+                                    "avg_ms": self.conversion_stats.average().as_secs_f64() * 1000.0,
+                                    "max_ms": self.conversion_stats.max().as_secs_f64() * 1000.0,
+                                },
+                                "blend_space": self.options.blend_space.name(),
+                                "scale_filter": self.options.scale_filter.name(),
+                                "crt": self.options.crt,
+                                "osd": self.options.osd,
+                                "cover": self.options.cover,
+                                "full_screen": self.canvas.window().fullscreen_state() != FullscreenType::Off,
+                            }));
+                            continue;
+                        }
+                    }
+                } else {
+                    break;
+                };
+
+                match command {
+                    Command::Quit => {
+                        return_action!(Action::Quit);
+                    }
+                    Command::Exit => {
+                        let window = self.canvas.window_mut();
+                        if window.fullscreen_state() == FullscreenType::Off {
+                            return_action!(Action::Quit);
+                        }
+                        window.set_fullscreen(FullscreenType::Off)?;
+                    }
+                    Command::ToggleBlend => {
+                        self.options.blend = !self.options.blend;
+
+                        show_message!("Blend Mode: {}",
+                            if !self.options.blend { "Disabled" }
+                            else if self.options.dither { "Dither" }
+                            else { "Enabled" });
+                    }
+                    Command::ToggleDither => {
+                        if self.options.blend && self.options.dither {
+                            self.options.dither = false;
+                            show_message!("Blend Mode: Enabled");
+                        } else {
+                            self.options.blend = true;
+                            self.options.dither = true;
+                            show_message!("Blend Mode: Dither");
+                        }
+                    }
+                    Command::NextBlendSpace => {
+                        self.options.blend_space = self.options.blend_space.next();
+
+                        show_message!("Blend Space: {}", self.options.blend_space);
+                    }
+                    Command::ToggleCover => {
+                        self.options.cover = !self.options.cover;
+
+                        if self.options.cover {
+                            show_message!("Zoom to cover");
+                        } else {
+                            show_message!("Zoom to contain");
+                        }
+                    }
+                    Command::ToggleOsd => {
+                        if self.options.osd {
+                            show_message!("OSD: Disabled");
+                            self.options.osd = false;
+                        } else {
+                            self.options.osd = true;
+                            show_message!("OSD: Enabled");
+                        }
+                    }
+                    Command::ToggleTimeline => {
+                        if self.options.timeline {
+                            show_message!("Timeline: Disabled");
+                            self.options.timeline = false;
+                        } else {
+                            self.options.timeline = true;
+                            show_message!("Timeline: Enabled");
+                        }
+                        self.sdl.mouse().show_cursor(self.options.timeline && !self.options.screensaver);
+                    }
+                    Command::NextScaleFilter => {
+                        self.options.scale_filter = self.options.scale_filter.next();
+                        update_display_image!();
+                        show_message!("Scale Filter: {}", self.options.scale_filter);
+                    }
+                    Command::ToggleHud => {
+                        self.options.hud = !self.options.hud;
+                        hud_textures.clear();
+                    }
+                    Command::ToggleCrt => {
+                        if self.options.crt {
+                            show_message!("CRT: OFF");
+                            self.options.crt = false;
+                        } else {
+                            self.options.crt = true;
+                            show_message!("CRT: ON");
+                        }
+                    }
+                    Command::IncreaseFps => {
+                        if self.options.fps < MAX_FPS {
+                            self.options.fps += 1;
+                            frame_duration = Duration::from_secs_f64(1.0 / self.options.fps as f64);
+
+                            show_message!("FPS: {}", self.options.fps);
+                        }
+                    }
+                    Command::DecreaseFps => {
+                        if self.options.fps > 1 {
+                            self.options.fps -= 1;
+                            frame_duration = Duration::from_secs_f64(1.0 / self.options.fps as f64);
+
+                            show_message!("FPS: {}", self.options.fps);
+                        }
+                    }
+                    Command::NextFile => {
+                        let new_index = self.file_index + 1;
+                        if new_index >= self.options.paths.len() {
+                            show_message!("Already at last file.");
+                        } else {
+                            return_action!(Action::Goto(new_index));
+                        }
+                    }
+                    Command::PreviousFile => {
+                        if self.file_index == 0 {
+                            show_message!("Already at first file.");
+                        } else {
+                            return_action!(Action::Goto(self.file_index - 1));
+                        }
+                    }
+                    Command::OpenFile(index) => {
+                        if index > self.options.paths.len() {
+                            show_message!("Only {} files opened!", self.options.paths.len());
+                        } else if index > 0 {
+                            return_action!(Action::Goto(index - 1));
+                        }
+                    }
+                    Command::OpenLastFile => {
+                        return_action!(Action::Goto(self.options.paths.len() - 1));
+                    }
+                    Command::TimeBackward(mins) => {
+                        let time_step = mins.max(1) as u64 * 60 * 1000;
+                        let rem = time_of_day % time_step;
+                        let new_time = time_of_day - rem;
+                        if new_time == time_of_day {
+                            if new_time < time_step {
+                                time_of_day = DAY_DURATION - time_step;
+                            } else {
+                                time_of_day = new_time - time_step;
                             }
+                        } else {
+                            time_of_day = new_time;
+                        }
+                        self.time_speed = self.options.time_speed;
+                        self.current_time = Some(time_of_day);
+                        show_message!("{}", self.describe_time(&living_world, time_of_day));
+                    }
+                    Command::TimeForward(mins) => {
+                        let time_step = mins.max(1) as u64 * 60 * 1000;
+                        let rem = time_of_day % time_step;
+                        let new_time = time_of_day - rem + time_step;
+                        if new_time >= DAY_DURATION {
+                            time_of_day = 0;
+                        } else {
+                            time_of_day = new_time;
                         }
+                        self.time_speed = self.options.time_speed;
+                        self.current_time = Some(time_of_day);
+                        show_message!("{}", self.describe_time(&living_world, time_of_day));
                     }
-                    Event::DropFile { filename, .. } => {
-                        return Ok(Action::OpenFile(filename));
+                    Command::CurrentTime => {
+                        self.time_speed = self.options.time_speed;
+                        self.current_time = None;
+                        time_of_day = self.get_time_of_day_msec();
+                        show_message!("{}", self.describe_time(&living_world, time_of_day));
+                    }
+                    Command::ToggleFullScreen => {
+                        if !repeat {
+                            let window = self.canvas.window_mut();
+                            let value = match window.fullscreen_state() {
+                                FullscreenType::Desktop | FullscreenType::True => FullscreenType::Off,
+                                FullscreenType::Off => FullscreenType::Desktop,
+                            };
+                            window.set_fullscreen(value).log_error("window.set_fullscreen()");
+                        }
+                    }
+                    Command::ToggleFastForward => {
+                        if self.time_speed != FAST_FORWARD_SPEED {
+                            self.time_speed = FAST_FORWARD_SPEED;
+                            self.current_time = None;
+                            time_of_day = self.get_time_of_day_msec();
+                            show_message!("Fast Forward: ON");
+                        } else {
+                            self.time_speed = self.options.time_speed;
+                            self.current_time = Some(time_of_day);
+                            show_message!("Fast Forward: OFF");
+                        }
+                    }
+                    Command::ColumnSwap => {
+                        // ILBM column swap
+                        living_world.column_swap();
+                        update_display_image!();
+                    }
+                    Command::MoveUp(amount) => {
+                        self.move_y(amount * y_aspect as i32);
+                    }
+                    Command::MoveDown(amount) => {
+                        self.move_y(-amount * y_aspect as i32);
+                    }
+                    Command::MoveLeft(amount) => {
+                        self.move_x(amount * x_aspect as i32);
+                    }
+                    Command::MoveRight(amount) => {
+                        self.move_x(-amount * x_aspect as i32);
+                    }
+                    Command::MoveToLeft => {
+                        if self.options.cover {
+                            self.x = 0;
+                            self.was_moved = true;
+                        }
+                    }
+                    Command::MoveToRight => {
+                        if self.options.cover {
+                            self.x = i32::MIN;
+                            self.was_moved = true;
+                        }
+                    }
+                    Command::MoveToTop => {
+                        if self.options.cover {
+                            self.y = 0;
+                            self.was_moved = true;
+                        }
+                    }
+                    Command::MoveToBottom => {
+                        if self.options.cover {
+                            self.y = i32::MIN;
+                            self.was_moved = true;
+                        }
+                    }
+                    Command::Screenshot(format) => {
+                        if !repeat {
+                            cancel_screenshot!("screenshot superseded by another screenshot");
+                            screenshot = Some((None, format, None));
+                        }
                     }
-                    _ => {}
                 }
             }
 
+            if let Some(slideshow) = self.options.slideshow &&
+               self.options.paths.len() > 1 &&
+               frame_start_ts - loop_start_ts >= slideshow {
+                return_action!(Action::Goto((self.file_index + 1) % self.options.paths.len()));
+            }
+
+            // adaptive pacing must not sleep past the next slideshow image
+            let slideshow_deadline = self.options.slideshow
+                .filter(|_| self.options.paths.len() > 1)
+                .map(|slideshow| (loop_start_ts + slideshow).saturating_duration_since(frame_start_ts));
+
             // render frame
+            let palette_start_ts = Instant::now();
             let blend_cycle = (frame_start_ts - loop_start_ts).as_secs_f64();
             let palette;
-            if !living_world.timeline().is_empty() {
-                let mut palette1 = &living_world.palettes()[living_world.timeline().last().unwrap().palette_index()];
-                let mut palette2 = palette1;
-                let mut prev_time_of_day = 0;
-                let mut next_time_of_day = 0;
-    
-                // TODO: binary search?
-                let mut found = false;
-                for event in living_world.timeline() {
-                    prev_time_of_day = next_time_of_day;
-                    next_time_of_day = event.time_of_day() as u64 * 1000;
-                    palette1 = palette2;
-                    palette2 = &living_world.palettes()[event.palette_index()];
-                    if next_time_of_day > time_of_day {
-                        found = true;
-                        break;
-                    }
+            let cycles;
+            let dither = self.options.blend && self.options.dither;
+            let mut dither_palette = None;
+            let sun_times = if living_world.timeline().is_empty() {
+                None
+            } else {
+                self.get_sun_times()
+            };
+            let scene_time = if let Some(sun_times) = &sun_times {
+                sun_times.scene_time(time_of_day)
+            } else {
+                time_of_day
+            };
+
+            if let Some((palette1, palette2, blend_palettes)) = living_world.palettes_at(scene_time) {
+                if dither {
+                    cycled_palette1.apply_cycles_dithered_from(&mut next_palette1, &mut dither_thresholds1, palette1.palette(), palette1.cycles(), blend_cycle);
+                    cycled_palette2.apply_cycles_dithered_from(&mut next_palette2, &mut dither_thresholds2, palette2.palette(), palette2.cycles(), blend_cycle);
+
+                    crate::palette::blend(&next_palette1, &next_palette2, blend_palettes, self.options.blend_space, &mut next_blended_palette);
+
+                    crate::palette::blend_thresholds(&dither_thresholds1, &dither_thresholds2, blend_palettes, &mut blended_dither_thresholds);
+                    dither_palette = Some((&next_blended_palette, &blended_dither_thresholds));
+                } else {
+                    cycled_palette1.apply_cycles_from(palette1.palette(), palette1.cycles(), blend_cycle, self.options.blend, self.options.blend_space);
+                    cycled_palette2.apply_cycles_from(palette2.palette(), palette2.cycles(), blend_cycle, self.options.blend, self.options.blend_space);
                 }
 
-                if !found {
-                    prev_time_of_day = next_time_of_day;
-                    next_time_of_day = DAY_DURATION;
-                    palette1 = palette2;
-                    palette2 = &living_world.palettes()[living_world.timeline().first().unwrap().palette_index()];
+                crate::palette::blend(&cycled_palette1, &cycled_palette2, blend_palettes, self.options.blend_space, &mut blended_palette);
+
+                palette = &blended_palette;
+                cycles = palette1.cycles();
+            } else {
+                if dither {
+                    cycled_palette1.apply_cycles_dithered_from(&mut next_palette1, &mut dither_thresholds1, &blended_palette, living_world.base().cycles(), blend_cycle);
+                    dither_palette = Some((&next_palette1, &dither_thresholds1));
+                    palette = &cycled_palette1;
+                } else if let Some(palette_loop) = palette_loop.as_ref().filter(|_| !self.options.blend) {
+                    palette = palette_loop.palette_at(blend_cycle);
+                } else {
+                    cycled_palette1.apply_cycles_from(&blended_palette, living_world.base().cycles(), blend_cycle, self.options.blend, self.options.blend_space);
+                    palette = &cycled_palette1;
+                }
+                cycles = living_world.base().cycles();
+            }
+
+            let palette_duration = palette_start_ts.elapsed();
+
+            if let Some((path, format, request)) = screenshot {
+                let res = if true_color_image.is_some() && !screenshot::supports_true_color(format) {
+                    Err(error::Error::new(format!("{format} screenshots of true-color images are not supported")))
+                } else {
+                    let stem = self.options.paths[self.file_index].file_stem()
+                        .map(|stem| stem.to_string_lossy().into_owned())
+                        .unwrap_or_else(|| "screenshot".to_owned());
+
+                    screenshot::create_screenshot_file(path, &self.options.screenshot_dir, &stem, format).and_then(|(path, file)| {
+                        let res = if let Some(true_color_image) = &true_color_image {
+                            screenshot::save_true_color_screenshot(file, true_color_image)
+                        } else {
+                            screenshot::save_screenshot(file, format, living_world.base().indexed_image(), palette, dither_palette, cycles)
+                        };
+                        match res {
+                            Ok(()) => Ok(path),
+                            Err(err) => Err(error::Error::with_source(path.to_string_lossy().into_owned(), Box::new(err))),
+                        }
+                    })
+                };
+                match res {
+                    Ok(path) => {
+                        show_message!("Saved {}", path.to_string_lossy());
+                        if let Some(request) = request {
+                            request.reply(json!({ "ok": true, "path": path.to_string_lossy() }));
+                        }
+                    }
+                    Err(err) => {
+                        eprintln!("ERROR: saving screenshot: {err}");
+                        show_message!("Error saving screenshot: {err}");
+                        if let Some(request) = request {
+                            request.error(err.to_string());
+                        }
+                    }
                 }
+            }
 
-                let current_span = next_time_of_day - prev_time_of_day;
-                let time_in_span = time_of_day - prev_time_of_day;
-                let blend_palettes = time_in_span as f64 / current_span as f64;
+            // the frame after a message expired has to be drawn without it
+            let message_was_visible = message_visible;
+            message_visible = message_end_ts >= frame_start_ts;
+            redraw |= message_was_visible && !message_visible;
 
-                cycled_palette1.apply_cycles_from(palette1.palette(), palette1.cycles(), blend_cycle, self.options.blend);
-                cycled_palette2.apply_cycles_from(palette2.palette(), palette2.cycles(), blend_cycle, self.options.blend);
+            let adaptive =
+                self.options.pacing == Pacing::Adaptive &&
+                !self.options.blend &&
+                !self.options.hud &&
+                message_end_ts < frame_start_ts;
 
-                crate::palette::blend(&cycled_palette1, &cycled_palette2, blend_palettes, &mut blended_palette);
+            if adaptive {
+                // the timeline cursor moves
+                redraw |= self.options.timeline && !living_world.timeline().is_empty();
 
-                palette = &blended_palette;
+                if !redraw && last_palette.as_ref() == Some(palette) {
+                    if let Some(delay) = Pacing::Adaptive.frame_delay(frame_duration, slideshow_deadline, || self.next_palette_change(&living_world, blend_cycle, time_of_day)) {
+                        wait_for_event((frame_start_ts + delay).saturating_duration_since(Instant::now()));
+                    }
+                    continue;
+                }
+            }
+
+            if let Some(last_palette) = &mut last_palette {
+                last_palette.clone_from(palette);
             } else {
-                cycled_palette1.apply_cycles_from(&blended_palette, living_world.base().cycles(), blend_cycle, self.options.blend);
-                palette = &cycled_palette1;
+                last_palette = Some(palette.clone());
             }
 
-            texture.with_lock(None, |pixels, pitch| {
-                let indexed_image = living_world.base().indexed_image();
-                for y in 0..img_height {
-                    let y_offset = y as usize * pitch;
-                    for x in 0..img_width {
-                        let index = indexed_image.get_index(x, y);
-                        let pixel_offset = y_offset + 3 * x as usize;
-                        let Rgb([r, g, b]) = palette[index];
-                        pixels[pixel_offset    ] = r;
-                        pixels[pixel_offset + 1] = g;
-                        pixels[pixel_offset + 2] = b;
+            let conversion_start_ts = Instant::now();
+            let display_image = display_image!();
+            let display_width  = display_image.width();
+            let display_height = display_image.height();
+
+            if self.options.crt && crt_texture.is_none() {
+                match texture_creator.create_texture(
+                    PixelFormatEnum::RGB24,
+                    sdl2::render::TextureAccess::Streaming,
+                    display_width * CRT_SCALE, display_height * CRT_SCALE
+                ) {
+                    Ok(new_texture) => crt_texture = Some(new_texture),
+                    Err(err) => {
+                        eprintln!("ERROR: creating CRT texture: {err}");
+                        show_message!("CRT: {err}");
+                        self.options.crt = false;
                     }
                 }
-            })?;
+            }
+
+            let crt_active = self.options.crt && crt_texture.is_some();
+            let unchanged = dither_palette.is_none() && texture_palette.as_ref().is_some_and(
+                |(texture_palette, texture_crt)| *texture_crt == crt_active && texture_palette == palette);
+
+            if !unchanged && true_color_image.is_none() && self.options.threads > 1 && self.conversion_pool.is_none() {
+                self.conversion_pool = Some(ConversionPool::new(self.options.threads, display_image)?);
+            }
+
+            let mut uploaded = unchanged;
+            if !unchanged && !crt_active && dither_palette.is_none() && true_color_image.is_none() {
+                // Only upload the rows that changed, unless the texture was
+                // last written by another path.
+                if texture_palette.as_ref().is_none_or(|(_, texture_crt)| *texture_crt) {
+                    changed_pixels.invalidate();
+                }
+                let rows = changed_pixels.update(display_image, palette, self.conversion_pool.as_mut())?;
+                if !rows.is_empty() {
+                    let pitch = changed_pixels.pitch();
+                    let rect = Rect::new(0, rows.start as i32, display_width, rows.len() as u32);
+                    texture.update(rect, &changed_pixels.pixels()[rows.start as usize * pitch..rows.end as usize * pitch], pitch)?;
+                }
+                uploaded = true;
+            }
+
+            let conversion_pool = &mut self.conversion_pool;
+            let mut convert = |pixels: &mut [u8], pitch: usize| -> Result<(), String> {
+                if let Some(true_color_image) = &true_color_image {
+                    let row_len = true_color_image.pitch();
+                    for (y, row) in true_color_image.data().chunks_exact(row_len).enumerate() {
+                        pixels[y * pitch..y * pitch + row_len].copy_from_slice(row);
+                    }
+                } else if let Some((next_palette, thresholds)) = dither_palette {
+                    // dithering needs the pixel position, so it is always done on the CPU
+                    if let Some(conversion_pool) = conversion_pool {
+                        conversion_pool.convert(pixels, pitch, palette, Some((next_palette, thresholds)))?;
+                    } else {
+                        render::convert_pixels_dithered(pixels, pitch, display_image, palette, next_palette, thresholds);
+                    }
+                } else {
+                    changed_pixels.update(display_image, palette, conversion_pool.as_mut())?;
+                    let row_len = changed_pixels.pitch();
+                    for (y, row) in changed_pixels.pixels().chunks_exact(row_len).enumerate() {
+                        pixels[y * pitch..y * pitch + row_len].copy_from_slice(row);
+                    }
+                }
+                Ok(())
+            };
+
+            let frame_texture = if let Some(crt_texture) = crt_texture.as_mut().filter(|_| self.options.crt) {
+                // the CRT effect needs the converted pixels as input
+                if !unchanged {
+                    let src_pitch = display_width as usize * 3;
+                    crt_source.resize(src_pitch * display_height as usize, 0);
+                    convert(&mut crt_source, src_pitch)?;
+                    crt_texture.with_lock(None, |pixels, pitch| {
+                        crt::apply_crt(&crt_source, src_pitch, display_width, display_height, pixels, pitch);
+                    })?;
+                }
+                crt_texture
+            } else {
+                if !uploaded {
+                    texture.with_lock(None, |pixels, pitch| convert(pixels, pitch))??;
+                }
+                &mut texture
+            };
+            let pixels_duration = conversion_start_ts.elapsed();
+            if !unchanged {
+                self.conversion_stats.record(pixels_duration);
+                // dithered pixels depend on more than the palette
+                texture_palette = dither_palette.is_none().then(|| (palette.clone(), crt_active));
+            }
 
             self.canvas.clear();
             let (canvas_width, canvas_height) = self.canvas.output_size()?;
@@ -809,33 +1831,63 @@ impl<'font> ColorCycleViewer<'font> {
                 } else { 0 };
             }
 
-            self.canvas.copy(&texture, None, Rect::new(draw_x, draw_y, draw_width, draw_height))?;
+            self.canvas.copy(frame_texture, None, Rect::new(draw_x, draw_y, draw_width, draw_height))?;
+
+            if self.options.timeline && !living_world.timeline().is_empty() {
+                // draw timeline
+                if day_graph.as_ref().is_none_or(|day_graph|
+                        day_graph.sun_times() != sun_times.as_ref() ||
+                        day_graph.blend_space() != self.options.blend_space) {
+                    day_graph = Some(DayGraph::new(&living_world, sun_times, self.options.blend_space));
+                }
+                let day_graph = day_graph.as_ref().unwrap();
+                let rect = timeline::timeline_rect(canvas_width, canvas_height);
+                day_graph.draw(&mut self.canvas, rect, time_of_day)?;
+
+                // same size as OSD messages so the font isn't reloaded all the time
+                let font_size = (canvas_height / 30) as u16;
+                if timeline_labels.as_ref().is_none_or(|(size, _)| *size != font_size) {
+                    let font = self.get_font(font_size)?;
+                    let mut labels = Vec::with_capacity(living_world.timeline().len());
+                    for event in living_world.timeline() {
+                        let name = living_world.palette_name(event.palette_index()).unwrap_or("?");
+                        let surface = font.render(name)
+                            .shaded(Color::RGB(255, 255, 255), Color::RGB(0, 0, 0))?;
+                        labels.push(texture_creator.create_texture_from_surface(surface)?);
+                    }
+                    timeline_labels = Some((font_size, labels));
+                }
+
+                // skip labels that would overlap the previous one
+                let label_y = rect.bottom() + (rect.height() / 4) as i32;
+                let mut min_x = i32::MIN;
+                for (label, &marker) in timeline_labels.as_ref().unwrap().1.iter().zip(day_graph.markers()) {
+                    let TextureQuery { width, height, .. } = label.query();
+                    let x = (timeline::time_to_x(rect, marker) - width as i32 / 2)
+                        .clamp(rect.x(), (rect.right() - width as i32).max(rect.x()));
+                    if x < min_x {
+                        continue;
+                    }
+                    self.canvas.copy(label, None, Rect::new(x, label_y, width, height))?;
+                    min_x = x + width as i32 + height as i32 / 2;
+                }
+            }
 
-            if self.time_speed != 1 && message.is_empty() {
-                let (hours, mins) = get_hours_mins(time_of_day);
-                show_message!("{hours}:{mins:02}");
+            if self.current_time.is_none() && self.time_speed != 1 && message.is_empty() {
+                show_message!("{}", self.describe_time(&living_world, time_of_day));
             }
 
             if message_end_ts >= frame_start_ts {
                 // draw OSD message
                 let new_font_size = (canvas_height / 30) as u16;
                 if new_font_size != self.font_size {
-                    self.font = None;
                     message_texture = None;
                 }
 
                 let texture = if let Some(texture) = &message_texture {
                     texture
                 } else {
-                    let font = if let Some(font) = &self.font {
-                        font
-                    } else {
-                        self.font = Some(self.options.ttf.load_font_from_rwops(
-                            RWops::from_bytes(HACK_FONT)?,
-                            new_font_size)?);
-                        self.font_size = new_font_size;
-                        self.font.as_ref().unwrap()
-                    };
+                    let font = self.get_font(new_font_size)?;
 
                     let surface = font.render(&message)
                         .shaded(Color::RGB(255, 255, 255), Color::RGB(0, 0, 0))?;
@@ -854,12 +1906,45 @@ impl<'font> ColorCycleViewer<'font> {
                     width, height))?;
             }
 
+            if self.options.hud {
+                // draw frame timing statistics, updated a few times a second to keep them readable
+                let font_size = (canvas_height / 30) as u16;
+                if hud_textures.is_empty() || font_size != self.font_size || frame_start_ts >= hud_update_ts {
+                    hud_update_ts = frame_start_ts + HUD_UPDATE_INTERVAL;
+                    let lines = self.frame_stats.hud_lines(self.options.fps);
+                    let font = self.get_font(font_size)?;
+                    hud_textures.clear();
+                    for line in &lines {
+                        let surface = font.render(line)
+                            .shaded(Color::RGB(255, 255, 255), Color::RGB(0, 0, 0))?;
+                        hud_textures.push(texture_creator.create_texture_from_surface(surface)?);
+                    }
+                }
+
+                let mut y = font_size as i32 / 2;
+                for texture in &hud_textures {
+                    let TextureQuery { width, height, .. } = texture.query();
+                    self.canvas.copy(texture, None, Rect::new(font_size as i32 / 2, y, width, height))?;
+                    y += height as i32;
+                }
+            }
+
+            let present_start_ts = Instant::now();
             self.canvas.present();
+            self.frame_stats.record(frame_start_ts, frame_duration, palette_duration, pixels_duration, present_start_ts.elapsed());
 
-            // sleep for rest of frame
-            let elapsed = frame_start_ts.elapsed();
-            if frame_duration > elapsed && !interruptable_sleep(frame_duration - elapsed) {
-                return Ok(Action::Quit);
+            // sleep for rest of frame, presenting with vsync already waited
+            let pacing = match self.options.pacing {
+                Pacing::Adaptive if !adaptive => Pacing::Fixed,
+                pacing => pacing,
+            };
+            if let Some(delay) = pacing.frame_delay(frame_duration, slideshow_deadline, || self.next_palette_change(&living_world, blend_cycle, time_of_day)) {
+                let elapsed = frame_start_ts.elapsed();
+                if adaptive {
+                    wait_for_event(delay.saturating_sub(elapsed));
+                } else if delay > elapsed && !interruptable_sleep(delay - elapsed) {
+                    return Ok(Action::Quit);
+                }
             }
         }
     }
@@ -901,65 +1986,264 @@ impl<'font> ColorCycleViewer<'font> {
             self.was_moved = true;
         }
     }
-}
 
-// const ALT: u16 = Mod::LALTMOD.bits() | Mod::RALTMOD.bits();
-const SHIFT: u16 = Mod::LSHIFTMOD.bits() | Mod::RSHIFTMOD.bits();
-const CTRL: u16 = Mod::LCTRLMOD.bits() | Mod::RCTRLMOD.bits();
+    fn get_font(&mut self, font_size: u16) -> Result<&sdl2::ttf::Font<'font, 'static>, error::Error> {
+        if self.font.is_none() || font_size != self.font_size {
+            self.font = Some(self.options.ttf.load_font_from_rwops(
+                RWops::from_bytes(HACK_FONT)?,
+                font_size)?);
+            self.font_size = font_size;
+        }
+        Ok(self.font.as_ref().unwrap())
+    }
 
-#[inline]
-fn get_move_amount(keymod: Mod) -> i32 {
-    let keymod = keymod.bits();
-    if keymod & CTRL != 0 {
-        10
-    } else {
-        1
+    /// Time until the palette changes next, see
+    /// [`pacing::next_palette_change()`]. Only meaningful without blend mode.
+    fn next_palette_change(&self, living_world: &LivingWorld, blend_cycle: f64, time_of_day: u64) -> Duration {
+        let sun_times = if living_world.timeline().is_empty() {
+            None
+        } else {
+            self.get_sun_times()
+        };
+        let time_speed = if self.current_time.is_none() { Some(self.time_speed) } else { None };
+
+        pacing::next_palette_change(living_world, blend_cycle, time_of_day, time_speed, |time_of_day| match &sun_times {
+            Some(sun_times) => sun_times.scene_time(time_of_day),
+            None => time_of_day,
+        })
+    }
+
+    #[inline]
+    fn get_time_of_day_msec(&self) -> u64 {
+        get_time_of_day_msec(self.options.utc_offset, self.options.time_offset, self.time_speed)
+    }
+
+    /// Time of day and for Living Worlds the palettes that are currently
+    /// blended, e.g. "18:30 Dusk → Night 40%".
+    fn describe_time(&self, living_world: &LivingWorld, time_of_day: u64) -> String {
+        let (hours, mins) = get_hours_mins(time_of_day);
+        let scene_time = match self.get_sun_times() {
+            Some(sun_times) if !living_world.timeline().is_empty() => sun_times.scene_time(time_of_day),
+            _ => time_of_day,
+        };
+
+        let Some((palette1, palette2, mid)) = living_world.timeline_at(scene_time) else {
+            return format!("{hours}:{mins:02}");
+        };
+
+        let name1 = living_world.palette_name(palette1).unwrap_or("?");
+        let percent = (mid * 100.0).round() as u32;
+        if palette1 == palette2 || percent == 0 {
+            return format!("{hours}:{mins:02} {name1}");
+        }
+
+        let name2 = living_world.palette_name(palette2).unwrap_or("?");
+        format!("{hours}:{mins:02} {name1} → {name2} {percent}%")
+    }
+
+    /// Sunrise and sunset of the current date at the configured location.
+    fn get_sun_times(&self) -> Option<SunTimes> {
+        let location = self.options.location.as_ref()?;
+        let (now, utc_offset) = get_clock_msec(self.options.utc_offset)?;
+        Some(SunTimes::new(location, now + self.options.time_offset as i128, utc_offset))
     }
 }
 
 enum Action {
     Goto(usize),
     Quit,
-    OpenFile(String),
+    OpenFile(PathBuf, Option<control::Request>),
 }
 
-fn get_time_of_day_msec(time_speed: u64) -> u64 {
+/// Milliseconds since the Unix epoch (UTC) and the UTC offset in seconds.
+/// Uses the local time zone if `utc_offset` is `None`.
+fn get_clock_msec(utc_offset: Option<i64>) -> Option<(i128, i64)> {
     #[cfg(not(windows))]
     unsafe {
         let mut tod = MaybeUninit::<libc::timespec>::zeroed();
         if libc::clock_gettime(libc::CLOCK_REALTIME, tod.as_mut_ptr()) != 0 {
-            return 0;
+            return None;
         }
         let tod = tod.assume_init_ref();
-        let mut tm = MaybeUninit::<libc::tm>::zeroed();
-        if libc::localtime_r(&tod.tv_sec, tm.as_mut_ptr()).is_null() {
-            return 0;
+        let gmtoff = if let Some(utc_offset) = utc_offset {
+            utc_offset
+        } else {
+            let mut tm = MaybeUninit::<libc::tm>::zeroed();
+            if libc::localtime_r(&tod.tv_sec, tm.as_mut_ptr()).is_null() {
+                return None;
+            }
+            #[allow(clippy::unnecessary_cast)]
+            let gmtoff = tm.assume_init_ref().tm_gmtoff as i64;
+            gmtoff
+        };
+
+        Some((tod.tv_sec as i128 * 1000 + tod.tv_nsec as i128 / 1_000_000, gmtoff))
+    }
+
+    #[cfg(windows)]
+    unsafe {
+        fn to_msec(tm: &winapi::um::minwinbase::SYSTEMTIME) -> i128 {
+            // days from civil, see http://howardhinnant.github.io/date_algorithms.html
+            let year = tm.wYear as i128 - if tm.wMonth <= 2 { 1 } else { 0 };
+            let era = year.div_euclid(400);
+            let yoe = year - era * 400;
+            let month = tm.wMonth as i128;
+            let doy = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + tm.wDay as i128 - 1;
+            let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+            let days = era * 146097 + doe - 719468;
+
+            days * DAY_DURATION as i128 +
+            tm.wHour as i128 * 60 * 60 * 1000 +
+            tm.wMinute as i128 * 60 * 1000 +
+            tm.wSecond as i128 * 1000 +
+            tm.wMilliseconds as i128
         }
-        let tm = tm.assume_init_ref();
-        let mut now = Duration::new(tod.tv_sec as u64, tod.tv_nsec as u32);
 
-        if tm.tm_gmtoff > 0 {
-            now += Duration::from_secs(tm.tm_gmtoff as u64);
+        let mut utc = MaybeUninit::<winapi::um::minwinbase::SYSTEMTIME>::zeroed();
+        winapi::um::sysinfoapi::GetSystemTime(utc.as_mut_ptr());
+        let utc = to_msec(utc.assume_init_ref());
+
+        let gmtoff = if let Some(utc_offset) = utc_offset {
+            utc_offset
         } else {
-            now -= Duration::from_secs((-tm.tm_gmtoff) as u64);
-        }
+            let mut local = MaybeUninit::<winapi::um::minwinbase::SYSTEMTIME>::zeroed();
+            winapi::um::sysinfoapi::GetLocalTime(local.as_mut_ptr());
+            // round to minutes, the two calls don't happen at the same millisecond
+            ((to_msec(local.assume_init_ref()) - utc) as f64 / 60_000.0).round() as i64 * 60
+        };
 
-        ((now.as_millis() * time_speed as u128) % DAY_DURATION as u128) as u64
+        Some((utc, gmtoff))
+    }
+}
+
+/// Milliseconds since midnight of the clock sped up by `time_speed` and
+/// then shifted by `time_offset` milliseconds. Uses the local time zone if
+/// `utc_offset` (in seconds) is `None`.
+fn get_time_of_day_msec(utc_offset: Option<i64>, time_offset: i64, time_speed: u64) -> u64 {
+    let Some((now, gmtoff)) = get_clock_msec(utc_offset) else {
+        return 0;
+    };
+
+    time_of_day_msec(now, gmtoff, time_offset, time_speed)
+}
+
+/// Time of day of the simulated clock at `now` (milliseconds since the Unix
+/// epoch) in the time zone `gmtoff` (seconds east of UTC). The offset is
+/// not sped up, so it shifts the clock by the same amount at any speed.
+fn time_of_day_msec(now: i128, gmtoff: i64, time_offset: i64, time_speed: u64) -> u64 {
+    ((now + gmtoff as i128 * 1000) * time_speed as i128 + time_offset as i128).rem_euclid(DAY_DURATION as i128) as u64
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TimeZone {
+    /// Fixed offset from UTC in seconds.
+    Fixed(i64),
+    /// Name of a time zone from the system's time zone database.
+    Named(String),
+}
+
+/// Parse `UTC`, `UTC+HH[:MM]`, `+HH[:MM]`, `-HHMM` etc. or a time zone name.
+fn parse_time_zone(value: &str) -> Result<TimeZone, String> {
+    let value = value.trim();
+    let offset = value.strip_prefix("UTC").or_else(|| value.strip_prefix("GMT")).unwrap_or(value);
+
+    if offset.is_empty() || offset == "Z" {
+        return Ok(TimeZone::Fixed(0));
+    }
+
+    let (sign, offset) = if let Some(offset) = offset.strip_prefix('+') {
+        (1, offset)
+    } else if let Some(offset) = offset.strip_prefix('-') {
+        (-1, offset)
+    } else if value.len() != offset.len() {
+        return Err(format!("illegal time zone: {value:?}"));
+    } else {
+        return Ok(TimeZone::Named(value.to_owned()));
+    };
+
+    let (hours, mins) = if let Some((hours, mins)) = offset.split_once(':') {
+        (hours, mins)
+    } else if offset.len() > 2 {
+        offset.split_at(offset.len() - 2)
+    } else {
+        (offset, "0")
+    };
+
+    let (Ok(hours), Ok(mins)) = (hours.parse::<i64>(), mins.parse::<i64>()) else {
+        return Err(format!("illegal time zone: {value:?}"));
+    };
+
+    if hours > 14 || mins >= 60 {
+        return Err(format!("illegal time zone: {value:?}, offset out of range"));
+    }
+
+    Ok(TimeZone::Fixed(sign * (hours * 60 + mins) * 60))
+}
+
+/// Parse `[-]HH:MM` or `[-]HH:MM:SS` into milliseconds.
+fn parse_time_offset(value: &str) -> Result<i64, String> {
+    let value = value.trim();
+    let (sign, offset) = if let Some(offset) = value.strip_prefix('-') {
+        (-1, offset)
+    } else {
+        (1, value.strip_prefix('+').unwrap_or(value))
+    };
+
+    Ok(sign * parse_time_of_day(offset)? as i64)
+}
+
+/// Use a named time zone for the local time. Must be called before any
+/// threads are started.
+#[cfg(unix)]
+fn set_time_zone(name: &str) -> Result<(), String> {
+    unsafe extern "C" {
+        fn tzset();
+    }
+
+    // glibc silently falls back to UTC for unknown names
+    let zoneinfo = std::path::Path::new("/usr/share/zoneinfo");
+    if zoneinfo.is_dir() && !zoneinfo.join(name).is_file() {
+        return Err(format!("unknown time zone: {name:?}"));
     }
 
-    #[cfg(windows)]
     unsafe {
-        let mut tm = MaybeUninit::<winapi::um::minwinbase::SYSTEMTIME>::zeroed();
-        winapi::um::sysinfoapi::GetLocalTime(tm.as_mut_ptr());
-        let tm = tm.assume_init_ref();
+        std::env::set_var("TZ", name);
+        tzset();
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_time_zone(name: &str) -> Result<(), String> {
+    Err(format!("time zone names are not supported on this platform: {name:?}, use an offset like UTC+02:00"))
+}
+
+/// Parse `HH:MM` or `HH:MM:SS` into milliseconds since midnight.
+pub fn parse_time_of_day(value: &str) -> Result<u64, String> {
+    let parts: Vec<&str> = value.trim().split(':').collect();
+    if parts.len() < 2 || parts.len() > 3 {
+        return Err(format!("illegal time of day: {value:?}, expected HH:MM or HH:MM:SS"));
+    }
+
+    let mut time_of_day = 0;
+    for (part, max) in parts.iter().zip([24, 60, 60]) {
+        let Ok(number) = part.parse::<u64>() else {
+            return Err(format!("illegal time of day: {value:?}, expected HH:MM or HH:MM:SS"));
+        };
+
+        if number >= max {
+            return Err(format!("illegal time of day: {value:?}, value out of range: {number}"));
+        }
 
-        (
-            tm.wHour as u64 * 60 * 60 * 1000 +
-            tm.wMinute as u64 * 60 * 1000 +
-            tm.wSecond as u64 * 1000 +
-            tm.wMilliseconds as u64
-        ) * time_speed % DAY_DURATION
+        time_of_day = time_of_day * 60 + number;
     }
+
+    if parts.len() == 2 {
+        time_of_day *= 60;
+    }
+
+    Ok(time_of_day * 1000)
 }
 
 fn get_hours_mins(time_of_day: u64) -> (u32, u32) {
@@ -1001,3 +2285,62 @@ where E: std::fmt::Display {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: i64 = 60 * 1000;
+    const HOUR: i64 = 60 * MINUTE;
+
+    /// 2024-06-21 12:00 UTC
+    const NOON_UTC: i128 = 1_718_971_200_000;
+
+    #[test]
+    fn time_offset() {
+        assert_eq!(parse_time_offset("00:10"), Ok(10 * MINUTE));
+        assert_eq!(parse_time_offset("+1:30"), Ok(HOUR + 30 * MINUTE));
+        assert_eq!(parse_time_offset("-3:00"), Ok(-3 * HOUR));
+        assert_eq!(parse_time_offset(" -00:00:30 "), Ok(-30 * 1000));
+        assert!(parse_time_offset("24:00").is_err());
+        assert!(parse_time_offset("12").is_err());
+        assert!(parse_time_offset("--1:00").is_err());
+        assert!(parse_time_offset("1h").is_err());
+    }
+
+    #[test]
+    fn time_zone() {
+        assert_eq!(parse_time_zone("UTC"), Ok(TimeZone::Fixed(0)));
+        assert_eq!(parse_time_zone("Z"), Ok(TimeZone::Fixed(0)));
+        assert_eq!(parse_time_zone("UTC+02:00"), Ok(TimeZone::Fixed(2 * 60 * 60)));
+        assert_eq!(parse_time_zone("GMT+2"), Ok(TimeZone::Fixed(2 * 60 * 60)));
+        assert_eq!(parse_time_zone("-05:30"), Ok(TimeZone::Fixed(-(5 * 60 + 30) * 60)));
+        assert_eq!(parse_time_zone("+0545"), Ok(TimeZone::Fixed((5 * 60 + 45) * 60)));
+        assert_eq!(parse_time_zone("Europe/Vienna"), Ok(TimeZone::Named("Europe/Vienna".to_owned())));
+
+        assert!(parse_time_zone("UTC+15").is_err());
+        assert!(parse_time_zone("+02:60").is_err());
+        assert!(parse_time_zone("UTC+ab").is_err());
+        assert!(parse_time_zone("UTCX").is_err());
+        assert!(parse_time_zone("-").is_err());
+    }
+
+    #[test]
+    fn offset_and_speed() {
+        let noon = 12 * HOUR as u64;
+        assert_eq!(time_of_day_msec(NOON_UTC, 0, 0, 1), noon);
+        assert_eq!(time_of_day_msec(NOON_UTC, 2 * 60 * 60, 0, 1), noon + 2 * HOUR as u64);
+        assert_eq!(time_of_day_msec(NOON_UTC, 0, -13 * HOUR, 1), 23 * HOUR as u64);
+
+        // the offset is not sped up
+        let fast = time_of_day_msec(NOON_UTC, 0, 0, 144);
+        assert_eq!(time_of_day_msec(NOON_UTC, 0, 10 * MINUTE, 144), (fast + 10 * MINUTE as u64) % DAY_DURATION);
+        assert_eq!(time_of_day_msec(NOON_UTC, 0, -10 * MINUTE, 144), (fast + DAY_DURATION - 10 * MINUTE as u64) % DAY_DURATION);
+
+        // the time zone is part of the clock that is sped up
+        assert_eq!(time_of_day_msec(NOON_UTC, 60 * 60, 0, 144), (fast + 144 * HOUR as u64) % DAY_DURATION);
+
+        // one real second is 144 simulated seconds
+        assert_eq!(time_of_day_msec(NOON_UTC + 1000, 0, 0, 144), (fast + 144 * 1000) % DAY_DURATION);
+    }
+}
@@ -0,0 +1,277 @@
+// color-cycle - render color cycle images
+// Copyright (C) 2025  Mathias Panzenböck
+// 
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+// 
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+// 
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// When the palette changes next, so the viewer only needs to wake up then.
+
+use std::{fmt::Display, str::FromStr, time::Duration};
+
+use crate::{color::Rgb, image::{living_world::DAY_DURATION, LivingWorld}, palette::Cycle};
+
+/// Longest time adaptive pacing waits without drawing a frame.
+pub const MAX_IDLE_DURATION: Duration = Duration::from_secs(1);
+/// Wake up a bit after a cycle step is due so the step is already taken.
+pub const CYCLE_STEP_MARGIN: Duration = Duration::from_micros(500);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Pacing {
+    /// Render at the configured FPS.
+    #[default]
+    Fixed,
+    /// Without blend mode only render when the palette changed and sleep
+    /// until it changes next.
+    Adaptive,
+    /// Render every frame and let presenting it wait for the vertical sync.
+    Vsync,
+}
+
+impl Pacing {
+    #[inline]
+    pub fn name(self) -> &'static str {
+        match self {
+            Pacing::Fixed    => "fixed",
+            Pacing::Adaptive => "adaptive",
+            Pacing::Vsync    => "vsync",
+        }
+    }
+
+    /// Time after the start of a frame until the next one is due, or `None`
+    /// if presenting already waited for the vertical sync. `next_change` is
+    /// only asked with adaptive pacing, which also doesn't wait past
+    /// `deadline` (e.g. the next slideshow image) after the start of the frame.
+    pub fn frame_delay(self, frame_duration: Duration, deadline: Option<Duration>, next_change: impl FnOnce() -> Duration) -> Option<Duration> {
+        match self {
+            Pacing::Fixed    => Some(frame_duration),
+            Pacing::Adaptive => {
+                let delay = next_change().max(frame_duration);
+                Some(deadline.map_or(delay, |deadline| delay.min(deadline)))
+            }
+            Pacing::Vsync    => None,
+        }
+    }
+}
+
+impl Display for Pacing {
+    #[inline]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Pacing {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        for pacing in [Pacing::Fixed, Pacing::Adaptive, Pacing::Vsync] {
+            if value.eq_ignore_ascii_case(pacing.name()) {
+                return Ok(pacing);
+            }
+        }
+        Err(format!("illegal pacing: {value:?}"))
+    }
+}
+
+/// Seconds after `now` (on the same clock as passed to
+/// [`crate::palette::Palette::apply_cycles()`]) until any of the cycles
+/// takes its next step, or `None` if none of them moves.
+///
+/// Sine cycles don't step at fixed times, for them this is the shortest time
+/// their rotation can take to change by one.
+pub fn next_cycle_step(cycles: &[Cycle], now: f64) -> Option<f64> {
+    let mut next: Option<f64> = None;
+
+    for cycle in cycles {
+        if cycle.moving_size().is_some() {
+            let steps_per_sec = cycle.rate().steps_per_sec();
+            let wait = if cycle.mode().is_sine() {
+                // d/dt (sin(2 pi t / size) + 1) * size / 2 is at most pi
+                1.0 / (std::f64::consts::PI * steps_per_sec)
+            } else {
+                let step_ts = ((steps_per_sec * now).floor() + 1.0) / steps_per_sec;
+                step_ts - now
+            };
+            next = Some(next.map_or(wait, |next| next.min(wait)));
+        }
+    }
+
+    next
+}
+
+/// Time after `blend_cycle` (seconds since the image was opened) until a
+/// cycle steps or the timeline blend changes the palette, at most
+/// [`MAX_IDLE_DURATION`]. `time_speed` is `None` if the clock is stopped and
+/// `scene_time` maps the time of day to the time of the timeline.
+pub fn next_palette_change(living_world: &LivingWorld, blend_cycle: f64, time_of_day: u64, time_speed: Option<u64>, scene_time: impl Fn(u64) -> u64) -> Duration {
+    let mut wait = MAX_IDLE_DURATION;
+
+    let now_scene_time = scene_time(time_of_day);
+    let cycle_step = if let Some((palette1, palette2, _)) = living_world.palettes_at(now_scene_time) {
+        let step1 = next_cycle_step(palette1.cycles(), blend_cycle);
+        let step2 = next_cycle_step(palette2.cycles(), blend_cycle);
+        step1.into_iter().chain(step2).reduce(f64::min)
+    } else {
+        next_cycle_step(living_world.base().cycles(), blend_cycle)
+    };
+
+    if let Some(cycle_step) = cycle_step {
+        wait = wait.min(Duration::from_secs_f64(cycle_step) + CYCLE_STEP_MARGIN);
+    }
+
+    if let Some(time_speed) = time_speed && !living_world.timeline().is_empty() {
+        let later_time_of_day = (time_of_day + MAX_IDLE_DURATION.as_millis() as u64 * time_speed) % DAY_DURATION;
+        if let Some(change) = next_timeline_change(living_world, now_scene_time, scene_time(later_time_of_day), MAX_IDLE_DURATION) {
+            wait = wait.min(change);
+        }
+    }
+
+    wait
+}
+
+/// Estimates how long it takes until blending the palettes of the Living
+/// Worlds timeline changes a color channel by at least one. `scene_time` is
+/// the time of day now and `later_scene_time` after `probe` real time.
+///
+/// Returns `None` if the palette doesn't change in that time.
+pub fn next_timeline_change(living_world: &LivingWorld, scene_time: u64, later_scene_time: u64, probe: Duration) -> Option<Duration> {
+    let (palette1, palette2, mid) = living_world.palettes_at(scene_time)?;
+    let (later_palette1, later_palette2, later_mid) = living_world.palettes_at(later_scene_time)?;
+
+    if !std::ptr::eq(palette1, later_palette1) || !std::ptr::eq(palette2, later_palette2) {
+        // passing a timeline event, just look again soon
+        return Some(probe / 10);
+    }
+
+    let mut max_diff = 0u8;
+    for (Rgb(rgb1), Rgb(rgb2)) in palette1.palette().0.iter().zip(palette2.palette().0.iter()) {
+        for (c1, c2) in rgb1.iter().zip(rgb2) {
+            max_diff = max_diff.max(c1.abs_diff(*c2));
+        }
+    }
+
+    let steps = (later_mid - mid).abs() * max_diff as f64;
+    if steps < 1.0 {
+        return None;
+    }
+
+    Some(probe.div_f64(steps))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{color::Rgb, image::{living_world::TimedEvent, CycleImage, IndexedImage}, palette::{CycleMode, Palette, LBM_CYCLE_RATE_DIVISOR}};
+
+    /// One step per second.
+    const RATE: u32 = LBM_CYCLE_RATE_DIVISOR;
+    const FRAME_DURATION: Duration = Duration::from_millis(40);
+
+    fn image(color: u8, cycles: &[Cycle]) -> CycleImage {
+        let mut palette = Palette::default();
+        for index in 0..=255u8 {
+            palette[index] = Rgb([color; 3]);
+        }
+        CycleImage::new(None, IndexedImage::new(1, 1, palette), cycles.into())
+    }
+
+    /// Blends from black at midnight to white at 10000 seconds and back.
+    fn living_world() -> LivingWorld {
+        LivingWorld::new(
+            None,
+            image(0, &[]),
+            Box::new([image(0, &[]), image(255, &[])]),
+            Box::new(["night".to_owned(), "day".to_owned()]),
+            Box::new([TimedEvent::new(0, 0, None), TimedEvent::new(10_000, 1, None)]))
+    }
+
+    fn assert_duration(actual: Duration, expected: Duration) {
+        let diff = actual.abs_diff(expected);
+        assert!(diff < Duration::from_micros(1), "{actual:?} instead of {expected:?}");
+    }
+
+    #[test]
+    fn frame_delay() {
+        assert_eq!(Pacing::Fixed.frame_delay(FRAME_DURATION, None, || panic!("fixed pacing asked for the next change")), Some(FRAME_DURATION));
+        assert_eq!(Pacing::Vsync.frame_delay(FRAME_DURATION, None, || panic!("vsync pacing asked for the next change")), None);
+
+        assert_eq!(Pacing::Adaptive.frame_delay(FRAME_DURATION, None, || Duration::from_millis(250)), Some(Duration::from_millis(250)));
+        // never faster than the configured FPS
+        assert_eq!(Pacing::Adaptive.frame_delay(FRAME_DURATION, None, || Duration::from_millis(1)), Some(FRAME_DURATION));
+    }
+
+    #[test]
+    fn frame_delay_deadline() {
+        // the next slideshow image is due before the palette changes
+        assert_eq!(Pacing::Adaptive.frame_delay(FRAME_DURATION, Some(Duration::from_millis(300)), || MAX_IDLE_DURATION), Some(Duration::from_millis(300)));
+        assert_eq!(Pacing::Adaptive.frame_delay(FRAME_DURATION, Some(Duration::from_millis(10)), || MAX_IDLE_DURATION), Some(Duration::from_millis(10)));
+        assert_eq!(Pacing::Adaptive.frame_delay(FRAME_DURATION, Some(Duration::ZERO), || MAX_IDLE_DURATION), Some(Duration::ZERO));
+
+        // the palette changes first
+        assert_eq!(Pacing::Adaptive.frame_delay(FRAME_DURATION, Some(Duration::from_secs(5)), || Duration::from_millis(250)), Some(Duration::from_millis(250)));
+
+        // only adaptive pacing waits longer than a frame
+        assert_eq!(Pacing::Fixed.frame_delay(FRAME_DURATION, Some(Duration::from_secs(5)), || MAX_IDLE_DURATION), Some(FRAME_DURATION));
+        assert_eq!(Pacing::Vsync.frame_delay(FRAME_DURATION, Some(Duration::from_secs(5)), || MAX_IDLE_DURATION), None);
+    }
+
+    #[test]
+    fn cycle_steps() {
+        assert_eq!(next_cycle_step(&[], 0.0), None);
+        assert_eq!(next_cycle_step(&[Cycle::new(4, 7, 0, CycleMode::Forward)], 0.0), None);
+        assert_eq!(next_cycle_step(&[Cycle::new(4, 4, RATE, CycleMode::Forward)], 0.0), None);
+
+        let forward = Cycle::new(4, 7, RATE, CycleMode::Forward);
+        assert_eq!(next_cycle_step(&[forward], 0.0), Some(1.0));
+        assert_eq!(next_cycle_step(&[forward], 2.25), Some(0.75));
+
+        let fast = Cycle::new(0, 3, RATE * 4, CycleMode::PingPong);
+        assert_eq!(next_cycle_step(&[forward, fast], 2.25), Some(0.25));
+
+        let sine = Cycle::new(8, 15, RATE, CycleMode::Sine);
+        assert_eq!(next_cycle_step(&[forward, sine], 0.0), Some(1.0 / std::f64::consts::PI));
+    }
+
+    #[test]
+    fn palette_change_of_cycles() {
+        let scene_time = |time_of_day| time_of_day;
+        let still = LivingWorld::only_base(image(0, &[]));
+        assert_eq!(next_palette_change(&still, 0.0, 0, Some(1), scene_time), MAX_IDLE_DURATION);
+
+        let cycling = LivingWorld::only_base(image(0, &[Cycle::new(4, 7, RATE * 4, CycleMode::Forward)]));
+        assert_duration(next_palette_change(&cycling, 1.1, 0, Some(1), scene_time), Duration::from_millis(150) + CYCLE_STEP_MARGIN);
+
+        let slow = LivingWorld::only_base(image(0, &[Cycle::new(4, 7, RATE / 5, CycleMode::Forward)]));
+        assert_eq!(next_palette_change(&slow, 0.0, 0, Some(1), scene_time), MAX_IDLE_DURATION);
+    }
+
+    #[test]
+    fn palette_change_of_timeline() {
+        let scene_time = |time_of_day| time_of_day;
+        let living_world = living_world();
+        let noon = 5_000_000;
+
+        // 255 * 1000 / 10_000_000 doesn't change any channel within a second
+        assert_eq!(next_palette_change(&living_world, 0.0, noon, Some(1), scene_time), MAX_IDLE_DURATION);
+        assert_eq!(next_palette_change(&living_world, 0.0, noon, None, scene_time), MAX_IDLE_DURATION);
+
+        // 1000 times as fast the blend changes by 25.5 in a second
+        assert_duration(next_palette_change(&living_world, 0.0, noon, Some(1000), scene_time), MAX_IDLE_DURATION.div_f64(25.5));
+
+        // passing midnight switches to the next pair of palettes
+        assert_eq!(next_palette_change(&living_world, 0.0, DAY_DURATION - 500, Some(1000), scene_time), MAX_IDLE_DURATION / 10);
+        assert_eq!(next_palette_change(&living_world, 0.0, DAY_DURATION - 5000, Some(1), scene_time), MAX_IDLE_DURATION);
+        assert_eq!(next_palette_change(&living_world, 0.0, DAY_DURATION - 5000, Some(1000), scene_time), MAX_IDLE_DURATION / 10);
+    }
+}
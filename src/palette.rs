@@ -16,7 +16,7 @@
 
 use std::{fmt::Display, ops::{Index, IndexMut}};
 
-use crate::color::Rgb;
+use crate::color::{blend_in, BlendSpace, Rgb};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Palette(pub Box<[Rgb; 256]>);
@@ -81,6 +81,19 @@ impl From<&[Rgb]> for Palette {
 
 pub const LBM_CYCLE_RATE_DIVISOR: u32 = 280;
 
+/// Number of dither levels between two cycle steps.
+pub const DITHER_LEVELS: u8 = 16;
+
+/// 4x4 Bayer matrix for ordered dithering. A pixel uses the next cycle step
+/// if the matrix value at its position is less than the dither threshold of
+/// its palette index.
+pub const BAYER_MATRIX: [[u8; 4]; 4] = [
+    [ 0,  8,  2, 10],
+    [12,  4, 14,  6],
+    [ 3, 11,  1,  9],
+    [15,  7, 13,  5],
+];
+
 impl Palette {
     #[inline]
     pub fn rotate_right(&mut self, low: u8, high: u8, distance: u32) {
@@ -97,11 +110,8 @@ impl Palette {
     pub fn apply_cycle(&mut self, cycle: &Cycle, now: f64) {
         let low = cycle.low();
         let high = cycle.high();
-        let rate = cycle.rate();
-        if high > low && rate > 0 {
-            let size = (high - low + 1) as f64;
-            let rate = rate as f64 / LBM_CYCLE_RATE_DIVISOR as f64;
-            let distance = ((rate * now) % size) as u32;
+        if let Some((_, distance)) = cycle.distance(now) {
+            let distance = distance as u32;
             if cycle.reverse() {
                 self.rotate_left(low, high, distance);
             } else {
@@ -110,15 +120,10 @@ impl Palette {
         }
     }
 
-    pub fn apply_cycle_blended(&mut self, palette: &Palette, cycle: &Cycle, now: f64) {
+    pub fn apply_cycle_blended(&mut self, palette: &Palette, cycle: &Cycle, now: f64, space: BlendSpace) {
         let low = cycle.low();
         let high = cycle.high();
-        let rate = cycle.rate();
-        if high > low && rate > 0 {
-            let size = high as u32 - low as u32 + 1;
-            let fsize = size as f64;
-            let rate = rate as f64 / LBM_CYCLE_RATE_DIVISOR as f64;
-            let fdistance = (rate * now) % fsize;
+        if let Some((size, fdistance)) = cycle.distance(now) {
             let distance = fdistance as u32;
             let mid = fdistance - distance as f64;
 
@@ -130,15 +135,46 @@ impl Palette {
                     let src_index = dest_index + distance;
                     let src_index1 = src_index % size;
                     let src_index2 = (src_index + 1) % size;
-                    dest[dest_index as usize] = crate::color::blend(src[src_index1 as usize], src[src_index2 as usize], mid);
+                    dest[dest_index as usize] = blend_in(src[src_index1 as usize], src[src_index2 as usize], mid, space);
                 }
             } else {
                 let inv = 1.0 - mid;
                 for src_index1 in 0..size {
                     let dest_index = (src_index1 + distance) % size;
                     let src_index2 = (src_index1 + 1) % size;
-                    dest[dest_index as usize] = crate::color::blend(src[src_index1 as usize], src[src_index2 as usize], inv);
+                    dest[dest_index as usize] = blend_in(src[src_index1 as usize], src[src_index2 as usize], inv, space);
+                }
+            }
+        }
+    }
+
+    /// Ordered dither alternative to [`Palette::apply_cycles_from()`] in blend
+    /// mode. `self` is set to the palette cycled by the whole number of steps,
+    /// `next` to the palette cycled one step further and `thresholds` to how
+    /// far each palette index is between the two (0 to [`DITHER_LEVELS`]).
+    pub fn apply_cycles_dithered_from(&mut self, next: &mut Palette, thresholds: &mut [u8; 256], palette: &Palette, cycles: &[Cycle], now: f64) {
+        self.clone_from(palette);
+        next.clone_from(palette);
+        thresholds.fill(0);
+
+        for cycle in cycles {
+            let low = cycle.low();
+            let high = cycle.high();
+            if let Some((size, fdistance)) = cycle.distance(now) {
+                let distance = fdistance as u32;
+                let mid = fdistance - distance as f64;
+                let next_distance = (distance + 1) % size;
+
+                if cycle.reverse() {
+                    self.rotate_left(low, high, distance);
+                    next.rotate_left(low, high, next_distance);
+                } else {
+                    self.rotate_right(low, high, distance);
+                    next.rotate_right(low, high, next_distance);
                 }
+
+                let threshold = (mid * DITHER_LEVELS as f64).round() as u8;
+                thresholds[low as usize..high as usize + 1].fill(threshold);
             }
         }
     }
@@ -149,12 +185,12 @@ impl Palette {
         }
     }
 
-    pub fn apply_cycles_from(&mut self, palette: &Palette, cycles: &[Cycle], now: f64, blend: bool) {
+    pub fn apply_cycles_from(&mut self, palette: &Palette, cycles: &[Cycle], now: f64, blend: bool, space: BlendSpace) {
         self.clone_from(&palette);
 
         if blend {
             for cycle in cycles {
-                self.apply_cycle_blended(palette, cycle, now);
+                self.apply_cycle_blended(palette, cycle, now, space);
             }
         } else {
             self.apply_cycles(cycles, now);
@@ -162,9 +198,272 @@ impl Palette {
     }
 }
 
-pub fn blend(p1: &Palette, p2: &Palette, mid: f64, output: &mut Palette) {
+pub fn blend(p1: &Palette, p2: &Palette, mid: f64, space: BlendSpace, output: &mut Palette) {
     for index in 0..256 {
-        output.0[index] = crate::color::blend(p1.0[index], p2.0[index], mid);
+        output.0[index] = blend_in(p1.0[index], p2.0[index], mid, space);
+    }
+}
+
+/// Like [`blend()`], but for the dither thresholds of
+/// [`Palette::apply_cycles_dithered_from()`].
+pub fn blend_thresholds(t1: &[u8; 256], t2: &[u8; 256], mid: f64, output: &mut [u8; 256]) {
+    let inv_mid = 1.0 - mid;
+    for ((output, &t1), &t2) in output.iter_mut().zip(t1).zip(t2) {
+        *output = (t1 as f64 * inv_mid + t2 as f64 * mid).round() as u8;
+    }
+}
+
+fn gcd(mut a: u128, mut b: u128) -> u128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// Exact period in seconds after which the palette cycled without blending
+/// repeats, as a fraction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CyclePeriod {
+    numerator: u128,
+    denominator: u128,
+}
+
+impl CyclePeriod {
+    #[inline]
+    pub fn numerator(&self) -> u128 {
+        self.numerator
+    }
+
+    #[inline]
+    pub fn denominator(&self) -> u128 {
+        self.denominator
+    }
+
+    #[inline]
+    pub fn as_secs_f64(&self) -> f64 {
+        self.numerator as f64 / self.denominator as f64
+    }
+}
+
+impl Display for CyclePeriod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.denominator == 1 {
+            write!(f, "{} s", self.numerator)
+        } else {
+            write!(f, "{}/{} s (~{:.3} s)", self.numerator, self.denominator, self.as_secs_f64())
+        }
+    }
+}
+
+/// Least common multiple of the periods of the moving cycles, each being
+/// [`Cycle::period_steps()`] times the duration of a step.
+///
+/// Returns `None` if nothing moves or on overflow.
+pub fn cycles_period(cycles: &[Cycle]) -> Option<CyclePeriod> {
+    let mut period: Option<CyclePeriod> = None;
+
+    for cycle in cycles {
+        let (Some(steps), Some((step_numerator, step_denominator))) = (cycle.period_steps(), cycle.rate().step_secs()) else {
+            continue;
+        };
+
+        let numerator = (steps as u128).checked_mul(step_numerator)?;
+        let denominator = step_denominator;
+        let divisor = gcd(numerator, denominator);
+        let numerator = numerator / divisor;
+        let denominator = denominator / divisor;
+
+        // lcm(a/b, c/d) = lcm(a, c) / gcd(b, d) for reduced fractions
+        period = Some(if let Some(period) = period {
+            let numerator = (period.numerator / gcd(period.numerator, numerator)).checked_mul(numerator)?;
+            CyclePeriod {
+                numerator,
+                denominator: gcd(period.denominator, denominator),
+            }
+        } else {
+            CyclePeriod { numerator, denominator }
+        });
+    }
+
+    period
+}
+
+/// All distinct palette states of one cycle period without blending.
+#[derive(Debug, Clone)]
+pub struct PaletteLoop {
+    period: CyclePeriod,
+    /// seconds into the period at which the corresponding palette starts
+    starts: Box<[f64]>,
+    palettes: Box<[Palette]>,
+}
+
+impl PaletteLoop {
+    /// Returns `None` if nothing moves, if there would be more than
+    /// `max_states` palettes or if a sine cycle moves, since those don't step
+    /// at fixed times.
+    pub fn new(palette: &Palette, cycles: &[Cycle], max_states: usize) -> Option<Self> {
+        if cycles.iter().any(|cycle| cycle.mode().is_sine() && cycle.moving_size().is_some()) {
+            return None;
+        }
+
+        let period = cycles_period(cycles)?;
+
+        // Steps happen at multiples of the step duration. Kept as fractions
+        // of seconds so simultaneous steps of different cycles compare equal.
+        let mut steps: Vec<(u128, u128)> = Vec::new();
+        for cycle in cycles {
+            let (Some(_), Some((step_numerator, step_denominator))) = (cycle.moving_size(), cycle.rate().step_secs()) else {
+                continue;
+            };
+            let count = period.numerator.checked_mul(step_denominator)? / period.denominator.checked_mul(step_numerator)?;
+            if steps.len() as u128 + count > max_states as u128 {
+                return None;
+            }
+            steps.extend((0..count).map(|step| (step * step_numerator, step_denominator)));
+        }
+
+        let cmp = |&(n1, d1): &(u128, u128), &(n2, d2): &(u128, u128)| (n1 * d2).cmp(&(n2 * d1));
+        steps.sort_by(cmp);
+        steps.dedup_by(|a, b| cmp(a, b).is_eq());
+
+        let to_secs = |(numerator, denominator): (u128, u128)| numerator as f64 / denominator as f64;
+        let period_secs = period.as_secs_f64();
+
+        let mut starts: Vec<f64> = Vec::with_capacity(steps.len());
+        let mut palettes: Vec<Palette> = Vec::with_capacity(steps.len());
+        for (index, &step) in steps.iter().enumerate() {
+            let start = to_secs(step);
+            let end = steps.get(index + 1).map(|&step| to_secs(step)).unwrap_or(period_secs);
+
+            // sample in the middle to be safe from rounding errors
+            let mut state = palette.clone();
+            state.apply_cycles(cycles, (start + end) / 2.0);
+
+            if palettes.last() != Some(&state) {
+                starts.push(start);
+                palettes.push(state);
+            }
+        }
+
+        Some(Self {
+            period,
+            starts: starts.into(),
+            palettes: palettes.into(),
+        })
+    }
+
+    #[inline]
+    pub fn period(&self) -> CyclePeriod {
+        self.period
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.palettes.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.palettes.is_empty()
+    }
+
+    /// Start (in seconds into the period) and palette of each state.
+    #[inline]
+    pub fn states(&self) -> impl Iterator<Item = (f64, &Palette)> {
+        self.starts.iter().copied().zip(self.palettes.iter())
+    }
+
+    /// Same as cycling the palette with [`Palette::apply_cycles()`].
+    pub fn palette_at(&self, now: f64) -> &Palette {
+        let time = now.rem_euclid(self.period.as_secs_f64());
+        let index = self.starts.partition_point(|&start| start <= time);
+        &self.palettes[index.saturating_sub(1)]
+    }
+}
+
+/// How fast a cycle moves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CycleRate {
+    /// Rate as in `CRNG` chunks, [`LBM_CYCLE_RATE_DIVISOR`] is one step per
+    /// second.
+    Rate(u32),
+    /// Delay as in `CCRT` chunks, one step every this many microseconds.
+    Delay(u64),
+}
+
+impl Default for CycleRate {
+    #[inline]
+    fn default() -> Self {
+        CycleRate::Rate(0)
+    }
+}
+
+impl CycleRate {
+    #[inline]
+    pub fn steps_per_sec(self) -> f64 {
+        match self {
+            CycleRate::Rate(rate) => rate as f64 / LBM_CYCLE_RATE_DIVISOR as f64,
+            CycleRate::Delay(0) => 0.0,
+            CycleRate::Delay(usec) => 1_000_000.0 / usec as f64,
+        }
+    }
+
+    /// Duration of one step in seconds as a fraction (numerator,
+    /// denominator), or `None` if the cycle doesn't move.
+    #[inline]
+    pub fn step_secs(self) -> Option<(u128, u128)> {
+        match self {
+            CycleRate::Rate(0) | CycleRate::Delay(0) => None,
+            CycleRate::Rate(rate) => Some((LBM_CYCLE_RATE_DIVISOR as u128, rate as u128)),
+            CycleRate::Delay(usec) => Some((usec as u128, 1_000_000)),
+        }
+    }
+}
+
+/// How a cycle moves. The numbers are the values of the `reverse` field in
+/// Canvas Cycle JSON files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum CycleMode {
+    /// 0 or 1, colors move towards the higher end of the range.
+    #[default]
+    Forward,
+    /// 2, colors move towards the lower end of the range.
+    Reverse,
+    /// 3, colors move forward by the whole range and then back again.
+    PingPong,
+    /// 4, colors swing back and forth along a sine wave over half the range.
+    Sine,
+    /// 5, colors swing back and forth along a sine wave over the whole range.
+    SineFull,
+}
+
+impl CycleMode {
+    #[inline]
+    pub fn from_value(value: i64) -> Option<Self> {
+        match value {
+            0 | 1 => Some(CycleMode::Forward),
+            2 => Some(CycleMode::Reverse),
+            3 => Some(CycleMode::PingPong),
+            4 => Some(CycleMode::Sine),
+            5 => Some(CycleMode::SineFull),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn value(self) -> i64 {
+        match self {
+            CycleMode::Forward  => 0,
+            CycleMode::Reverse  => 2,
+            CycleMode::PingPong => 3,
+            CycleMode::Sine     => 4,
+            CycleMode::SineFull => 5,
+        }
+    }
+
+    #[inline]
+    pub fn is_sine(self) -> bool {
+        matches!(self, CycleMode::Sine | CycleMode::SineFull)
     }
 }
 
@@ -172,21 +471,40 @@ pub fn blend(p1: &Palette, p2: &Palette, mid: f64, output: &mut Palette) {
 pub struct Cycle {
     low: u8,
     high: u8,
-    rate: u32,
-    reverse: bool,
+    rate: CycleRate,
+    mode: CycleMode,
 }
 
 impl Cycle {
+    /// Cycle with a rate as in `CRNG` chunks.
+    #[inline]
+    pub fn new(low: u8, high: u8, rate: u32, mode: CycleMode) -> Self {
+        Self {
+            low,
+            high,
+            rate: CycleRate::Rate(rate),
+            mode,
+        }
+    }
+
+    /// Cycle that takes a step every `delay_usec` microseconds, as in `CCRT`
+    /// chunks.
     #[inline]
-    pub fn new(low: u8, high: u8, rate: u32, reverse: bool) -> Self {
+    pub fn with_delay(low: u8, high: u8, delay_usec: u64, mode: CycleMode) -> Self {
         Self {
             low,
             high,
-            rate,
-            reverse,
+            rate: CycleRate::Delay(delay_usec),
+            mode,
         }
     }
 
+    /// The same range and rate moving in another way.
+    #[inline]
+    pub fn with_mode(self, mode: CycleMode) -> Self {
+        Self { mode, ..self }
+    }
+
     #[inline]
     pub fn low(&self) -> u8 {
         self.low
@@ -198,12 +516,290 @@ impl Cycle {
     }
 
     #[inline]
-    pub fn rate(&self) -> u32 {
+    pub fn rate(&self) -> CycleRate {
         self.rate
     }
 
+    #[inline]
+    pub fn mode(&self) -> CycleMode {
+        self.mode
+    }
+
     #[inline]
     pub fn reverse(&self) -> bool {
-        self.reverse
+        self.mode == CycleMode::Reverse
+    }
+
+    /// Number of colors of the cycle if it moves at all.
+    #[inline]
+    pub fn moving_size(&self) -> Option<u32> {
+        if self.high > self.low && self.rate.step_secs().is_some() {
+            Some(self.high as u32 - self.low as u32 + 1)
+        } else {
+            None
+        }
+    }
+
+    /// Number of steps until the cycle repeats if it moves at all.
+    #[inline]
+    pub fn period_steps(&self) -> Option<u32> {
+        let size = self.moving_size()?;
+        if self.mode == CycleMode::PingPong {
+            Some(size * 2)
+        } else {
+            Some(size)
+        }
+    }
+
+    /// Number of colors of the cycle and how far they are rotated at `now`,
+    /// from `0` up to the number of colors. Reverse cycles rotate towards the
+    /// lower end, all others towards the higher end. Fractions are how far
+    /// the rotation is towards the next step.
+    pub fn distance(&self, now: f64) -> Option<(u32, f64)> {
+        let size = self.moving_size()?;
+        let fsize = size as f64;
+        let steps = self.rate.steps_per_sec() * now;
+
+        let distance = match self.mode {
+            CycleMode::Forward | CycleMode::Reverse => steps % fsize,
+            CycleMode::PingPong => {
+                let distance = steps % (2.0 * fsize);
+                if distance > fsize {
+                    2.0 * fsize - distance
+                } else {
+                    distance
+                }
+            }
+            CycleMode::Sine | CycleMode::SineFull => {
+                let amplitude = if self.mode == CycleMode::Sine { fsize / 4.0 } else { fsize / 2.0 };
+                let angle = (steps % fsize) * std::f64::consts::TAU / fsize;
+                (angle.sin() + 1.0) * amplitude
+            }
+        };
+
+        Some((size, distance % fsize))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One step per second.
+    const RATE: u32 = LBM_CYCLE_RATE_DIVISOR;
+
+    fn assert_distance(cycle: &Cycle, now: f64, expected: f64) {
+        let (size, distance) = cycle.distance(now).unwrap();
+        assert_eq!(size, cycle.high() as u32 - cycle.low() as u32 + 1);
+        assert!((distance - expected).abs() < 1e-9, "{:?} at {now}: {distance} instead of {expected}", cycle.mode());
+    }
+
+    #[test]
+    fn distance_forward() {
+        for mode in [CycleMode::Forward, CycleMode::Reverse] {
+            let cycle = Cycle::new(4, 7, RATE, mode);
+            assert_distance(&cycle, 0.0, 0.0);
+            assert_distance(&cycle, 1.0, 1.0);
+            assert_distance(&cycle, 5.5, 1.5);
+        }
+
+        assert_eq!(Cycle::new(4, 4, RATE, CycleMode::Forward).distance(1.0), None);
+        assert_eq!(Cycle::new(4, 7, 0, CycleMode::PingPong).distance(1.0), None);
+        assert_eq!(Cycle::with_delay(4, 7, 0, CycleMode::Sine).distance(1.0), None);
+    }
+
+    #[test]
+    fn distance_ping_pong() {
+        let cycle = Cycle::new(4, 7, RATE, CycleMode::PingPong);
+        for (now, expected) in [
+            (0.0, 0.0), (1.0, 1.0), (2.5, 2.5), (3.0, 3.0),
+            // turns around, the full range looks like no rotation
+            (4.0, 0.0), (5.0, 3.0), (6.5, 1.5), (7.0, 1.0),
+            (8.0, 0.0), (9.0, 1.0),
+        ] {
+            assert_distance(&cycle, now, expected);
+        }
+        assert_eq!(cycle.period_steps(), Some(8));
+    }
+
+    #[test]
+    fn distance_sine() {
+        // swings by a quarter of the range around the middle
+        let cycle = Cycle::new(4, 7, RATE, CycleMode::Sine);
+        for (now, expected) in [(0.0, 1.0), (1.0, 2.0), (2.0, 1.0), (3.0, 0.0), (4.0, 1.0)] {
+            assert_distance(&cycle, now, expected);
+        }
+        assert_eq!(cycle.period_steps(), Some(4));
+
+        // swings by half of the range, the maximum is the full rotation
+        let cycle = Cycle::new(4, 7, RATE, CycleMode::SineFull);
+        for (now, expected) in [(0.0, 2.0), (0.5, 2.0 + 2.0 * 0.5f64.sqrt()), (1.0, 0.0), (2.0, 2.0), (3.0, 0.0), (4.0, 2.0)] {
+            assert_distance(&cycle, now, expected);
+        }
+    }
+
+    fn period(cycles: &[Cycle]) -> Option<(u128, u128)> {
+        cycles_period(cycles).map(|period| (period.numerator(), period.denominator()))
+    }
+
+    #[test]
+    fn period_of_mixed_rates() {
+        // 4 colors at 1 step/s and 6 colors at 2 steps/s: lcm(4 s, 3 s)
+        assert_eq!(period(&[
+            Cycle::new(0, 3, RATE, CycleMode::Forward),
+            Cycle::new(4, 9, 2 * RATE, CycleMode::Reverse),
+        ]), Some((12, 1)));
+
+        // 4 colors at 1.5 steps/s (8/3 s) and 3 colors every 0.1 s (3/10 s)
+        assert_eq!(period(&[
+            Cycle::new(0, 3, RATE * 3 / 2, CycleMode::Forward),
+            Cycle::with_delay(10, 12, 100_000, CycleMode::Forward),
+        ]), Some((24, 1)));
+
+        // fractions that don't add up to whole seconds
+        assert_eq!(period(&[Cycle::new(0, 2, 3 * RATE, CycleMode::Forward)]), Some((1, 1)));
+        assert_eq!(period(&[Cycle::new(0, 1, 3 * RATE, CycleMode::Forward)]), Some((2, 3)));
+        assert_eq!(period(&[
+            Cycle::with_delay(0, 1, 250_000, CycleMode::Forward),
+            Cycle::with_delay(2, 4, 250_000, CycleMode::Reverse),
+        ]), Some((3, 2)));
+
+        // ping-pong takes twice as long, sine once the range
+        assert_eq!(period(&[Cycle::new(0, 3, RATE, CycleMode::PingPong)]), Some((8, 1)));
+        assert_eq!(period(&[Cycle::new(0, 3, RATE, CycleMode::Sine)]), Some((4, 1)));
+
+        // cycles that don't move don't count
+        assert_eq!(period(&[
+            Cycle::new(0, 3, RATE, CycleMode::Forward),
+            Cycle::new(5, 5, RATE, CycleMode::Forward),
+            Cycle::new(6, 9, 0, CycleMode::Forward),
+            Cycle::with_delay(10, 12, 0, CycleMode::Forward),
+        ]), Some((4, 1)));
+        assert_eq!(period(&[Cycle::new(6, 9, 0, CycleMode::Forward)]), None);
+        assert_eq!(period(&[]), None);
+    }
+
+    fn test_palette() -> Palette {
+        let mut palette = Palette::default();
+        for index in 0..=255u8 {
+            palette[index] = Rgb([index, 255 - index, index / 2]);
+        }
+        palette
+    }
+
+    #[test]
+    fn palette_loop_matches_apply_cycles() {
+        let palette = test_palette();
+        let cycles = [
+            Cycle::new(0, 3, RATE, CycleMode::Forward),
+            Cycle::new(4, 9, 2 * RATE, CycleMode::Reverse),
+            Cycle::new(10, 12, RATE, CycleMode::PingPong),
+            Cycle::with_delay(20, 22, 300_000, CycleMode::Forward),
+        ];
+        let palette_loop = PaletteLoop::new(&palette, &cycles, 10_000).unwrap();
+        assert_eq!(palette_loop.period().as_secs_f64(), 36.0);
+        assert!(palette_loop.len() > 1);
+
+        // between the steps, which are at multiples of 0.1 s
+        for step in 0..2 * 36 * 10 {
+            let now = step as f64 / 10.0 + 0.05;
+            let mut expected = palette.clone();
+            expected.apply_cycles(&cycles, now);
+            assert_eq!(palette_loop.palette_at(now), &expected, "at {now} s");
+        }
+
+        // wraps around at the period
+        assert_eq!(palette_loop.palette_at(0.0), &palette);
+        assert_eq!(palette_loop.palette_at(36.0), &palette);
+        assert_eq!(palette_loop.palette_at(36.55), palette_loop.palette_at(0.55));
+        assert_eq!(palette_loop.palette_at(-0.05), palette_loop.palette_at(35.95));
+    }
+
+    #[test]
+    fn palette_loop_limits() {
+        let palette = test_palette();
+        let cycles = [Cycle::new(0, 3, RATE, CycleMode::Forward)];
+        assert_eq!(PaletteLoop::new(&palette, &cycles, 4).map(|palette_loop| palette_loop.len()), Some(4));
+        assert!(PaletteLoop::new(&palette, &cycles, 3).is_none());
+        assert!(PaletteLoop::new(&palette, &[Cycle::new(0, 3, RATE, CycleMode::Sine)], 100).is_none());
+        assert!(PaletteLoop::new(&palette, &[Cycle::new(0, 3, 0, CycleMode::Forward)], 100).is_none());
+    }
+
+    #[test]
+    fn bayer_matrix() {
+        let mut values: Vec<u8> = BAYER_MATRIX.iter().flatten().copied().collect();
+        values.sort();
+        assert_eq!(values, (0..DITHER_LEVELS).collect::<Vec<_>>());
+
+        // each 2x2 block holds every fourth level, spread over all four corners
+        for (block_y, block_x) in [(0, 0), (0, 2), (2, 0), (2, 2)] {
+            let block = [
+                BAYER_MATRIX[block_y][block_x],     BAYER_MATRIX[block_y][block_x + 1],
+                BAYER_MATRIX[block_y + 1][block_x], BAYER_MATRIX[block_y + 1][block_x + 1],
+            ];
+            let mut quarters: Vec<_> = block.iter().map(|value| value / 4).collect();
+            quarters.sort();
+            assert_eq!(quarters, [0, 1, 2, 3]);
+            assert!(block.iter().all(|value| value % 4 == block[0] % 4));
+        }
+    }
+
+    #[test]
+    fn dithered_cycles() {
+        let palette = test_palette();
+        let mut current = Palette::default();
+        let mut next = Palette::default();
+        let mut thresholds = [0u8; 256];
+
+        for mode in [CycleMode::Forward, CycleMode::Reverse] {
+            let cycles = [Cycle::new(4, 7, RATE, mode)];
+            let cycled_at = |now: f64| {
+                let mut cycled = palette.clone();
+                cycled.apply_cycles(&cycles, now);
+                cycled
+            };
+
+            current.apply_cycles_dithered_from(&mut next, &mut thresholds, &palette, &cycles, 1.25);
+            assert_eq!(current, cycled_at(1.0));
+            assert_eq!(next, cycled_at(2.0));
+            assert!(thresholds[4..=7].iter().all(|&threshold| threshold == DITHER_LEVELS / 4));
+            assert!(thresholds[..4].iter().chain(&thresholds[8..]).all(|&threshold| threshold == 0));
+
+            // the next step wraps around
+            current.apply_cycles_dithered_from(&mut next, &mut thresholds, &palette, &cycles, 3.5);
+            assert_eq!(current, cycled_at(3.0));
+            assert_eq!(next, palette);
+            assert_eq!(thresholds[4], DITHER_LEVELS / 2);
+
+            // on a step there is nothing to dither
+            current.apply_cycles_dithered_from(&mut next, &mut thresholds, &palette, &cycles, 2.0);
+            assert_eq!(current, cycled_at(2.0));
+            assert!(thresholds.iter().all(|&threshold| threshold == 0));
+        }
+    }
+
+    #[test]
+    fn blended_thresholds() {
+        let mut t1 = [0u8; 256];
+        let mut t2 = [0u8; 256];
+        t1[4] = 4;
+        t2[4] = 12;
+        t2[8] = 16;
+        let mut output = [0u8; 256];
+
+        blend_thresholds(&t1, &t2, 0.0, &mut output);
+        assert_eq!(output, t1);
+        blend_thresholds(&t1, &t2, 1.0, &mut output);
+        assert_eq!(output, t2);
+
+        // no jump half way through the transition
+        let mut prev = 4;
+        for step in 0..=100 {
+            blend_thresholds(&t1, &t2, step as f64 / 100.0, &mut output);
+            assert!(output[4] == prev || output[4] == prev + 1, "{} after {prev}", output[4]);
+            prev = output[4];
+        }
+        blend_thresholds(&t1, &t2, 0.5, &mut output);
+        assert_eq!((output[4], output[8]), (8, 8));
     }
 }
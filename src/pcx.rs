@@ -0,0 +1,200 @@
+// color-cycle - render color cycle images
+// Copyright (C) 2025  Mathias Panzenböck
+// 
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+// 
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+// 
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Reader for palette based ZSoft PCX files.
+
+use std::io::Read;
+
+use crate::{color::Rgb, error::Error, image::IndexedImage, palette::Palette};
+
+const HEADER_SIZE: usize = 128;
+const VGA_PALETTE_MARKER: u8 = 0x0C;
+const VGA_PALETTE_SIZE: usize = 1 + 256 * 3;
+
+/// Reads 8 bits per pixel single plane images with a 256 color palette, and
+/// 1, 2 or 4 bits per pixel images with up to 4 planes using the 16 color
+/// palette in the header.
+pub fn read_pcx(reader: &mut impl Read) -> Result<IndexedImage, Error> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+
+    if data.len() < HEADER_SIZE || data[0] != 0x0A {
+        return Err(Error::new("not a PCX file"));
+    }
+
+    let encoding = data[2];
+    let bits_per_pixel = data[3] as u32;
+    let x_min = read_u16le(&data, 4) as u32;
+    let y_min = read_u16le(&data, 6) as u32;
+    let x_max = read_u16le(&data, 8) as u32;
+    let y_max = read_u16le(&data, 10) as u32;
+    let num_planes = data[65] as u32;
+    let bytes_per_line = read_u16le(&data, 66) as usize;
+
+    if encoding > 1 {
+        return Err(Error::new(format!("unsupported PCX encoding: {encoding}")));
+    }
+
+    if x_max < x_min || y_max < y_min {
+        return Err(Error::new("illegal PCX image dimensions"));
+    }
+    let width = x_max - x_min + 1;
+    let height = y_max - y_min + 1;
+
+    let depth = bits_per_pixel * num_planes;
+    match (bits_per_pixel, num_planes) {
+        (8, 1) | (1, 1..=4) | (2, 1) | (4, 1) => {}
+        _ => {
+            return Err(Error::new(format!(
+                "unsupported PCX format: {bits_per_pixel} bits per pixel and {num_planes} planes")));
+        }
+    }
+
+    if bytes_per_line * 8 < (width * bits_per_pixel) as usize {
+        return Err(Error::new(format!("PCX line length too short: {bytes_per_line}")));
+    }
+
+    let mut palette = Palette::default();
+    let mut body = &data[HEADER_SIZE..];
+    if depth == 8 {
+        let Some(palette_offset) = data.len().checked_sub(VGA_PALETTE_SIZE) else {
+            return Err(Error::new("PCX 256 color palette missing"));
+        };
+        if palette_offset < HEADER_SIZE || data[palette_offset] != VGA_PALETTE_MARKER {
+            return Err(Error::new("PCX 256 color palette missing"));
+        }
+        for (index, rgb) in data[palette_offset + 1..].chunks_exact(3).enumerate() {
+            palette[index as u8] = Rgb([rgb[0], rgb[1], rgb[2]]);
+        }
+        body = &data[HEADER_SIZE..palette_offset];
+    } else {
+        for (index, rgb) in data[16..16 + 16 * 3].chunks_exact(3).enumerate() {
+            palette[index as u8] = Rgb([rgb[0], rgb[1], rgb[2]]);
+        }
+    }
+
+    let scan_line_len = bytes_per_line * num_planes as usize;
+    let mut scan_line = vec![0u8; scan_line_len];
+    let mut image = IndexedImage::new(width, height, palette);
+    let mut pos = 0;
+    let pixels_per_byte = 8 / bits_per_pixel;
+    let mask = ((1u16 << bits_per_pixel) - 1) as u8;
+
+    for y in 0..height {
+        if encoding == 0 {
+            let Some(line) = body.get(pos..pos + scan_line_len) else {
+                return Err(Error::new("truncated PCX file"));
+            };
+            scan_line.copy_from_slice(line);
+            pos += scan_line_len;
+        } else {
+            // runs may cross plane boundaries, but not scan lines
+            let mut offset = 0;
+            while offset < scan_line_len {
+                let Some(&byte) = body.get(pos) else {
+                    return Err(Error::new("truncated PCX file"));
+                };
+                pos += 1;
+                if byte >= 0xC0 {
+                    let Some(&value) = body.get(pos) else {
+                        return Err(Error::new("truncated PCX file"));
+                    };
+                    pos += 1;
+                    let count = ((byte & 0x3F) as usize).min(scan_line_len - offset);
+                    scan_line[offset..offset + count].fill(value);
+                    offset += count;
+                } else {
+                    scan_line[offset] = byte;
+                    offset += 1;
+                }
+            }
+        }
+
+        for x in 0..width {
+            let byte_index = (x / pixels_per_byte) as usize;
+            let shift = 8 - bits_per_pixel * (x % pixels_per_byte + 1);
+            let mut index = 0u8;
+            for (plane, plane_line) in scan_line.chunks_exact(bytes_per_line).enumerate() {
+                index |= ((plane_line[byte_index] >> shift) & mask) << plane;
+            }
+            image.set_index(x, y, index);
+        }
+    }
+
+    Ok(image)
+}
+
+#[inline]
+fn read_u16le(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pcx_header(encoding: u8, bits_per_pixel: u8, num_planes: u8, width: u16, height: u16, bytes_per_line: u16) -> Vec<u8> {
+        let mut header = vec![0u8; HEADER_SIZE];
+        header[0] = 0x0A;
+        header[1] = 5;
+        header[2] = encoding;
+        header[3] = bits_per_pixel;
+        header[8..10].copy_from_slice(&(width - 1).to_le_bytes());
+        header[10..12].copy_from_slice(&(height - 1).to_le_bytes());
+        header[65] = num_planes;
+        header[66..68].copy_from_slice(&bytes_per_line.to_le_bytes());
+        header
+    }
+
+    #[test]
+    fn rle_256_colors() {
+        let mut file = pcx_header(1, 8, 1, 5, 2, 6);
+        file.extend_from_slice(&[
+            // run of three 3, escaped 200, literal 1, padding
+            0xC3, 3, 0xC1, 200, 1, 0,
+            // a run can fill the padding
+            0xC6, 9,
+        ]);
+        file.push(VGA_PALETTE_MARKER);
+        for index in 0..=255u8 {
+            file.extend_from_slice(&[index, 255 - index, 7]);
+        }
+
+        let image = read_pcx(&mut &file[..]).unwrap();
+        assert_eq!(image.size(), (5, 2));
+        assert_eq!(image.data(), &[3, 3, 3, 200, 1, 9, 9, 9, 9, 9]);
+        assert_eq!(image.palette()[200], Rgb([200, 55, 7]));
+    }
+
+    #[test]
+    fn uncompressed_planes() {
+        let mut file = pcx_header(0, 1, 4, 3, 1, 2);
+        file[16 + 10 * 3..16 + 11 * 3].copy_from_slice(&[1, 2, 3]);
+        // pixels 5, 10 and 15, one bit plane after the other
+        file.extend_from_slice(&[0xA0, 0, 0x60, 0, 0xA0, 0, 0x60, 0]);
+
+        let image = read_pcx(&mut &file[..]).unwrap();
+        assert_eq!(image.data(), &[5, 10, 15]);
+        assert_eq!(image.palette()[10], Rgb([1, 2, 3]));
+    }
+
+    #[test]
+    fn true_color_unsupported() {
+        let mut file = pcx_header(0, 8, 3, 1, 1, 2);
+        file.extend_from_slice(&[1, 0, 2, 0, 3, 0]);
+        assert!(read_pcx(&mut &file[..]).is_err());
+    }
+}
@@ -14,7 +14,7 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use crate::{color::Rgb, image::{living_world::TimedEvent, CycleImage, IndexedImage, LivingWorld}, palette::{Cycle, Palette}};
+use crate::{color::Rgb, easing::Easing, image::{living_world::TimedEvent, CycleImage, IndexedImage, LivingWorld}, palette::{Cycle, CycleMode, Palette}};
 
 use std::{collections::HashMap, convert::TryInto};
 use serde::{de::{Error, IgnoredAny, Visitor}, Deserializer, Deserialize};
@@ -127,8 +127,120 @@ impl<'de> serde::de::Deserialize<'de> for CycleImage {
     }
 }
 
+impl<'de> serde::de::Deserialize<'de> for Easing {
+    #[inline]
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where D: Deserializer<'de> {
+        let value = String::deserialize(deserializer)?;
+        value.parse().map_err(Error::custom)
+    }
+}
+
+/// Palette name of a timeline event and optionally the easing of the
+/// transition to it. Either just the name or `{"palette": name, "easing": easing}`.
 #[derive(Debug)]
-struct Timeline(pub Vec<(u32, String)>);
+struct TimelineEntry(pub String, pub Option<Easing>);
+
+struct TimelineEntryVisitor;
+
+impl<'de> Visitor<'de> for TimelineEntryVisitor {
+    type Value = TimelineEntry;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a palette name or an object with a palette name and an easing")
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+    where E: Error {
+        Ok(TimelineEntry(value.to_owned(), None))
+    }
+
+    fn visit_string<E>(self, value: String) -> Result<Self::Value, E>
+    where E: Error {
+        Ok(TimelineEntry(value, None))
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where A: serde::de::MapAccess<'de> {
+        let mut palette = None;
+        let mut easing = None;
+
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "palette" => {
+                    palette = Some(map.next_value()?);
+                }
+                "easing" => {
+                    easing = map.next_value()?;
+                }
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        let Some(palette) = palette else {
+            return Err(Error::missing_field("palette"));
+        };
+
+        Ok(TimelineEntry(palette, easing))
+    }
+}
+
+impl<'de> serde::de::Deserialize<'de> for TimelineEntry {
+    #[inline]
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where D: Deserializer<'de> {
+        deserializer.deserialize_any(TimelineEntryVisitor)
+    }
+}
+
+/// A timeline event as `[seconds, palette]` or `[seconds, palette, easing]`.
+#[derive(Debug)]
+struct TimelineItem(pub u32, pub TimelineEntry);
+
+struct TimelineItemVisitor;
+
+impl<'de> Visitor<'de> for TimelineItemVisitor {
+    type Value = TimelineItem;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a seconds-name tuple or a seconds-name-easing tuple")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where A: serde::de::SeqAccess<'de> {
+        let Some(time_of_day) = seq.next_element()? else {
+            return Err(Error::invalid_length(0, &self));
+        };
+
+        let Some(TimelineEntry(palette, easing)) = seq.next_element()? else {
+            return Err(Error::invalid_length(1, &self));
+        };
+
+        let easing = match seq.next_element::<Option<Easing>>()? {
+            Some(Some(easing)) => Some(easing),
+            _ => easing,
+        };
+
+        if seq.next_element::<IgnoredAny>()?.is_some() {
+            return Err(Error::invalid_length(4, &self));
+        }
+
+        Ok(TimelineItem(time_of_day, TimelineEntry(palette, easing)))
+    }
+}
+
+impl<'de> serde::de::Deserialize<'de> for TimelineItem {
+    #[inline]
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where D: Deserializer<'de> {
+        deserializer.deserialize_seq(TimelineItemVisitor)
+    }
+}
+
+#[derive(Debug)]
+struct Timeline(pub Vec<(u32, TimelineEntry)>);
 
 struct TimelineVisitor;
 
@@ -141,14 +253,14 @@ impl<'de> Visitor<'de> for TimelineVisitor {
 
     fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
     where A: serde::de::SeqAccess<'de> {
-        let mut timeline: Vec<(u32, String)> = if let Some(size) = seq.size_hint() {
+        let mut timeline: Vec<(u32, TimelineEntry)> = if let Some(size) = seq.size_hint() {
             Vec::with_capacity(size)
         } else {
             Vec::new()
         };
 
-        while let Some(item) = seq.next_element()? {
-            timeline.push(item);
+        while let Some(TimelineItem(time_of_day, entry)) = seq.next_element()? {
+            timeline.push((time_of_day, entry));
         }
 
         timeline.sort_by(|a, b| a.0.cmp(&b.0));
@@ -169,8 +281,8 @@ impl<'de> Visitor<'de> for TimelineVisitor {
                 Ok(value) => value,
                 Err(err) => return Err(Error::custom(format_args!("illegal time of day in timeline: {:?}\n{}", time_of_day, err)))
             };
-            let name = map.next_value()?;
-            timeline.push((time_of_day, name));
+            let entry = map.next_value()?;
+            timeline.push((time_of_day, entry));
         }
 
         timeline.sort_by(|a, b| a.0.cmp(&b.0));
@@ -187,6 +299,49 @@ impl<'de> serde::de::Deserialize<'de> for Timeline {
     }
 }
 
+/// Named palettes in the order they appear in the file.
+#[derive(Debug)]
+struct NamedPalettes(pub Vec<(String, CycleImage)>);
+
+struct NamedPalettesVisitor;
+
+impl<'de> Visitor<'de> for NamedPalettesVisitor {
+    type Value = NamedPalettes;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a map of palette names to palettes")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where A: serde::de::MapAccess<'de> {
+        let mut palettes: Vec<(String, CycleImage)> = if let Some(size) = map.size_hint() {
+            Vec::with_capacity(size)
+        } else {
+            Vec::new()
+        };
+
+        while let Some(name) = map.next_key::<String>()? {
+            let image = map.next_value()?;
+            if let Some(item) = palettes.iter_mut().find(|(other, _)| *other == name) {
+                // last one wins, like it did with a HashMap
+                item.1 = image;
+            } else {
+                palettes.push((name, image));
+            }
+        }
+
+        Ok(NamedPalettes(palettes))
+    }
+}
+
+impl<'de> serde::de::Deserialize<'de> for NamedPalettes {
+    #[inline]
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where D: Deserializer<'de> {
+        deserializer.deserialize_map(NamedPalettesVisitor)
+    }
+}
+
 struct LivingWorldVisitor;
 
 impl<'de> Visitor<'de> for LivingWorldVisitor {
@@ -207,8 +362,9 @@ impl<'de> Visitor<'de> for LivingWorldVisitor {
         let mut format: Option<FormatInfo> = None;
         let mut data: Option<MagratheaWorldData> = None;
         let mut base: Option<CycleImage> = None;
-        let mut palettes_map: Option<HashMap<String, CycleImage>> = None;
+        let mut named_palettes: Option<NamedPalettes> = None;
         let mut named_timeline: Option<Timeline> = None;
+        let mut easing: Option<Easing> = None;
 
         while let Some(key) = map.next_key::<String>()? {
             match key.as_str() {
@@ -237,11 +393,14 @@ impl<'de> Visitor<'de> for LivingWorldVisitor {
                     base = Some(map.next_value()?);
                 }
                 "palettes" => {
-                    palettes_map = Some(map.next_value()?);
+                    named_palettes = Some(map.next_value()?);
                 }
                 "timeline" => {
                     named_timeline = Some(map.next_value()?);
                 }
+                "easing" => {
+                    easing = map.next_value()?;
+                }
                 "filename" => {
                     filename = Some(map.next_value()?);
                 }
@@ -252,13 +411,15 @@ impl<'de> Visitor<'de> for LivingWorldVisitor {
         }
 
         if let Some(base) = base {
-            let palettes_len: usize = if let Some(palettes) = &palettes_map { palettes.len() } else { 0 };
+            let palettes_len: usize = if let Some(NamedPalettes(palettes)) = &named_palettes { palettes.len() } else { 0 };
 
             let mut palettes = Vec::with_capacity(palettes_len);
+            let mut palette_names = Vec::with_capacity(palettes_len);
             let mut index_map = HashMap::with_capacity(palettes_len);
-            if let Some(palettes_map) = palettes_map {
-                for (index, (key, image)) in palettes_map.into_iter().enumerate() {
-                    index_map.insert(key, index);
+            if let Some(NamedPalettes(named_palettes)) = named_palettes {
+                for (index, (name, image)) in named_palettes.into_iter().enumerate() {
+                    index_map.insert(name.clone(), index);
+                    palette_names.push(name);
                     palettes.push(image);
                 }
             }
@@ -266,20 +427,27 @@ impl<'de> Visitor<'de> for LivingWorldVisitor {
             let timeline_len = if let Some(Timeline(timeline)) = &named_timeline { timeline.len() } else { 0 };
             let mut timeline = Vec::with_capacity(timeline_len);
             if let Some(Timeline(named_timeline)) = named_timeline {
-                for (time_of_day, palette_name) in named_timeline {
+                for (time_of_day, TimelineEntry(palette_name, easing)) in named_timeline {
                     if let Some(palette_index) = index_map.get(&palette_name) {
-                        timeline.push(TimedEvent::new(time_of_day, *palette_index));
+                        timeline.push(TimedEvent::new(time_of_day, *palette_index, easing));
                     } else {
                         return Err(Error::custom(format_args!("missing palette name referenced in timeline: {:?}", palette_name)));
                     }
                 }
             }
 
-            return Ok(LivingWorld::new(
+            let mut living_world = LivingWorld::new(
                 base.filename().map(|name| name.to_owned()),
                 base,
-                palettes.into_boxed_slice(), timeline.into_boxed_slice()),
-            );
+                palettes.into_boxed_slice(),
+                palette_names.into_boxed_slice(),
+                timeline.into_boxed_slice());
+
+            if let Some(easing) = easing {
+                living_world.set_easing(easing);
+            }
+
+            return Ok(living_world);
         }
 
         if let Some(format) = format {
@@ -422,7 +590,7 @@ impl<'de> Visitor<'de> for CycleVisitor {
 
     fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
     where A: serde::de::MapAccess<'de>, {
-        let mut reverse = false;
+        let mut mode = CycleMode::Forward;
         let mut rate = 0;
         let mut low = None;
         let mut high = None;
@@ -430,16 +598,13 @@ impl<'de> Visitor<'de> for CycleVisitor {
         while let Some(key) = map.next_key::<String>()? {
             match key.as_str() {
                 "reverse" => {
-                    let value: i32 = map.next_value()?;
-                    if value == 0 {
-                        reverse = false;
-                    } else if value == 2 {
-                        reverse = true;
-                    } else {
+                    let value: i64 = map.next_value()?;
+                    let Some(value) = CycleMode::from_value(value) else {
                         return Err(Error::invalid_value(
-                            serde::de::Unexpected::Signed(value as i64),
-                            &"0 or 2"));
-                    }
+                            serde::de::Unexpected::Signed(value),
+                            &"0 to 5"));
+                    };
+                    mode = value;
                 }
                 "rate" => {
                     rate = map.next_value()?;
@@ -464,7 +629,7 @@ impl<'de> Visitor<'de> for CycleVisitor {
             return Err(Error::missing_field("high"));
         };
 
-        Ok(Cycle::new(low, high, rate, reverse))
+        Ok(Cycle::new(low, high, rate, mode))
     }
 }
 
@@ -475,3 +640,165 @@ impl<'de> serde::de::Deserialize<'de> for Cycle {
         deserializer.deserialize_map(CycleVisitor)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cycle_modes() {
+        for (reverse, mode) in [
+            (0, CycleMode::Forward),
+            (1, CycleMode::Forward),
+            (2, CycleMode::Reverse),
+            (3, CycleMode::PingPong),
+            (4, CycleMode::Sine),
+            (5, CycleMode::SineFull),
+        ] {
+            let json = format!(r#"{{ "low": 16, "high": 31, "rate": 1400, "reverse": {reverse} }}"#);
+            let cycle: Cycle = serde_json::from_str(&json).unwrap();
+            assert_eq!(cycle, Cycle::new(16, 31, 1400, mode));
+        }
+
+        let cycle: Cycle = serde_json::from_str(r#"{ "low": 16, "high": 31 }"#).unwrap();
+        assert_eq!(cycle, Cycle::new(16, 31, 0, CycleMode::Forward));
+
+        for reverse in [-1, 6] {
+            let json = format!(r#"{{ "low": 16, "high": 31, "rate": 1400, "reverse": {reverse} }}"#);
+            assert!(serde_json::from_str::<Cycle>(&json).is_err());
+        }
+    }
+
+    fn timeline(json: &str) -> Result<Vec<(u32, String, Option<Easing>)>, serde_json::Error> {
+        let Timeline(timeline) = serde_json::from_str(json)?;
+        Ok(timeline.into_iter().map(|(time_of_day, TimelineEntry(palette, easing))| (time_of_day, palette, easing)).collect())
+    }
+
+    #[test]
+    fn timeline_easing() {
+        let expected = vec![
+            (21600, "Dawn".to_owned(), None),
+            (64800, "Dusk".to_owned(), Some(Easing::Fade(45 * 60 * 1000))),
+            (72000, "Night".to_owned(), Some(Easing::Hold(0.5))),
+        ];
+
+        assert_eq!(timeline(r#"{
+            "64800": { "palette": "Dusk", "easing": "fade:45m" },
+            "21600": "Dawn",
+            "72000": { "palette": "Night", "easing": "hold:0.5", "comment": "ignored" }
+        }"#).unwrap(), expected);
+
+        assert_eq!(timeline(r#"[
+            [64800, "Dusk", "fade:45m"],
+            [21600, "Dawn"],
+            [72000, { "palette": "Night", "easing": "hold:0.5" }]
+        ]"#).unwrap(), expected);
+
+        // null and missing easings
+        assert_eq!(timeline(r#"[[0, "Dawn", null], [1, { "palette": "Dusk", "easing": null }]]"#).unwrap(), [
+            (0, "Dawn".to_owned(), None),
+            (1, "Dusk".to_owned(), None),
+        ]);
+
+        for json in [
+            r#"{ "0": { "palette": "Dawn", "easing": "bounce" } }"#,
+            r#"[[0, "Dawn", "bounce"]]"#,
+            r#"[[0, "Dawn", "linear", "extra"]]"#,
+            r#"{ "0": { "easing": "linear" } }"#,
+        ] {
+            assert!(timeline(json).is_err(), "{json}");
+        }
+
+        let err = timeline(r#"[[0, "Dawn", "bounce"]]"#).unwrap_err();
+        assert!(err.to_string().contains("illegal easing"), "{err}");
+    }
+
+    /// A 1x1 image with every color set to `color`.
+    fn image_json(color: u8) -> String {
+        let colors = vec![format!("[{color}, {color}, {color}]"); 256].join(", ");
+        format!(r#"{{ "width": 1, "height": 1, "colors": [{colors}], "cycles": [], "pixels": [0] }}"#)
+    }
+
+    fn living_world(palettes: &[(&str, u8)], timeline: &str) -> LivingWorld {
+        let palettes: Vec<_> = palettes.iter()
+            .map(|(name, color)| format!("{name:?}: {}", image_json(*color)))
+            .collect();
+        let json = format!(r#"{{ "base": {}, "palettes": {{ {} }}, "timeline": {timeline} }}"#, image_json(0), palettes.join(", "));
+        serde_json::from_str(&json).unwrap()
+    }
+
+    fn color(living_world: &LivingWorld, index: usize) -> u8 {
+        living_world.palettes()[index].palette()[0].0[0]
+    }
+
+    #[test]
+    fn palette_names_in_file_order() {
+        let names = [
+            "Sunset", "Dawn", "Zenith", "Afternoon", "Midnight", "Noon", "Dusk",
+            "Morning", "Blue Hour", "Twilight", "Evening", "Golden Hour", "Night",
+            "Late Night", "Early Morning", "Storm",
+        ];
+        let palettes: Vec<_> = names.iter().enumerate().map(|(index, name)| (*name, index as u8)).collect();
+        let living_world = living_world(&palettes, r#"{ "0": "Storm", "43200": "Sunset" }"#);
+
+        assert_eq!(living_world.palette_names(), names);
+        for (index, name) in names.iter().enumerate() {
+            assert_eq!(living_world.palette_name(index), Some(*name));
+            assert_eq!(color(&living_world, index), index as u8);
+        }
+        assert_eq!(living_world.palette_name(names.len()), None);
+
+        let timeline: Vec<_> = living_world.timeline().iter().map(|event| event.palette_index()).collect();
+        assert_eq!(timeline, [15, 0]);
+    }
+
+    #[test]
+    fn duplicate_palette_name() {
+        let living_world = living_world(
+            &[("Day", 1), ("Night", 2), ("Day", 3), ("Dusk", 4)],
+            r#"{ "21600": "Day", "64800": "Dusk", "72000": "Night" }"#);
+
+        // the last definition wins, but keeps the position of the first
+        assert_eq!(living_world.palette_names(), ["Day", "Night", "Dusk"]);
+        assert_eq!(living_world.palettes().len(), 3);
+        assert_eq!(color(&living_world, 0), 3);
+        assert_eq!(color(&living_world, 1), 2);
+        assert_eq!(color(&living_world, 2), 4);
+
+        let timeline: Vec<_> = living_world.timeline().iter().map(|event| event.palette_index()).collect();
+        assert_eq!(timeline, [0, 2, 1]);
+    }
+
+    #[test]
+    fn timeline_at() {
+        const HOUR: u64 = 60 * 60 * 1000;
+        let living_world = living_world(
+            &[("Night", 0), ("Dawn", 1), ("Day", 2), ("Dusk", 3)],
+            r#"{ "64800": "Dusk", "21600": "Dawn", "43200": "Day", "72000": "Night" }"#);
+
+        let names_at = |time_of_day| {
+            let (palette1, palette2, mid) = living_world.timeline_at(time_of_day).unwrap();
+            (living_world.palette_name(palette1).unwrap(), living_world.palette_name(palette2).unwrap(), mid)
+        };
+
+        // before the first event it blends from the last to the first one
+        assert_eq!(names_at(0), ("Night", "Dawn", 0.0));
+        assert_eq!(names_at(3 * HOUR), ("Night", "Dawn", 0.5));
+
+        // between events
+        assert_eq!(names_at(6 * HOUR), ("Dawn", "Day", 0.0));
+        assert_eq!(names_at(9 * HOUR), ("Dawn", "Day", 0.5));
+        assert_eq!(names_at(15 * HOUR), ("Day", "Dusk", 0.5));
+        assert_eq!(names_at(19 * HOUR), ("Dusk", "Night", 0.5));
+
+        // past the last event it wraps around to the first one
+        assert_eq!(names_at(20 * HOUR), ("Night", "Dawn", 0.0));
+        assert_eq!(names_at(22 * HOUR), ("Night", "Dawn", 0.5));
+
+        let (palette1, palette2, _) = living_world.palettes_at(22 * HOUR).unwrap();
+        assert_eq!(palette1.palette()[0], Rgb([0, 0, 0]));
+        assert_eq!(palette2.palette()[0], Rgb([1, 1, 1]));
+
+        assert_eq!(LivingWorld::only_base(living_world.base().clone()).timeline_at(0), None);
+    }
+}
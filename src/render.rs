@@ -0,0 +1,491 @@
+// color-cycle - render color cycle images
+// Copyright (C) 2025  Mathias Panzenböck
+// 
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+// 
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+// 
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Conversion of the indexed image to the RGB24 pixels of the streaming texture.
+// This happens on the CPU, since SDL 2 renderers don't support palettized
+// textures (SDL_CreateTexture() fails with "Palettized textures are not
+// supported") and can't run a shader doing the lookup.
+
+use std::{fmt::Display, ops::Range, sync::{mpsc::{channel, Receiver, Sender}, Arc}, thread::JoinHandle, time::Duration};
+
+use crate::{color::Rgb, image::IndexedImage, palette::{Palette, BAYER_MATRIX}};
+
+pub fn convert_pixels(pixels: &mut [u8], pitch: usize, image: &IndexedImage, palette: &Palette) {
+    convert_rows(pixels, pitch, image, palette, 0..image.height());
+}
+
+/// Convert only the given rows. `pixels` starts at the first of them.
+pub fn convert_rows(pixels: &mut [u8], pitch: usize, image: &IndexedImage, palette: &Palette, rows: Range<u32>) {
+    let start_y = rows.start;
+    for y in rows {
+        let y_offset = (y - start_y) as usize * pitch;
+        for x in 0..image.width() {
+            let index = image.get_index(x, y);
+            let pixel_offset = y_offset + 3 * x as usize;
+            let Rgb([r, g, b]) = palette[index];
+            pixels[pixel_offset    ] = r;
+            pixels[pixel_offset + 1] = g;
+            pixels[pixel_offset + 2] = b;
+        }
+    }
+}
+
+/// Use `next_palette` instead of `palette` for pixels where the value of the
+/// Bayer matrix is less than the threshold of the palette index.
+pub fn convert_pixels_dithered(pixels: &mut [u8], pitch: usize, image: &IndexedImage, palette: &Palette, next_palette: &Palette, thresholds: &[u8; 256]) {
+    convert_rows_dithered(pixels, pitch, image, palette, next_palette, thresholds, 0..image.height());
+}
+
+/// Dithered version of [`convert_rows()`].
+pub fn convert_rows_dithered(pixels: &mut [u8], pitch: usize, image: &IndexedImage, palette: &Palette, next_palette: &Palette, thresholds: &[u8; 256], rows: Range<u32>) {
+    let start_y = rows.start;
+    for y in rows {
+        let y_offset = (y - start_y) as usize * pitch;
+        let bayer_row = &BAYER_MATRIX[y as usize % 4];
+        for x in 0..image.width() {
+            let index = image.get_index(x, y);
+            let pixel_offset = y_offset + 3 * x as usize;
+            let Rgb([r, g, b]) = if bayer_row[x as usize % 4] < thresholds[index as usize] {
+                next_palette[index]
+            } else {
+                palette[index]
+            };
+            pixels[pixel_offset    ] = r;
+            pixels[pixel_offset + 1] = g;
+            pixels[pixel_offset + 2] = b;
+        }
+    }
+}
+
+/// Pixel offsets grouped by palette index.
+#[derive(Debug, Clone)]
+pub struct IndexMap {
+    /// offsets (`x + y * width`) of all pixels, sorted by their palette index
+    offsets: Box<[u32]>,
+    /// `offsets[starts[index]..starts[index + 1]]` are the pixels with that index
+    starts: Box<[usize; 257]>,
+}
+
+impl IndexMap {
+    pub fn new(image: &IndexedImage) -> Self {
+        let data = image.data();
+        let mut starts = Box::new([0usize; 257]);
+        for &index in data {
+            starts[index as usize + 1] += 1;
+        }
+        for index in 0..256 {
+            starts[index + 1] += starts[index];
+        }
+
+        let mut next = starts.clone();
+        let mut offsets = vec![0u32; data.len()].into_boxed_slice();
+        for (offset, &index) in data.iter().enumerate() {
+            let next = &mut next[index as usize];
+            offsets[*next] = offset as u32;
+            *next += 1;
+        }
+
+        Self { offsets, starts }
+    }
+
+    #[inline]
+    pub fn pixels(&self, index: u8) -> &[u32] {
+        &self.offsets[self.starts[index as usize]..self.starts[index as usize + 1]]
+    }
+}
+
+/// CPU conversion that only rewrites the pixels whose palette entries
+/// changed since the previous frame. Keeps its own copy of the RGB24 pixels,
+/// since the contents of a locked texture are undefined. Only the rows
+/// between the first and the last changed pixel are uploaded again.
+#[derive(Debug, Clone)]
+pub struct ChangedPixelsRenderer {
+    index_map: IndexMap,
+    width: u32,
+    pixels: Box<[u8]>,
+    palette: Option<Palette>,
+}
+
+impl ChangedPixelsRenderer {
+    pub fn new(image: &IndexedImage) -> Self {
+        Self {
+            index_map: IndexMap::new(image),
+            width: image.width(),
+            pixels: vec![0u8; image.data().len() * 3].into_boxed_slice(),
+            palette: None,
+        }
+    }
+
+    #[inline]
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    #[inline]
+    pub fn pitch(&self) -> usize {
+        self.width as usize * 3
+    }
+
+    /// Repaint everything on the next update.
+    #[inline]
+    pub fn invalidate(&mut self) {
+        self.palette = None;
+    }
+
+    /// Apply the palette and return the rows that contain repainted pixels,
+    /// so only those need to be uploaded into the texture.
+    ///
+    /// If most of the pixels changed the whole image is converted instead,
+    /// using the `pool` if given.
+    pub fn update(&mut self, image: &IndexedImage, palette: &Palette, pool: Option<&mut ConversionPool>) -> Result<Range<u32>, String> {
+        let changed_count = if let Some(prev_palette) = &self.palette {
+            (0..=255u8)
+                .filter(|&index| prev_palette[index] != palette[index])
+                .map(|index| self.index_map.pixels(index).len())
+                .sum()
+        } else {
+            usize::MAX
+        };
+
+        let Some(prev_palette) = self.palette.as_mut().filter(|_| changed_count <= image.data().len() / 2) else {
+            let pitch = self.pitch();
+            if let Some(pool) = pool {
+                pool.convert(&mut self.pixels, pitch, palette, None)?;
+            } else {
+                convert_pixels(&mut self.pixels, pitch, image, palette);
+            }
+            if let Some(prev_palette) = &mut self.palette {
+                prev_palette.clone_from(palette);
+            } else {
+                self.palette = Some(palette.clone());
+            }
+            return Ok(0..image.height());
+        };
+
+        let width = self.width.max(1);
+        let mut first_offset = u32::MAX;
+        let mut last_offset = 0;
+        for index in 0..=255u8 {
+            let color = palette[index];
+            if prev_palette[index] == color {
+                continue;
+            }
+            prev_palette[index] = color;
+
+            let Rgb(rgb) = color;
+            let offsets = self.index_map.pixels(index);
+            for &offset in offsets {
+                let pixel_offset = offset as usize * 3;
+                self.pixels[pixel_offset..pixel_offset + 3].copy_from_slice(&rgb);
+            }
+
+            // the offsets of each index are sorted
+            if let (Some(&first), Some(&last)) = (offsets.first(), offsets.last()) {
+                first_offset = first_offset.min(first);
+                last_offset = last_offset.max(last);
+            }
+        }
+
+        if first_offset > last_offset {
+            return Ok(0..0);
+        }
+
+        Ok(first_offset / width..last_offset / width + 1)
+    }
+}
+
+/// A band of rows converted by one of the threads of a [`ConversionPool`].
+#[derive(Debug)]
+struct Job {
+    image: Arc<IndexedImage>,
+    palette: Arc<Palette>,
+    dither: Option<Arc<(Palette, [u8; 256])>>,
+    rows: Range<u32>,
+    band: usize,
+    pixels: Vec<u8>,
+}
+
+/// Worker threads that convert the image in bands of rows. Each band is
+/// converted with the same functions as on the main thread into a buffer of
+/// its own and then copied into the texture, so the output is identical.
+#[derive(Debug)]
+pub struct ConversionPool {
+    jobs: Vec<Sender<Job>>,
+    handles: Vec<JoinHandle<()>>,
+    results: Receiver<(usize, Vec<u8>)>,
+    buffers: Vec<Vec<u8>>,
+    image: Arc<IndexedImage>,
+}
+
+impl ConversionPool {
+    pub fn new(threads: usize, image: &IndexedImage) -> std::io::Result<Self> {
+        let (result_sender, results) = channel();
+        let mut jobs = Vec::with_capacity(threads);
+        let mut handles = Vec::with_capacity(threads);
+
+        for thread_index in 0..threads {
+            let (job_sender, job_receiver) = channel::<Job>();
+            let result_sender = result_sender.clone();
+            let handle = std::thread::Builder::new()
+                .name(format!("convert-{thread_index}"))
+                .spawn(move || {
+                    while let Ok(mut job) = job_receiver.recv() {
+                        let pitch = job.image.width() as usize * 3;
+                        if let Some(dither) = &job.dither {
+                            let (next_palette, thresholds) = &**dither;
+                            convert_rows_dithered(&mut job.pixels, pitch, &job.image, &job.palette, next_palette, thresholds, job.rows);
+                        } else {
+                            convert_rows(&mut job.pixels, pitch, &job.image, &job.palette, job.rows);
+                        }
+                        if result_sender.send((job.band, job.pixels)).is_err() {
+                            break;
+                        }
+                    }
+                })?;
+            jobs.push(job_sender);
+            handles.push(handle);
+        }
+
+        Ok(Self {
+            jobs,
+            handles,
+            results,
+            buffers: vec![Vec::new(); threads],
+            image: Arc::new(image.clone()),
+        })
+    }
+
+    #[inline]
+    pub fn threads(&self) -> usize {
+        self.jobs.len()
+    }
+
+    /// Image that is converted from now on.
+    #[inline]
+    pub fn set_image(&mut self, image: &IndexedImage) {
+        self.image = Arc::new(image.clone());
+    }
+
+    /// Like [`convert_pixels()`] or with `dither` like
+    /// [`convert_pixels_dithered()`], but split over all threads.
+    pub fn convert(&mut self, pixels: &mut [u8], pitch: usize, palette: &Palette, dither: Option<(&Palette, &[u8; 256])>) -> Result<(), String> {
+        let height = self.image.height();
+        let row_len = self.image.width() as usize * 3;
+        let band_height = height.div_ceil(self.jobs.len() as u32).max(1);
+        let palette = Arc::new(palette.clone());
+        let dither = dither.map(|(next_palette, thresholds)| Arc::new((next_palette.clone(), *thresholds)));
+
+        let mut pending = 0;
+        for (band, job_sender) in self.jobs.iter().enumerate() {
+            let start_y = band as u32 * band_height;
+            if start_y >= height {
+                break;
+            }
+            let rows = start_y..(start_y + band_height).min(height);
+
+            let mut band_pixels = std::mem::take(&mut self.buffers[band]);
+            band_pixels.resize(rows.len() * row_len, 0);
+
+            job_sender.send(Job {
+                image: self.image.clone(),
+                palette: palette.clone(),
+                dither: dither.clone(),
+                rows,
+                band,
+                pixels: band_pixels,
+            }).map_err(|_| "pixel conversion thread died".to_owned())?;
+            pending += 1;
+        }
+
+        for _ in 0..pending {
+            let (band, band_pixels) = self.results.recv()
+                .map_err(|_| "pixel conversion thread died".to_owned())?;
+
+            let start_y = band * band_height as usize;
+            for (row_index, row) in band_pixels.chunks_exact(row_len).enumerate() {
+                let offset = (start_y + row_index) * pitch;
+                pixels[offset..offset + row_len].copy_from_slice(row);
+            }
+
+            self.buffers[band] = band_pixels;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for ConversionPool {
+    fn drop(&mut self) {
+        // closing the channels ends the threads
+        self.jobs.clear();
+        for handle in self.handles.drain(..) {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Time spent on turning the indexed image into the pixels of the texture.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConversionStats {
+    frames: u64,
+    total: Duration,
+    max: Duration,
+}
+
+impl ConversionStats {
+    #[inline]
+    pub fn record(&mut self, duration: Duration) {
+        self.frames += 1;
+        self.total += duration;
+        self.max = self.max.max(duration);
+    }
+
+    #[inline]
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    #[inline]
+    pub fn average(&self) -> Duration {
+        if self.frames == 0 {
+            Duration::ZERO
+        } else {
+            self.total.div_f64(self.frames as f64)
+        }
+    }
+
+    #[inline]
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+}
+
+impl Display for ConversionStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} frames, avg {:.3} ms, max {:.3} ms",
+            self.frames,
+            self.average().as_secs_f64() * 1000.0,
+            self.max.as_secs_f64() * 1000.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_image(width: u32, height: u32) -> IndexedImage {
+        // simple LCG, so the indices are irregular but reproducible
+        let mut state = 0x2545F491u32;
+        let data: Vec<u8> = (0..width * height).map(|_| {
+            state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+            (state >> 24) as u8 % 64
+        }).collect();
+
+        let mut palette = Palette::default();
+        for index in 0..=255u8 {
+            palette[index] = Rgb([index, index.wrapping_mul(3), 255 - index]);
+        }
+
+        IndexedImage::from_buffer(width, height, data.into(), palette).unwrap()
+    }
+
+    fn full_conversion(image: &IndexedImage, palette: &Palette) -> Vec<u8> {
+        let pitch = image.width() as usize * 3;
+        let mut pixels = vec![0u8; pitch * image.height() as usize];
+        convert_pixels(&mut pixels, pitch, image, palette);
+        pixels
+    }
+
+    #[test]
+    fn index_map_groups_all_pixels() {
+        let image = test_image(37, 11);
+        let index_map = IndexMap::new(&image);
+
+        let mut total = 0;
+        for index in 0..=255u8 {
+            let offsets = index_map.pixels(index);
+            assert!(offsets.is_sorted());
+            for &offset in offsets {
+                assert_eq!(image.data()[offset as usize], index);
+            }
+            total += offsets.len();
+        }
+        assert_eq!(total, image.data().len());
+    }
+
+    #[test]
+    fn changed_pixels_match_full_conversion() {
+        let image = test_image(37, 11);
+        let mut renderer = ChangedPixelsRenderer::new(&image);
+        let mut palette = image.palette().clone();
+
+        assert_eq!(renderer.update(&image, &palette, None).unwrap(), 0..11);
+        assert_eq!(renderer.pixels(), &full_conversion(&image, &palette)[..]);
+
+        for step in 0..20u8 {
+            let prev_pixels = full_conversion(&image, &palette);
+            palette.rotate_right(8 + step % 3, 20 + step, 1);
+            if step % 5 == 0 {
+                // not used by any pixel
+                palette[200] = Rgb([step, step, step]);
+            }
+
+            let rows = renderer.update(&image, &palette, None).unwrap();
+            let pixels = full_conversion(&image, &palette);
+            assert_eq!(renderer.pixels(), &pixels[..]);
+
+            // every changed row is in the returned range
+            let pitch = renderer.pitch();
+            for (y, (prev_row, row)) in prev_pixels.chunks(pitch).zip(pixels.chunks(pitch)).enumerate() {
+                if prev_row != row {
+                    assert!(rows.contains(&(y as u32)), "row {y} changed, but not in {rows:?}");
+                }
+            }
+        }
+
+        // nothing changed
+        assert!(renderer.update(&image, &palette, None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn pool_matches_single_thread() {
+        let mut thresholds = [0u8; 256];
+        for (index, threshold) in thresholds.iter_mut().enumerate() {
+            *threshold = (index * 7 % 17) as u8;
+        }
+
+        for (width, height, threads) in [(37, 11, 4), (5, 3, 8), (16, 1, 3), (9, 7, 7), (1, 13, 2)] {
+            let image = test_image(width, height);
+            let palette = image.palette().clone();
+            let mut next_palette = palette.clone();
+            next_palette.rotate_left(0, 63, 5);
+
+            // with some padding at the end of each row, like a texture might have
+            let pitch = width as usize * 3 + 5;
+            let mut expected = vec![0u8; pitch * height as usize];
+            let mut pixels = vec![0u8; pitch * height as usize];
+            let mut pool = ConversionPool::new(threads, &image).unwrap();
+
+            convert_pixels(&mut expected, pitch, &image, &palette);
+            pool.convert(&mut pixels, pitch, &palette, None).unwrap();
+            assert_eq!(pixels, expected, "{width} x {height}, {threads} threads");
+
+            convert_pixels_dithered(&mut expected, pitch, &image, &palette, &next_palette, &thresholds);
+            pool.convert(&mut pixels, pitch, &palette, Some((&next_palette, &thresholds))).unwrap();
+            assert_eq!(pixels, expected, "{width} x {height}, {threads} threads, dithered");
+        }
+    }
+}
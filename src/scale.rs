@@ -0,0 +1,368 @@
+// color-cycle - render color cycle images
+// Copyright (C) 2025  Mathias Panzenböck
+// 
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+// 
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+// 
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Pixel-art upscaling of the indexed image. Since only indices are copied the
+// scaled image still color cycles, so this has to be done only once per image.
+
+use std::{fmt::Display, str::FromStr};
+
+use crate::{color::Rgb, image::IndexedImage};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ScaleFilter {
+    /// No filter, just let SDL scale the pixels.
+    #[default]
+    Nearest,
+    /// Scale2x (aka EPX) doubling the resolution.
+    Scale2x,
+    /// Scale3x tripling the resolution.
+    Scale3x,
+    /// xBR-like 2x edge detection. Since only indices can be used it doesn't
+    /// blend colors, but picks the neighbor the edge runs along. Edges are
+    /// detected using the colors of the base palette.
+    Xbr,
+}
+
+impl ScaleFilter {
+    #[inline]
+    pub fn next(self) -> Self {
+        match self {
+            ScaleFilter::Nearest => ScaleFilter::Scale2x,
+            ScaleFilter::Scale2x => ScaleFilter::Scale3x,
+            ScaleFilter::Scale3x => ScaleFilter::Xbr,
+            ScaleFilter::Xbr     => ScaleFilter::Nearest,
+        }
+    }
+
+    #[inline]
+    pub fn name(self) -> &'static str {
+        match self {
+            ScaleFilter::Nearest => "nearest",
+            ScaleFilter::Scale2x => "scale2x",
+            ScaleFilter::Scale3x => "scale3x",
+            ScaleFilter::Xbr     => "xbr",
+        }
+    }
+
+    #[inline]
+    pub fn factor(self) -> u32 {
+        match self {
+            ScaleFilter::Nearest => 1,
+            ScaleFilter::Scale2x => 2,
+            ScaleFilter::Scale3x => 3,
+            ScaleFilter::Xbr     => 2,
+        }
+    }
+
+    /// Returns `None` for [`ScaleFilter::Nearest`], meaning the image is
+    /// used as is.
+    pub fn scale(self, image: &IndexedImage) -> Option<IndexedImage> {
+        let factor = self.factor();
+        if factor == 1 || image.width() == 0 || image.height() == 0 {
+            return None;
+        }
+
+        let width  = image.width();
+        let height = image.height();
+        let dest_width = width * factor;
+        let mut data = vec![0u8; dest_width as usize * (height * factor) as usize].into_boxed_slice();
+
+        match self {
+            ScaleFilter::Nearest => {}
+            ScaleFilter::Scale2x => scale2x(image, dest_width, &mut data),
+            ScaleFilter::Scale3x => scale3x(image, dest_width, &mut data),
+            ScaleFilter::Xbr     => xbr2x(image, dest_width, &mut data),
+        }
+
+        IndexedImage::from_buffer(dest_width, height * factor, data, image.palette().clone())
+    }
+}
+
+impl Display for ScaleFilter {
+    #[inline]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for ScaleFilter {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        for filter in [ScaleFilter::Nearest, ScaleFilter::Scale2x, ScaleFilter::Scale3x, ScaleFilter::Xbr] {
+            if value.eq_ignore_ascii_case(filter.name()) {
+                return Ok(filter);
+            }
+        }
+
+        if value.eq_ignore_ascii_case("epx") {
+            return Ok(ScaleFilter::Scale2x);
+        }
+
+        Err(format!("illegal scale filter: {value:?}"))
+    }
+}
+
+/// Index at the given position, clamped to the edges of the image.
+#[inline]
+fn get_clamped(image: &IndexedImage, x: i64, y: i64) -> u8 {
+    let x = x.clamp(0, image.width() as i64 - 1) as u32;
+    let y = y.clamp(0, image.height() as i64 - 1) as u32;
+    image.get_index(x, y)
+}
+
+fn scale2x(image: &IndexedImage, dest_width: u32, data: &mut [u8]) {
+    let dest_width = dest_width as usize;
+    for y in 0..image.height() {
+        for x in 0..image.width() {
+            let (ix, iy) = (x as i64, y as i64);
+            let a = get_clamped(image, ix, iy - 1);
+            let b = get_clamped(image, ix + 1, iy);
+            let c = get_clamped(image, ix - 1, iy);
+            let d = get_clamped(image, ix, iy + 1);
+            let p = image.get_index(x, y);
+
+            let offset = 2 * y as usize * dest_width + 2 * x as usize;
+            data[offset] = if c == a && c != d && a != b { a } else { p };
+            data[offset + 1] = if a == b && a != c && b != d { b } else { p };
+            data[offset + dest_width] = if d == c && d != b && c != a { c } else { p };
+            data[offset + dest_width + 1] = if b == d && b != a && d != c { d } else { p };
+        }
+    }
+}
+
+fn scale3x(image: &IndexedImage, dest_width: u32, data: &mut [u8]) {
+    let dest_width = dest_width as usize;
+    for y in 0..image.height() {
+        for x in 0..image.width() {
+            let (ix, iy) = (x as i64, y as i64);
+            let a = get_clamped(image, ix - 1, iy - 1);
+            let b = get_clamped(image, ix,     iy - 1);
+            let c = get_clamped(image, ix + 1, iy - 1);
+            let d = get_clamped(image, ix - 1, iy);
+            let e = image.get_index(x, y);
+            let f = get_clamped(image, ix + 1, iy);
+            let g = get_clamped(image, ix - 1, iy + 1);
+            let h = get_clamped(image, ix,     iy + 1);
+            let i = get_clamped(image, ix + 1, iy + 1);
+
+            let offset = 3 * y as usize * dest_width + 3 * x as usize;
+            let row = &mut data[offset..offset + 3];
+            row[0] = if d == b && b != f && d != h { d } else { e };
+            row[1] = if (d == b && b != f && d != h && e != c) || (b == f && b != d && f != h && e != a) { b } else { e };
+            row[2] = if b == f && b != d && f != h { f } else { e };
+
+            let row = &mut data[offset + dest_width..offset + dest_width + 3];
+            row[0] = if (d == b && b != f && d != h && e != g) || (d == h && d != b && h != f && e != a) { d } else { e };
+            row[1] = e;
+            row[2] = if (b == f && b != d && f != h && e != i) || (h == f && d != h && b != f && e != c) { f } else { e };
+
+            let row = &mut data[offset + 2 * dest_width..offset + 2 * dest_width + 3];
+            row[0] = if d == h && d != b && h != f { d } else { e };
+            row[1] = if (d == h && d != b && h != f && e != i) || (h == f && d != h && b != f && e != g) { h } else { e };
+            row[2] = if h == f && d != h && b != f { f } else { e };
+        }
+    }
+}
+
+/// Weighted YUV distance used by xBR.
+#[inline]
+fn yuv_distance(yuv: &[[i32; 3]; 256], a: u8, b: u8) -> i32 {
+    let [y1, u1, v1] = yuv[a as usize];
+    let [y2, u2, v2] = yuv[b as usize];
+    48 * (y1 - y2).abs() + 7 * (u1 - u2).abs() + 6 * (v1 - v2).abs()
+}
+
+fn xbr2x(image: &IndexedImage, dest_width: u32, data: &mut [u8]) {
+    let mut yuv = [[0i32; 3]; 256];
+    for (index, yuv) in yuv.iter_mut().enumerate() {
+        let Rgb([r, g, b]) = image.palette()[index as u8];
+        let (r, g, b) = (r as i32, g as i32, b as i32);
+        *yuv = [
+            ( 299 * r + 587 * g + 114 * b) / 1000,
+            (-169 * r - 331 * g + 500 * b) / 1000,
+            ( 500 * r - 419 * g -  81 * b) / 1000,
+        ];
+    }
+
+    let dest_width = dest_width as usize;
+    for y in 0..image.height() {
+        for x in 0..image.width() {
+            let e = image.get_index(x, y);
+            let offset = 2 * y as usize * dest_width + 2 * x as usize;
+
+            // xBR only blends the corners of a single dot half way, picking
+            // the neighbor would make it vanish
+            let isolated = (-1..=1).all(|dy| (-1..=1).all(|dx|
+                (dx == 0 && dy == 0) || get_clamped(image, x as i64 + dx, y as i64 + dy) != e));
+
+            // Each output pixel is the corner of E towards (sx, sy). Written
+            // for the bottom right corner, the others are mirrored:
+            //
+            //       B
+            //    D  E  F  F4
+            //    G  H  I  I4
+            //          H5 I5
+            //
+            // (C is above F.)
+            for (sy, row_offset) in [(-1i64, 0), (1, dest_width)] {
+                for (sx, column_offset) in [(-1i64, 0), (1, 1)] {
+                    let get = |dx: i64, dy: i64| get_clamped(image, x as i64 + dx * sx, y as i64 + dy * sy);
+                    let b  = get( 0, -1);
+                    let c  = get( 1, -1);
+                    let d  = get(-1,  0);
+                    let f  = get( 1,  0);
+                    let g  = get(-1,  1);
+                    let h  = get( 0,  1);
+                    let i  = get( 1,  1);
+                    let f4 = get( 2,  0);
+                    let i4 = get( 2,  1);
+                    let h5 = get( 0,  2);
+                    let i5 = get( 1,  2);
+
+                    let dist = |a: u8, b: u8| yuv_distance(&yuv, a, b);
+
+                    // edge along F-H vs. along E-I
+                    let wd1 = dist(e, c) + dist(e, g) + dist(i, f4) + dist(i, h5) + 4 * dist(h, f);
+                    let wd2 = dist(d, h) + dist(h, i5) + dist(f, i4) + dist(b, f) + 4 * dist(e, i);
+
+                    data[offset + row_offset + column_offset] = if !isolated && wd1 < wd2 && e != f && e != h {
+                        if dist(e, f) <= dist(e, h) { f } else { h }
+                    } else {
+                        e
+                    };
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::palette::Palette;
+
+    fn image(width: u32, rows: &[&[u8]]) -> IndexedImage {
+        let mut palette = Palette::default();
+        palette[1] = Rgb([255, 255, 255]);
+        palette[2] = Rgb([255, 0, 0]);
+        IndexedImage::from_buffer(width, rows.len() as u32, rows.concat().into(), palette).unwrap()
+    }
+
+    fn scale(filter: ScaleFilter, width: u32, rows: &[&[u8]]) -> IndexedImage {
+        filter.scale(&image(width, rows)).unwrap()
+    }
+
+    /// Every pixel repeated `factor` times in both directions.
+    fn nearest(image: &IndexedImage, factor: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        for y in 0..image.height() * factor {
+            for x in 0..image.width() * factor {
+                data.push(image.get_index(x / factor, y / factor));
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn dimensions() {
+        let source = image(3, &[&[0, 1, 2], &[2, 1, 0]]);
+        assert!(ScaleFilter::Nearest.scale(&source).is_none());
+        assert!(ScaleFilter::Scale2x.scale(&IndexedImage::new(0, 2, Palette::default())).is_none());
+
+        for filter in [ScaleFilter::Scale2x, ScaleFilter::Scale3x, ScaleFilter::Xbr] {
+            let scaled = filter.scale(&source).unwrap();
+            assert_eq!(scaled.size(), (3 * filter.factor(), 2 * filter.factor()), "{filter}");
+            assert_eq!(scaled.palette(), source.palette());
+        }
+    }
+
+    #[test]
+    fn single_pixels_and_lines() {
+        // the clamped neighbors are the pixel itself, so nothing changes
+        for filter in [ScaleFilter::Scale2x, ScaleFilter::Scale3x, ScaleFilter::Xbr] {
+            for (width, rows) in [
+                (1, &[&[2u8][..]][..]),
+                (1, &[&[0], &[1], &[2], &[1]]),
+                (4, &[&[0, 1, 1, 2]]),
+            ] {
+                let source = image(width, rows);
+                let scaled = filter.scale(&source).unwrap();
+                assert_eq!(scaled.data(), nearest(&source, filter.factor()), "{filter} {rows:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn straight_edges() {
+        let rows: &[&[u8]] = &[&[1, 1, 1, 1], &[1, 1, 1, 1], &[0, 0, 0, 0], &[0, 0, 0, 0]];
+        for filter in [ScaleFilter::Scale2x, ScaleFilter::Scale3x, ScaleFilter::Xbr] {
+            let source = image(4, rows);
+            assert_eq!(filter.scale(&source).unwrap().data(), nearest(&source, filter.factor()), "{filter}");
+        }
+    }
+
+    #[test]
+    fn scale2x_diagonal() {
+        // E0 = D == B && B != F && D != H ? D : E etc. with the edges clamped
+        let scaled = scale(ScaleFilter::Scale2x, 3, &[&[1, 0, 0], &[0, 1, 0], &[0, 0, 1]]);
+        assert_eq!(scaled.data(), [
+            1, 1, 0, 0, 0, 0,
+            1, 0, 1, 0, 0, 0,
+            0, 1, 1, 1, 0, 0,
+            0, 0, 1, 1, 1, 0,
+            0, 0, 0, 1, 0, 1,
+            0, 0, 0, 0, 1, 1,
+        ]);
+    }
+
+    #[test]
+    fn scale3x_diagonal() {
+        let scaled = scale(ScaleFilter::Scale3x, 2, &[&[1, 0], &[0, 1]]);
+        assert_eq!(scaled.data(), [
+            1, 1, 1, 0, 0, 0,
+            1, 1, 0, 1, 0, 0,
+            1, 0, 0, 1, 1, 0,
+            0, 1, 1, 0, 0, 1,
+            0, 0, 1, 0, 1, 1,
+            0, 0, 0, 1, 1, 1,
+        ]);
+    }
+
+    #[test]
+    fn isolated_pixel() {
+        let rows: &[&[u8]] = &[&[0, 0, 0], &[0, 1, 0], &[0, 0, 0]];
+        for filter in [ScaleFilter::Scale2x, ScaleFilter::Scale3x, ScaleFilter::Xbr] {
+            let source = image(3, rows);
+            assert_eq!(filter.scale(&source).unwrap().data(), nearest(&source, filter.factor()), "{filter}");
+        }
+    }
+
+    #[test]
+    fn xbr_diagonal() {
+        // the corners beside a diagonal line take the background color
+        let scaled = scale(ScaleFilter::Xbr, 4, &[&[1, 0, 0, 0], &[0, 1, 0, 0], &[0, 0, 1, 0], &[0, 0, 0, 1]]);
+        let block = |x: u32, y: u32| [
+            scaled.get_index(2 * x, 2 * y), scaled.get_index(2 * x + 1, 2 * y),
+            scaled.get_index(2 * x, 2 * y + 1), scaled.get_index(2 * x + 1, 2 * y + 1),
+        ];
+        assert_eq!(block(1, 1), [1, 0, 0, 1]);
+        assert_eq!(block(2, 2), [1, 0, 0, 1]);
+        assert!(scaled.data().iter().all(|&index| index <= 1));
+    }
+}
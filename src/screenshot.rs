@@ -0,0 +1,213 @@
+// color-cycle - render color cycle images
+// Copyright (C) 2025  Mathias Panzenböck
+// 
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+// 
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+// 
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::{fmt::Display, fs::{File, OpenOptions}, io::{BufWriter, Write}, path::{Path, PathBuf}, str::FromStr};
+
+use crate::{error::Error, image::{IndexedImage, RgbImage}, palette::{Cycle, Palette}, render};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ScreenshotFormat {
+    /// True-color PNG of the current frame.
+    PNG,
+    /// Indexed ILBM with the current palette state and its cycles.
+    ILBM,
+}
+
+impl ScreenshotFormat {
+    #[inline]
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ScreenshotFormat::PNG  => "png",
+            ScreenshotFormat::ILBM => "ilbm",
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_string_lossy().to_ascii_lowercase();
+        match extension.as_str() {
+            "png" => Some(ScreenshotFormat::PNG),
+            "ilbm" | "lbm" | "iff" => Some(ScreenshotFormat::ILBM),
+            _ => None,
+        }
+    }
+}
+
+impl Display for ScreenshotFormat {
+    #[inline]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScreenshotFormat::PNG  => "png".fmt(f),
+            ScreenshotFormat::ILBM => "ilbm".fmt(f),
+        }
+    }
+}
+
+impl FromStr for ScreenshotFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "png" => Ok(ScreenshotFormat::PNG),
+            "ilbm" | "lbm" | "iff" => Ok(ScreenshotFormat::ILBM),
+            _ => Err(format!("unsupported screenshot format: {value:?}")),
+        }
+    }
+}
+
+/// Create the screenshot file at `path`, or if that is `None` the first
+/// `{stem}-{number}.{extension}` in `dir` that doesn't exist yet. Numbered
+/// files are created exclusively, so two screenshots saved at the same time
+/// never get the same file.
+pub fn create_screenshot_file(path: Option<PathBuf>, dir: &Path, stem: &str, format: ScreenshotFormat) -> Result<(PathBuf, File), Error> {
+    if let Some(path) = path {
+        return match File::create(&path) {
+            Ok(file) => Ok((path, file)),
+            Err(err) => Err(Error::with_source(path.to_string_lossy().into_owned(), Box::new(err))),
+        };
+    }
+
+    let extension = format.extension();
+    for number in 1u32.. {
+        let path = dir.join(format!("{stem}-{number:04}.{extension}"));
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {}
+            Err(err) => return Err(Error::with_source(path.to_string_lossy().into_owned(), Box::new(err))),
+        }
+    }
+
+    Err(Error::new(format!("no free screenshot file name in {}", dir.to_string_lossy())))
+}
+
+/// Save the frame shown with `palette`, or for dithered blending with
+/// `dither` as in [`render::convert_pixels_dithered()`]. ILBM files can only
+/// hold one palette, so they get the undithered `palette`.
+pub fn save_screenshot(file: File, format: ScreenshotFormat, image: &IndexedImage, palette: &Palette, dither: Option<(&Palette, &[u8; 256])>, cycles: &[Cycle]) -> Result<(), Error> {
+    let mut writer = BufWriter::new(file);
+
+    match format {
+        ScreenshotFormat::PNG => {
+            let pitch = image.width() as usize * 3;
+            let mut data = vec![0u8; image.data().len() * 3];
+            if let Some((next_palette, thresholds)) = dither {
+                render::convert_pixels_dithered(&mut data, pitch, image, palette, next_palette, thresholds);
+            } else {
+                render::convert_pixels(&mut data, pitch, image, palette);
+            }
+
+            let mut encoder = png::Encoder::new(&mut writer, image.width(), image.height());
+            encoder.set_color(png::ColorType::Rgb);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut png_writer = encoder.write_header()?;
+            png_writer.write_image_data(&data)?;
+            png_writer.finish()?;
+        }
+        ScreenshotFormat::ILBM => {
+            let mut image = image.clone();
+            image.palette_mut().clone_from(palette);
+            crate::ilbm::write_ilbm(&mut writer, &image, cycles)?;
+        }
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// True-color images have no palette, so they can only be saved as PNG.
+#[inline]
+pub fn supports_true_color(format: ScreenshotFormat) -> bool {
+    format == ScreenshotFormat::PNG
+}
+
+pub fn save_true_color_screenshot(file: File, image: &RgbImage) -> Result<(), Error> {
+    let mut writer = BufWriter::new(file);
+
+    let mut encoder = png::Encoder::new(&mut writer, image.width(), image.height());
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut png_writer = encoder.write_header()?;
+    png_writer.write_image_data(image.data())?;
+    png_writer.finish()?;
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::color::Rgb;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("color-cycle-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn numbered_files() {
+        let dir = test_dir("numbered");
+        std::fs::write(dir.join("scene-0002.png"), b"keep").unwrap();
+
+        let names: Vec<_> = (0..3).map(|_| {
+            let (path, _file) = create_screenshot_file(None, &dir, "scene", ScreenshotFormat::PNG).unwrap();
+            path.file_name().unwrap().to_string_lossy().into_owned()
+        }).collect();
+        assert_eq!(names, ["scene-0001.png", "scene-0003.png", "scene-0004.png"]);
+        assert_eq!(std::fs::read(dir.join("scene-0002.png")).unwrap(), b"keep");
+
+        let (path, _file) = create_screenshot_file(None, &dir, "scene", ScreenshotFormat::ILBM).unwrap();
+        assert_eq!(path, dir.join("scene-0001.ilbm"));
+
+        let path = dir.join("named.png");
+        assert_eq!(create_screenshot_file(Some(path.clone()), &dir, "scene", ScreenshotFormat::PNG).unwrap().0, path);
+        assert!(create_screenshot_file(None, &dir.join("missing"), "scene", ScreenshotFormat::PNG).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn dithered_png() {
+        let dir = test_dir("dithered");
+        let mut palette = Palette::default();
+        let mut next_palette = Palette::default();
+        palette[1] = Rgb([10, 20, 30]);
+        next_palette[1] = Rgb([200, 100, 50]);
+        let mut thresholds = [0u8; 256];
+        thresholds[1] = 8;
+        let image = IndexedImage::from_index(4, 4, 1, palette.clone());
+
+        let path = dir.join("dithered.png");
+        save_screenshot(File::create(&path).unwrap(), ScreenshotFormat::PNG, &image, &palette, Some((&next_palette, &thresholds)), &[]).unwrap();
+
+        let decoder = png::Decoder::new(File::open(&path).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let mut data = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut data).unwrap();
+
+        for (offset, rgb) in data.chunks_exact(3).enumerate() {
+            let (x, y) = (offset % 4, offset / 4);
+            let expected = if crate::palette::BAYER_MATRIX[y][x] < 8 { next_palette[1] } else { palette[1] };
+            assert_eq!(rgb, expected.0, "pixel {x}, {y}");
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,227 @@
+// color-cycle - render color cycle images
+// Copyright (C) 2025  Mathias Panzenböck
+// 
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+// 
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+// 
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Frame timing statistics for the performance HUD.
+
+use std::{collections::VecDeque, time::{Duration, Instant}};
+
+/// Number of frames the statistics are calculated over.
+pub const FRAME_STATS_WINDOW: usize = 240;
+
+/// Where the time of a single frame went.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameTiming {
+    /// Time since the start of the previous frame.
+    pub interval: Duration,
+    /// Cycling and blending the palettes.
+    pub palette: Duration,
+    /// Turning the palette indices into pixels.
+    pub pixels: Duration,
+    /// Presenting the canvas.
+    pub present: Duration,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FrameStats {
+    timings: VecDeque<FrameTiming>,
+    dropped: u64,
+    prev_frame_start_ts: Option<Instant>,
+}
+
+impl FrameStats {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forget when the previous frame started, so the time it takes to load
+    /// the next image isn't counted as dropped frames.
+    #[inline]
+    pub fn restart(&mut self) {
+        self.prev_frame_start_ts = None;
+    }
+
+    /// A frame counts as dropped if it started more than 1.5 frame durations
+    /// after the previous one. Longer gaps count as several dropped frames.
+    pub fn record(&mut self, frame_start_ts: Instant, frame_duration: Duration, palette: Duration, pixels: Duration, present: Duration) {
+        let Some(prev_frame_start_ts) = self.prev_frame_start_ts.replace(frame_start_ts) else {
+            return;
+        };
+
+        let interval = frame_start_ts - prev_frame_start_ts;
+        if interval > frame_duration * 3 / 2 {
+            self.dropped += (interval.as_secs_f64() / frame_duration.as_secs_f64()).round() as u64 - 1;
+        }
+
+        if self.timings.len() >= FRAME_STATS_WINDOW {
+            self.timings.pop_front();
+        }
+        self.timings.push_back(FrameTiming { interval, palette, pixels, present });
+    }
+
+    #[inline]
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Actual frames per second.
+    pub fn fps(&self) -> f64 {
+        let total: Duration = self.timings.iter().map(|timing| timing.interval).sum();
+        if total.is_zero() {
+            0.0
+        } else {
+            self.timings.len() as f64 / total.as_secs_f64()
+        }
+    }
+
+    /// Frame interval that `percent` of the frames don't exceed.
+    pub fn percentile(&self, percent: f64) -> Duration {
+        if self.timings.is_empty() {
+            return Duration::ZERO;
+        }
+
+        let mut intervals: Vec<_> = self.timings.iter().map(|timing| timing.interval).collect();
+        intervals.sort_unstable();
+
+        let index = ((intervals.len() - 1) as f64 * percent / 100.0).round() as usize;
+        intervals[index]
+    }
+
+    /// Average of the times selected by `get`.
+    pub fn average(&self, get: impl Fn(&FrameTiming) -> Duration) -> Duration {
+        if self.timings.is_empty() {
+            return Duration::ZERO;
+        }
+
+        let total: Duration = self.timings.iter().map(get).sum();
+        total / self.timings.len() as u32
+    }
+
+    /// Lines of text shown in the HUD.
+    pub fn hud_lines(&self, target_fps: u32) -> [String; 4] {
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        [
+            format!(" FPS: {:.1} / {target_fps} ", self.fps()),
+            format!(" Frame: p50 {:.1} ms, p95 {:.1} ms, p99 {:.1} ms ",
+                ms(self.percentile(50.0)),
+                ms(self.percentile(95.0)),
+                ms(self.percentile(99.0))),
+            format!(" Palette {:.2} ms, pixels {:.2} ms, present {:.2} ms ",
+                ms(self.average(|timing| timing.palette)),
+                ms(self.average(|timing| timing.pixels)),
+                ms(self.average(|timing| timing.present))),
+            format!(" Dropped frames: {} ", self.dropped),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME_DURATION: Duration = Duration::from_millis(20);
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn breakdown() {
+        let mut stats = FrameStats::new();
+        assert_eq!(stats.fps(), 0.0);
+        assert_eq!(stats.percentile(50.0), Duration::ZERO);
+        assert_eq!(stats.average(|timing| timing.palette), Duration::ZERO);
+
+        let mut frame_start_ts = Instant::now();
+        // the first frame has no interval and isn't counted
+        stats.record(frame_start_ts, FRAME_DURATION, ms(100), ms(100), ms(100));
+
+        for index in 0..10 {
+            frame_start_ts += FRAME_DURATION;
+            let palette = if index % 2 == 0 { ms(1) } else { ms(3) };
+            stats.record(frame_start_ts, FRAME_DURATION, palette, ms(4), Duration::from_micros(500 * index));
+        }
+
+        assert!((stats.fps() - 50.0).abs() < 1e-9);
+        assert_eq!(stats.percentile(50.0), FRAME_DURATION);
+        assert_eq!(stats.average(|timing| timing.palette), ms(2));
+        assert_eq!(stats.average(|timing| timing.pixels), ms(4));
+        assert_eq!(stats.average(|timing| timing.present), Duration::from_micros(2250));
+        assert_eq!(stats.dropped(), 0);
+
+        assert_eq!(stats.hud_lines(50), [
+            " FPS: 50.0 / 50 ".to_owned(),
+            " Frame: p50 20.0 ms, p95 20.0 ms, p99 20.0 ms ".to_owned(),
+            " Palette 2.00 ms, pixels 4.00 ms, present 2.25 ms ".to_owned(),
+            " Dropped frames: 0 ".to_owned(),
+        ]);
+    }
+
+    #[test]
+    fn dropped_frames() {
+        let mut stats = FrameStats::new();
+        let mut frame_start_ts = Instant::now();
+        stats.record(frame_start_ts, FRAME_DURATION, ms(1), ms(1), ms(1));
+
+        // up to 1.5 frame durations is still on time
+        frame_start_ts += ms(30);
+        stats.record(frame_start_ts, FRAME_DURATION, ms(1), ms(1), ms(1));
+        assert_eq!(stats.dropped(), 0);
+
+        // four frames missing
+        frame_start_ts += ms(100);
+        stats.record(frame_start_ts, FRAME_DURATION, ms(1), ms(1), ms(1));
+        assert_eq!(stats.dropped(), 4);
+        assert_eq!(stats.percentile(0.0), ms(30));
+        assert_eq!(stats.percentile(100.0), ms(100));
+
+        // loading an image doesn't drop frames
+        stats.restart();
+        frame_start_ts += ms(5000);
+        stats.record(frame_start_ts, FRAME_DURATION, ms(1), ms(1), ms(1));
+        assert_eq!(stats.dropped(), 4);
+        assert_eq!(stats.percentile(100.0), ms(100));
+    }
+
+    #[test]
+    fn window() {
+        let mut stats = FrameStats::new();
+        let mut frame_start_ts = Instant::now();
+        stats.record(frame_start_ts, FRAME_DURATION, ms(1), ms(1), ms(1));
+
+        for _ in 0..FRAME_STATS_WINDOW {
+            frame_start_ts += ms(40);
+            stats.record(frame_start_ts, FRAME_DURATION * 2, ms(10), ms(20), ms(30));
+        }
+        assert_eq!(stats.average(|timing| timing.palette), ms(10));
+        assert!((stats.fps() - 25.0).abs() < 1e-9);
+
+        // the older frames drop out of the window
+        for index in 0..FRAME_STATS_WINDOW as u32 {
+            frame_start_ts += FRAME_DURATION;
+            stats.record(frame_start_ts, FRAME_DURATION, ms(1), ms(2), ms(3));
+
+            let old = FRAME_STATS_WINDOW as u32 - index - 1;
+            let expected = (ms(10) * old + ms(1) * (index + 1)) / FRAME_STATS_WINDOW as u32;
+            assert_eq!(stats.average(|timing| timing.palette), expected);
+        }
+        assert_eq!(stats.average(|timing| timing.pixels), ms(2));
+        assert_eq!(stats.average(|timing| timing.present), ms(3));
+        assert!((stats.fps() - 50.0).abs() < 1e-9);
+        assert_eq!(stats.percentile(100.0), FRAME_DURATION);
+        assert_eq!(stats.dropped(), 0);
+    }
+}
@@ -0,0 +1,267 @@
+// color-cycle - render color cycle images
+// Copyright (C) 2025  Mathias Panzenböck
+// 
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+// 
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+// 
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Sunrise equation as described in https://en.wikipedia.org/wiki/Sunrise_equation
+// It is accurate to about a minute, which is plenty for picking a palette.
+
+use std::{fmt::Display, str::FromStr};
+
+const DAY_DURATION: f64 = crate::image::living_world::DAY_DURATION as f64;
+const UNIX_EPOCH_JULIAN_DAY: f64 = 2440587.5;
+const J2000: f64 = 2451545.0;
+
+/// Shortest and longest half day that is used for the timeline, so that
+/// during polar night and midnight sun there is still a short dusk/dawn.
+const MIN_HALF_DAY: f64 = 1.0 * 60.0 * 60.0 * 1000.0;
+const MAX_HALF_DAY: f64 = 11.0 * 60.0 * 60.0 * 1000.0;
+
+/// Times of day the Living Worlds timelines were made for.
+const SCENE_SUNRISE: f64 = 6.0 * 60.0 * 60.0 * 1000.0;
+const SCENE_NOON:    f64 = 12.0 * 60.0 * 60.0 * 1000.0;
+const SCENE_SUNSET:  f64 = 18.0 * 60.0 * 60.0 * 1000.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Location {
+    /// degrees north
+    latitude: f64,
+    /// degrees east
+    longitude: f64,
+}
+
+impl Location {
+    #[inline]
+    pub fn new(latitude: f64, longitude: f64) -> Self {
+        Self { latitude, longitude }
+    }
+
+    #[inline]
+    pub fn latitude(&self) -> f64 {
+        self.latitude
+    }
+
+    #[inline]
+    pub fn longitude(&self) -> f64 {
+        self.longitude
+    }
+}
+
+impl Display for Location {
+    #[inline]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{}", self.latitude, self.longitude)
+    }
+}
+
+impl FromStr for Location {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let Some((latitude, longitude)) = value.split_once(',') else {
+            return Err(format!("illegal location: {value:?}, expected LATITUDE,LONGITUDE"));
+        };
+
+        let (Ok(latitude), Ok(longitude)) = (latitude.trim().parse::<f64>(), longitude.trim().parse::<f64>()) else {
+            return Err(format!("illegal location: {value:?}, expected LATITUDE,LONGITUDE"));
+        };
+
+        if !(-90.0..=90.0).contains(&latitude) {
+            return Err(format!("illegal location: {value:?}, latitude out of range"));
+        }
+
+        if !(-180.0..=180.0).contains(&longitude) {
+            return Err(format!("illegal location: {value:?}, longitude out of range"));
+        }
+
+        Ok(Self { latitude, longitude })
+    }
+}
+
+/// Solar noon and length of the day for one date and location.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SunTimes {
+    /// local time of day of solar noon in milliseconds
+    noon: f64,
+    /// half of the time between sunrise and sunset in milliseconds
+    half_day: f64,
+}
+
+impl SunTimes {
+    /// `now` is in milliseconds since the Unix epoch (UTC) and `utc_offset`
+    /// is in seconds.
+    pub fn new(location: &Location, now: i128, utc_offset: i64) -> Self {
+        let julian_day = now as f64 / DAY_DURATION + UNIX_EPOCH_JULIAN_DAY;
+        let n = (julian_day - J2000 + 0.0008).ceil();
+        let mean_solar_time = n - location.longitude / 360.0;
+
+        let mean_anomaly = (357.5291 + 0.98560028 * mean_solar_time).rem_euclid(360.0);
+        let m = mean_anomaly.to_radians();
+        let center = 1.9148 * m.sin() + 0.0200 * (2.0 * m).sin() + 0.0003 * (3.0 * m).sin();
+        let ecliptic_longitude = (mean_anomaly + center + 180.0 + 102.9372).rem_euclid(360.0).to_radians();
+
+        let transit = J2000 + mean_solar_time + 0.0053 * m.sin() - 0.0069 * (2.0 * ecliptic_longitude).sin();
+
+        let sin_declination = ecliptic_longitude.sin() * 23.4397f64.to_radians().sin();
+        let cos_declination = sin_declination.asin().cos();
+        let latitude = location.latitude.to_radians();
+
+        let cos_hour_angle = ((-0.833f64).to_radians().sin() - latitude.sin() * sin_declination) /
+            (latitude.cos() * cos_declination);
+        let hour_angle = cos_hour_angle.clamp(-1.0, 1.0).acos().to_degrees();
+
+        let noon = (transit - UNIX_EPOCH_JULIAN_DAY) * DAY_DURATION + utc_offset as f64 * 1000.0;
+        let half_day = (hour_angle / 360.0 * DAY_DURATION).clamp(MIN_HALF_DAY, MAX_HALF_DAY);
+
+        Self {
+            noon: noon.rem_euclid(DAY_DURATION),
+            half_day,
+        }
+    }
+
+    /// Local time of day of sunrise in milliseconds.
+    #[inline]
+    pub fn sunrise(&self) -> u64 {
+        (self.noon - self.half_day).rem_euclid(DAY_DURATION) as u64
+    }
+
+    /// Local time of day of solar noon in milliseconds.
+    #[inline]
+    pub fn noon(&self) -> u64 {
+        self.noon as u64
+    }
+
+    /// Local time of day of sunset in milliseconds.
+    #[inline]
+    pub fn sunset(&self) -> u64 {
+        (self.noon + self.half_day).rem_euclid(DAY_DURATION) as u64
+    }
+
+    /// Map the local time of day to the time of day of a timeline that
+    /// assumes sunrise at 6:00, noon at 12:00 and sunset at 18:00.
+    pub fn scene_time(&self, time_of_day: u64) -> u64 {
+        // relative to solar midnight
+        let time = (time_of_day as f64 - self.noon + SCENE_NOON).rem_euclid(DAY_DURATION);
+        let sunrise = SCENE_NOON - self.half_day;
+        let sunset  = SCENE_NOON + self.half_day;
+
+        let scene_time = if time < sunrise {
+            time / sunrise * SCENE_SUNRISE
+        } else if time < SCENE_NOON {
+            SCENE_SUNRISE + (time - sunrise) / self.half_day * (SCENE_NOON - SCENE_SUNRISE)
+        } else if time < sunset {
+            SCENE_NOON + (time - SCENE_NOON) / self.half_day * (SCENE_SUNSET - SCENE_NOON)
+        } else {
+            SCENE_SUNSET + (time - sunset) / (DAY_DURATION - sunset) * (DAY_DURATION - SCENE_SUNSET)
+        };
+
+        (scene_time as u64).min(DAY_DURATION as u64 - 1)
+    }
+
+    /// Inverse of [`SunTimes::scene_time()`].
+    pub fn real_time(&self, scene_time: u64) -> u64 {
+        let scene_time = scene_time as f64;
+        let sunrise = SCENE_NOON - self.half_day;
+        let sunset  = SCENE_NOON + self.half_day;
+
+        let time = if scene_time < SCENE_SUNRISE {
+            scene_time / SCENE_SUNRISE * sunrise
+        } else if scene_time < SCENE_NOON {
+            sunrise + (scene_time - SCENE_SUNRISE) / (SCENE_NOON - SCENE_SUNRISE) * self.half_day
+        } else if scene_time < SCENE_SUNSET {
+            SCENE_NOON + (scene_time - SCENE_NOON) / (SCENE_SUNSET - SCENE_NOON) * self.half_day
+        } else {
+            sunset + (scene_time - SCENE_SUNSET) / (DAY_DURATION - SCENE_SUNSET) * (DAY_DURATION - sunset)
+        };
+
+        (time + self.noon - SCENE_NOON).rem_euclid(DAY_DURATION) as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: u64 = 60 * 1000;
+    const HOUR: u64 = 60 * MINUTE;
+
+    /// 12:00 UTC of the given day
+    const SUMMER_SOLSTICE_2024: i128 = 1_718_971_200_000;
+    const WINTER_SOLSTICE_2024: i128 = 1_734_782_400_000;
+
+    fn assert_time(actual: u64, hours: u64, minutes: u64) {
+        // one minute accuracy plus the rounding of the published times
+        let expected = hours * HOUR + minutes * MINUTE;
+        assert!(actual.abs_diff(expected) <= MINUTE + MINUTE / 2,
+            "{actual} ms ({}:{:02}) instead of {hours}:{minutes:02}", actual / HOUR, actual / MINUTE % 60);
+    }
+
+    #[test]
+    fn published_sun_times() {
+        // Vienna, CEST
+        let sun_times = SunTimes::new(&Location::new(48.2082, 16.3738), SUMMER_SOLSTICE_2024, 2 * 60 * 60);
+        assert_time(sun_times.sunrise(), 4, 53);
+        assert_time(sun_times.sunset(), 20, 58);
+
+        // London, GMT
+        let sun_times = SunTimes::new(&Location::new(51.5074, -0.1278), WINTER_SOLSTICE_2024, 0);
+        assert_time(sun_times.sunrise(), 8, 3);
+        assert_time(sun_times.sunset(), 15, 53);
+    }
+
+    #[test]
+    fn polar_clamps() {
+        let svalbard = Location::new(78.0, 15.0);
+
+        // midnight sun
+        let sun_times = SunTimes::new(&svalbard, SUMMER_SOLSTICE_2024, 2 * 60 * 60);
+        assert_eq!(sun_times.half_day, MAX_HALF_DAY);
+
+        // polar night
+        let sun_times = SunTimes::new(&svalbard, WINTER_SOLSTICE_2024, 60 * 60);
+        assert_eq!(sun_times.half_day, MIN_HALF_DAY);
+        assert_eq!(sun_times.sunset() - sun_times.sunrise(), 2 * HOUR);
+    }
+
+    #[test]
+    fn scene_time_round_trip() {
+        // times are truncated to whole milliseconds, the mapping stretches
+        // them by up to 6 times
+        const TOLERANCE: u64 = 10;
+
+        for (location, now, utc_offset) in [
+            (Location::new(48.2082, 16.3738), SUMMER_SOLSTICE_2024, 2 * 60 * 60),
+            (Location::new(51.5074, -0.1278), WINTER_SOLSTICE_2024, 0),
+            (Location::new(-33.8688, 151.2093), WINTER_SOLSTICE_2024, 11 * 60 * 60),
+            (Location::new(78.0, 15.0), SUMMER_SOLSTICE_2024, 2 * 60 * 60),
+            (Location::new(78.0, 15.0), WINTER_SOLSTICE_2024, 60 * 60),
+        ] {
+            let sun_times = SunTimes::new(&location, now, utc_offset);
+            let sunrise = sun_times.scene_time(sun_times.sunrise());
+            let sunset = sun_times.scene_time(sun_times.sunset());
+            assert!(sunrise.abs_diff(SCENE_SUNRISE as u64) <= TOLERANCE, "{location}: sunrise at {sunrise}");
+            assert!(sunset.abs_diff(SCENE_SUNSET as u64) <= TOLERANCE, "{location}: sunset at {sunset}");
+
+            for time in (0..DAY_DURATION as u64).step_by(MINUTE as usize) {
+                let scene_time = sun_times.scene_time(time);
+                assert!(scene_time < DAY_DURATION as u64);
+
+                // compare on the circle, 23:59:59.999 is close to 0:00
+                let diff = sun_times.real_time(scene_time).abs_diff(time);
+                let diff = diff.min(DAY_DURATION as u64 - diff);
+                assert!(diff <= TOLERANCE, "{location}: {time} -> {scene_time} -> {}", sun_times.real_time(scene_time));
+            }
+        }
+    }
+}
@@ -0,0 +1,214 @@
+// color-cycle - render color cycle images
+// Copyright (C) 2025  Mathias Panzenböck
+// 
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+// 
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+// 
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// OSD bar showing the Living Worlds timeline over 24 hours.
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::WindowCanvas;
+
+use crate::color::{blend_in, BlendSpace, Rgb};
+use crate::image::{living_world::DAY_DURATION, LivingWorld};
+use crate::sun::SunTimes;
+
+/// Number of colors sampled over the day, one per 5 minutes.
+const SAMPLES: usize = 24 * 12;
+
+/// Average color of the scene over the day and the times of the timeline
+/// events on the (real) clock.
+#[derive(Debug, Clone)]
+pub struct DayGraph {
+    colors: Box<[Rgb]>,
+    markers: Box<[u64]>,
+    sun_times: Option<SunTimes>,
+    blend_space: BlendSpace,
+}
+
+impl DayGraph {
+    pub fn new(living_world: &LivingWorld, sun_times: Option<SunTimes>, blend_space: BlendSpace) -> Self {
+        // weight palette entries by how often they are used
+        let mut histogram = [0u64; 256];
+        for &index in living_world.base().indexed_image().data() {
+            histogram[index as usize] += 1;
+        }
+        let total = histogram.iter().sum::<u64>().max(1) as f64;
+
+        let mut colors = Vec::with_capacity(SAMPLES);
+        for sample in 0..SAMPLES {
+            let time_of_day = sample as u64 * DAY_DURATION / SAMPLES as u64;
+            let scene_time = if let Some(sun_times) = &sun_times {
+                sun_times.scene_time(time_of_day)
+            } else {
+                time_of_day
+            };
+
+            let Some((palette1, palette2, mid)) = living_world.palettes_at(scene_time) else {
+                break;
+            };
+
+            let mut sum = [0f64; 3];
+            for (index, &count) in histogram.iter().enumerate() {
+                if count == 0 {
+                    continue;
+                }
+                let index = index as u8;
+                let Rgb(rgb) = blend_in(palette1.palette()[index], palette2.palette()[index], mid, blend_space);
+                for (sum, value) in sum.iter_mut().zip(rgb) {
+                    *sum += value as f64 * count as f64;
+                }
+            }
+
+            colors.push(Rgb(sum.map(|sum| (sum / total).round() as u8)));
+        }
+
+        let markers = living_world.timeline().iter().map(|event| {
+            let scene_time = event.time_of_day() as u64 * 1000;
+            if let Some(sun_times) = &sun_times {
+                sun_times.real_time(scene_time)
+            } else {
+                scene_time
+            }
+        }).collect();
+
+        Self {
+            colors: colors.into(),
+            markers,
+            sun_times,
+            blend_space,
+        }
+    }
+
+    /// The sun times this graph was computed with.
+    #[inline]
+    pub fn sun_times(&self) -> Option<&SunTimes> {
+        self.sun_times.as_ref()
+    }
+
+    /// The color space the palettes were blended in.
+    #[inline]
+    pub fn blend_space(&self) -> BlendSpace {
+        self.blend_space
+    }
+
+    /// Time of day of each timeline event in milliseconds since midnight.
+    #[inline]
+    pub fn markers(&self) -> &[u64] {
+        &self.markers
+    }
+
+    pub fn draw(&self, canvas: &mut WindowCanvas, rect: Rect, time_of_day: u64) -> Result<(), String> {
+        let width = rect.width() as i64;
+
+        if !self.colors.is_empty() {
+            let mut prev_x = rect.x();
+            for (sample, &Rgb([r, g, b])) in self.colors.iter().enumerate() {
+                let next_x = rect.x() + ((sample as i64 + 1) * width / self.colors.len() as i64) as i32;
+                if next_x > prev_x {
+                    canvas.set_draw_color(Color::RGB(r, g, b));
+                    canvas.fill_rect(Rect::new(prev_x, rect.y(), (next_x - prev_x) as u32, rect.height()))?;
+                    prev_x = next_x;
+                }
+            }
+        }
+
+        canvas.set_draw_color(Color::RGB(255, 255, 255));
+        canvas.draw_rect(rect)?;
+
+        // hour ticks
+        let tick_height = (rect.height() / 4).max(1);
+        for hour in 1..24 {
+            let x = time_to_x(rect, hour * 60 * 60 * 1000);
+            let len = if hour % 6 == 0 { tick_height * 2 } else { tick_height };
+            canvas.fill_rect(Rect::new(x, rect.bottom() - len as i32, 1, len))?;
+        }
+
+        // timeline events
+        canvas.set_draw_color(Color::RGB(255, 255, 0));
+        for &marker in self.markers.iter() {
+            let x = time_to_x(rect, marker);
+            canvas.fill_rect(Rect::new(x, rect.y(), 1, rect.height()))?;
+        }
+
+        // current time
+        let x = time_to_x(rect, time_of_day);
+        let cursor_width = (rect.height() / 8).max(3);
+        let cursor = Rect::new(x - cursor_width as i32 / 2, rect.y() - cursor_width as i32, cursor_width, rect.height() + 2 * cursor_width);
+        canvas.set_draw_color(Color::RGB(255, 255, 255));
+        canvas.fill_rect(cursor)?;
+        canvas.set_draw_color(Color::RGB(0, 0, 0));
+        canvas.draw_rect(cursor)?;
+
+        Ok(())
+    }
+}
+
+/// Where the timeline is drawn in the window.
+pub fn timeline_rect(canvas_width: u32, canvas_height: u32) -> Rect {
+    let height = (canvas_height / 30).max(8);
+    let margin = height;
+    Rect::new(margin as i32, margin as i32, canvas_width.saturating_sub(2 * margin).max(1), height)
+}
+
+#[inline]
+pub fn time_to_x(rect: Rect, time_of_day: u64) -> i32 {
+    rect.x() + (time_of_day as i64 * rect.width() as i64 / DAY_DURATION as i64) as i32
+}
+
+/// Time of day for the given x coordinate, clamped to the timeline. The
+/// right edge of the timeline is the end of the day, so it can be reached
+/// even if the timeline is just one pixel wide. Rounds up so that
+/// [`time_to_x()`] maps the time back to `x`.
+#[inline]
+pub fn x_to_time(rect: Rect, x: i32) -> u64 {
+    let x = (x as i64 - rect.x() as i64).clamp(0, rect.width() as i64);
+    (x as u64 * DAY_DURATION).div_ceil(rect.width() as u64).min(DAY_DURATION - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn x_time_round_trip() {
+        for width in [1, 2, 3, 7, 640, 1000, 1921] {
+            let rect = Rect::new(10, 10, width, 8);
+            for x in rect.x()..rect.x() + width as i32 {
+                let time_of_day = x_to_time(rect, x);
+                assert!(time_of_day < DAY_DURATION);
+                assert_eq!(time_to_x(rect, time_of_day), x, "width {width}");
+            }
+        }
+    }
+
+    #[test]
+    fn x_to_time_clamps() {
+        for width in [1, 2, 640] {
+            let rect = Rect::new(10, 10, width, 8);
+            assert_eq!(x_to_time(rect, i32::MIN), 0);
+            assert_eq!(x_to_time(rect, 0), 0);
+            assert_eq!(x_to_time(rect, 10), 0);
+            assert_eq!(x_to_time(rect, 10 + width as i32), DAY_DURATION - 1);
+            assert_eq!(x_to_time(rect, i32::MAX), DAY_DURATION - 1);
+            assert_eq!(time_to_x(rect, DAY_DURATION - 1), 10 + width as i32 - 1);
+        }
+
+        // a one pixel wide timeline still reaches the end of the day
+        let rect = timeline_rect(1, 1);
+        assert_eq!(rect.width(), 1);
+        assert_eq!(x_to_time(rect, rect.x()), 0);
+        assert_eq!(x_to_time(rect, rect.x() + 1), DAY_DURATION - 1);
+    }
+}